@@ -0,0 +1,45 @@
+/* The C interface to uwc's counters, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --lib --no-default-features --features ffi \
+ *         --crate-type cdylib
+ */
+
+#ifndef UWC_H
+#define UWC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The counters, as bits of the mask given to uwc_count_utf8. A counter's count
+ * is written at the index of its bit, e.g., words at index 1. */
+#define UWC_LINES      (1u << 0)
+#define UWC_WORDS      (1u << 1)
+#define UWC_BYTES      (1u << 2)
+#define UWC_GRAPHEMES  (1u << 3)
+#define UWC_CODEPOINTS (1u << 4)
+#define UWC_PAGES      (1u << 5)
+
+/* The length of the array that counts are written to. */
+#define UWC_NUM_COUNTERS 6
+
+#define UWC_OK                    0
+#define UWC_ERR_INVALID_ARGUMENT (-1)
+#define UWC_ERR_INVALID_UTF8     (-2)
+#define UWC_ERR_PANIC            (-3)
+
+/* Counts the counters in counter_mask in the len bytes of UTF-8 text at ptr,
+ * and writes their counts to out_counts, which must have room for
+ * UWC_NUM_COUNTERS counts. The counts of the other counters are left as they
+ * are. Returns UWC_OK, or one of the UWC_ERR_* codes. */
+int uwc_count_utf8(const uint8_t *ptr, size_t len, uint32_t counter_mask,
+                   size_t *out_counts);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* UWC_H */
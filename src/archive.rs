@@ -0,0 +1,134 @@
+use std::io::{self, Cursor, Read};
+
+/// The number of bytes needed to detect an archive format. Tar archives are
+/// identified by the magic string at the end of their first header block.
+const SNIFF_LENGTH: usize = 512;
+
+/// The offset of the "ustar" magic string in a tar header block.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// The supported archive formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detects the archive format from the first bytes of a stream.
+    pub fn detect(header: &[u8]) -> Option<ArchiveFormat> {
+        if header.starts_with(b"PK\x03\x04") {
+            return Some(ArchiveFormat::Zip);
+        }
+
+        match header.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5) {
+            Some(b"ustar") => Some(ArchiveFormat::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the first bytes of the given reader to detect if it is an archive.
+/// The returned reader yields the whole stream, including the bytes that were
+/// read to detect the format.
+pub fn sniff<R: Read>(mut reader: R) -> io::Result<(Option<ArchiveFormat>, impl Read)> {
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    (&mut reader)
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut header)?;
+
+    let format = ArchiveFormat::detect(&header);
+
+    Ok((format, Cursor::new(header).chain(reader)))
+}
+
+/// Calls `f` with the path and contents of every regular file in the archive.
+/// Stops at the first error, either from reading the archive or from `f`.
+pub fn for_each_member<R, F, E>(format: ArchiveFormat, mut reader: R, mut f: F) -> Result<(), E>
+where
+    R: Read,
+    F: FnMut(&str, &mut dyn Read) -> Result<(), E>,
+    E: From<io::Error>,
+{
+    match format {
+        ArchiveFormat::Tar => {
+            let mut archive = tar::Archive::new(reader);
+
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+
+                let path = entry.path()?.to_string_lossy().into_owned();
+                f(&path, &mut entry)?;
+            }
+        }
+        ArchiveFormat::Zip => {
+            while let Some(mut file) =
+                zip::read::read_zipfile_from_stream(&mut reader).map_err(io::Error::from)?
+            {
+                if !file.is_file() {
+                    continue;
+                }
+
+                let path = file.name().to_string();
+                f(&path, &mut file)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tar_members() {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for (path, contents) in &[("a.txt", "hello"), ("dir/b.txt", "goodbye")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+
+        let archive = builder.into_inner().unwrap();
+        let (format, reader) = sniff(&archive[..]).unwrap();
+        assert_eq!(Some(ArchiveFormat::Tar), format);
+
+        let mut members = Vec::new();
+        for_each_member(ArchiveFormat::Tar, reader, |path, member| {
+            let mut contents = String::new();
+            member.read_to_string(&mut contents)?;
+            members.push((path.to_string(), contents));
+            Ok::<_, io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ("a.txt".to_string(), "hello".to_string()),
+                ("dir/b.txt".to_string(), "goodbye".to_string()),
+            ],
+            members
+        );
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        let (format, mut reader) = sniff(&b"just some text"[..]).unwrap();
+        assert_eq!(None, format);
+
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).unwrap();
+        assert_eq!("just some text", contents);
+    }
+}
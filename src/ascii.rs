@@ -0,0 +1,200 @@
+//! Counting for text that's all ASCII. In ASCII, every character is a single
+//! byte, and every one is a grapheme cluster of its own but CRLF, so most of
+//! the counters can be counted byte by byte, without the full Unicode
+//! segmentation rules.
+
+use crate::counter::{CountOptions, Counter, WordAlgorithm};
+
+/// The classes of the ASCII characters under the word boundary rules of
+/// UAX #29 that matter for finding words.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum WordClass {
+    Letter,
+    Numeric,
+    ExtendNumLet,
+
+    /// Only joins letters, like the `:` in "a:b".
+    MidLetter,
+
+    /// Only joins numbers, like the `,` in "1,000".
+    MidNum,
+
+    /// Joins both, like the `.` in "e.g" or "3.14".
+    MidNumLet,
+
+    Other,
+}
+
+fn word_class(b: u8) -> WordClass {
+    match b {
+        b'A'..=b'Z' | b'a'..=b'z' => WordClass::Letter,
+        b'0'..=b'9' => WordClass::Numeric,
+        b'_' => WordClass::ExtendNumLet,
+        b':' => WordClass::MidLetter,
+        b',' | b';' => WordClass::MidNum,
+        b'.' | b'\'' => WordClass::MidNumLet,
+        _ => WordClass::Other,
+    }
+}
+
+/// Determines if `mid` keeps the word going between `before` and `after`.
+fn joins(before: WordClass, mid: WordClass, after: WordClass) -> bool {
+    match mid {
+        WordClass::MidLetter => before == WordClass::Letter && after == WordClass::Letter,
+        WordClass::MidNum => before == WordClass::Numeric && after == WordClass::Numeric,
+        WordClass::MidNumLet => {
+            before == after && (before == WordClass::Letter || before == WordClass::Numeric)
+        }
+        _ => false,
+    }
+}
+
+/// Counts the words in ASCII text, the same as `unicode_words` would.
+fn count_words(s: &[u8]) -> usize {
+    let mut words = 0;
+    let mut i = 0;
+
+    while i < s.len() {
+        let mut class = word_class(s[i]);
+
+        if !is_word_part(class) {
+            i += 1;
+            continue;
+        }
+
+        // words that are only underscores aren't words
+        let mut alphanumeric = class != WordClass::ExtendNumLet;
+        i += 1;
+
+        while i < s.len() {
+            let next = word_class(s[i]);
+
+            if is_word_part(next) {
+                alphanumeric |= next != WordClass::ExtendNumLet;
+                class = next;
+                i += 1;
+            } else if i + 1 < s.len() && joins(class, next, word_class(s[i + 1])) {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        if alphanumeric {
+            words += 1;
+        }
+    }
+
+    words
+}
+
+fn is_word_part(class: WordClass) -> bool {
+    matches!(
+        class,
+        WordClass::Letter | WordClass::Numeric | WordClass::ExtendNumLet
+    )
+}
+
+/// Counts the lines in ASCII text, i.e., the grapheme clusters that are one of
+/// the selected newlines.
+fn count_lines(s: &str, options: &CountOptions) -> usize {
+    let bytes = s.as_bytes();
+    let mut lines = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let len = match bytes[i..] {
+            [b'\r', b'\n', ..] => 2,
+            _ => 1,
+        };
+
+        // only control characters are newlines
+        if bytes[i] < b' ' && options.newlines.contains(&s[i..i + len]) {
+            lines += 1;
+        }
+
+        i += len;
+    }
+
+    lines
+}
+
+/// Counts `counter` in `s`, which must be all ASCII. Returns `None` if it can't
+/// be counted byte by byte, i.e., for words that are counted with ICU, or
+/// tailored to a locale, and for the counters that look at them themselves,
+/// like links, letter cases, widths, code points, line classes, and custom
+/// counters.
+pub fn count(counter: Counter, s: &str, options: &CountOptions) -> Option<usize> {
+    debug_assert!(s.is_ascii());
+
+    let count = match counter {
+        Counter::NumByte | Counter::CodePoints => s.len(),
+        Counter::GraphemeCluster => s.len() - s.matches("\r\n").count(),
+        Counter::Line => count_lines(s, options),
+        Counter::Pages => memchr::memchr_iter(0x0c, s.as_bytes()).count(),
+        Counter::Words => match options {
+            CountOptions {
+                word_algorithm: WordAlgorithm::Unicode,
+                locale: None,
+                ..
+            } => count_words(s.as_bytes()),
+            _ => return None,
+        },
+        Counter::Link(_)
+        | Counter::LetterCase(_)
+        | Counter::Width(_)
+        | Counter::CodePoint(_)
+        | Counter::LineClass(_)
+        | Counter::Custom(_) => return None,
+    };
+
+    Some(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::{GraphemeMode, ALL_COUNTERS};
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        // a small alphabet, so that the characters that join words show up
+        // next to each other a lot
+        let alphabet = b"ab1_:,;.' \r\n\x0c\t-";
+        let mut seed: u64 = 1;
+
+        for len in 0..2000 {
+            let s: String = (0..len % 40)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    alphabet[(seed >> 33) as usize % alphabet.len()] as char
+                })
+                .collect();
+
+            for grapheme_mode in &[GraphemeMode::Extended, GraphemeMode::Legacy] {
+                let options = CountOptions {
+                    grapheme_mode: *grapheme_mode,
+                    ..CountOptions::default()
+                };
+
+                for counter in &ALL_COUNTERS {
+                    assert_eq!(
+                        Some(counter.count_segmented(&s, &options)),
+                        count(*counter, &s, &options),
+                        "{} in {:?}",
+                        counter,
+                        s
+                    );
+                }
+            }
+        }
+
+        let options = CountOptions {
+            word_algorithm: WordAlgorithm::Icu,
+            ..CountOptions::default()
+        };
+        assert_eq!(None, count(Counter::Words, "a b", &options));
+    }
+}
@@ -0,0 +1,368 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use failure::Error;
+use rayon::prelude::*;
+use tokio::io::AsyncReadExt;
+use tokio::runtime;
+use tokio::sync::Semaphore;
+
+use crate::cache::Cache;
+use crate::input::{Input, STDIN_IDENTIFIER};
+use crate::open_files;
+use crate::opt::Opt;
+use crate::ordered;
+use crate::output_dir;
+use crate::progress;
+use crate::report::{self, InputError};
+use crate::timings::{self, Timings};
+use crate::{count_file_cached, count_input, InputCounts};
+
+/// Regular files larger than this are read as usual, since they're read into
+/// memory whole on the runtime. Longer inputs that aren't regular files only
+/// have this much read into memory at first.
+const MAX_ASYNC_LEN: u64 = 16 << 20;
+
+/// How much of an input is read at a time once it's longer than
+/// `MAX_ASYNC_LEN`.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// How many chunks of an input can be read ahead of where it's counted.
+const MAX_CHUNKS_AHEAD: usize = 16;
+
+/// The most inputs that are read at once.
+const MAX_CONCURRENT_READS: usize = 256;
+
+/// Determines if the input with the given name is read on the async runtime,
+/// i.e., if it isn't stdin or a large regular file. With `--flush-interval` or
+/// `--interval`, FIFOs and other inputs that aren't regular files are read as
+/// usual too, so that what they've given so far is counted as it arrives,
+/// rather than once they've been read whole.
+fn reads_async(file_name: &str, opts: &Opt) -> bool {
+    if file_name == STDIN_IDENTIFIER {
+        return false;
+    }
+
+    let counts_partial = opts.flush_interval.is_some() || opts.interval.is_some();
+
+    // errors are given when the input is read
+    match fs::metadata(file_name) {
+        Ok(metadata) if metadata.is_file() => metadata.len() <= MAX_ASYNC_LEN,
+        Ok(_) => !counts_partial,
+        Err(_) => true,
+    }
+}
+
+/// An input that was read on the runtime: up to `MAX_ASYNC_LEN` bytes of it,
+/// and the rest of it in chunks if it's longer, which are read as it's
+/// counted. This way, an input that never ends, like `/dev/zero` or a FIFO
+/// that's never closed, isn't read into memory whole.
+struct ReadInput {
+    read: io::Cursor<Vec<u8>>,
+    rest: Option<tokio::sync::mpsc::Receiver<io::Result<Vec<u8>>>>,
+}
+
+impl Read for ReadInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = Read::read(&mut self.read, buf)?;
+
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            let chunk = match self.rest {
+                Some(ref mut rest) => rest.blocking_recv(),
+                None => None,
+            };
+
+            match chunk {
+                Some(chunk) => self.read = io::Cursor::new(chunk?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Reads up to `MAX_ASYNC_LEN` bytes of the input with the given name,
+/// returning the file too if there could be more of it.
+async fn read_start(file_name: &str) -> io::Result<(Vec<u8>, Option<tokio::fs::File>)> {
+    let mut file = tokio::fs::File::open(file_name).await?;
+    let mut bytes = Vec::new();
+    (&mut file)
+        .take(MAX_ASYNC_LEN)
+        .read_to_end(&mut bytes)
+        .await?;
+
+    match bytes.len() as u64 {
+        MAX_ASYNC_LEN => Ok((bytes, Some(file))),
+        _ => Ok((bytes, None)),
+    }
+}
+
+/// Reads the rest of an input a chunk at a time, sending the chunks as they're
+/// read, until it ends, or until they're no longer wanted.
+async fn read_rest(
+    mut file: tokio::fs::File,
+    chunks: tokio::sync::mpsc::Sender<io::Result<Vec<u8>>>,
+) {
+    loop {
+        let mut chunk = vec![0; CHUNK_LEN];
+
+        let chunk = match file.read(&mut chunk).await {
+            Ok(0) => return,
+            Ok(read) => {
+                chunk.truncate(read);
+                Ok(chunk)
+            }
+            Err(e) => Err(e),
+        };
+
+        let failed = chunk.is_err();
+
+        if chunks.send(chunk).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Reads the given inputs concurrently on an async runtime, no more than
+/// `max_open` of them at once, and sends each one as soon as it's been read
+/// whole, or as soon as `MAX_ASYNC_LEN` bytes of it have been, in which case
+/// the rest of it is read as it's counted. The runtime runs on a thread of its
+/// own, so that the inputs can be counted while the others are still read.
+fn read_all(
+    file_names: Vec<String>,
+    max_open: usize,
+) -> mpsc::Receiver<(String, io::Result<ReadInput>)> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = match runtime::Builder::new_current_thread().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                for file_name in file_names {
+                    let e = io::Error::new(e.kind(), e.to_string());
+                    let _ = tx.send((file_name, Err(e)));
+                }
+
+                return;
+            }
+        };
+
+        runtime.block_on(async {
+            let permits = Arc::new(Semaphore::new(max_open));
+
+            let reads: Vec<_> = file_names
+                .into_iter()
+                .map(|file_name| {
+                    let permits = permits.clone();
+                    let tx = tx.clone();
+
+                    tokio::spawn(async move {
+                        let _permit = permits.acquire_owned().await;
+
+                        let (read, file) = match read_start(&file_name).await {
+                            Ok(start) => start,
+                            Err(e) => {
+                                let _ = tx.send((file_name, Err(e)));
+                                return;
+                            }
+                        };
+
+                        let (chunks, rest) = match file {
+                            Some(_) => {
+                                let (chunks, rest) = tokio::sync::mpsc::channel(MAX_CHUNKS_AHEAD);
+                                (Some(chunks), Some(rest))
+                            }
+                            None => (None, None),
+                        };
+
+                        let read = io::Cursor::new(read);
+                        let _ = tx.send((file_name, Ok(ReadInput { read, rest })));
+
+                        if let (Some(file), Some(chunks)) = (file, chunks) {
+                            read_rest(file, chunks).await;
+                        }
+                    })
+                })
+                .collect();
+
+            for read in reads {
+                let _ = read.await;
+            }
+        });
+    });
+
+    rx
+}
+
+/// Counts an input that's been read on the runtime.
+fn count_read(
+    file_name: &str,
+    read: io::Result<ReadInput>,
+    opts: &Opt,
+    cache: Option<&Cache>,
+    output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<InputCounts, Error> {
+    let input = match read.and_then(|read| Input::from_reader(read, opts.decompress)) {
+        Ok(input) => input,
+        Err(e) => {
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok((false, Vec::new()));
+        }
+    };
+
+    let (success, rows) = count_input(file_name, input, opts, output_writer)?;
+    progress::finish(file_name);
+
+    if let (true, Some(cache)) = (success, cache) {
+        cache.insert(file_name, &rows);
+    }
+
+    Ok((success, rows))
+}
+
+/// Counts an input with `count`, giving it a writer for its rows. Since the
+/// inputs are counted in the order they're read in, rather than the order they
+/// were given in, their rows are written together when they're done, unless
+/// `--unordered` is given.
+fn write_rows<F>(
+    opts: &Opt,
+    output_writer: &Arc<Mutex<dyn Write + Send + Sync>>,
+    count: F,
+) -> Result<InputCounts, Error>
+where
+    F: FnOnce(Arc<Mutex<dyn Write + Send + Sync>>) -> Result<InputCounts, Error>,
+{
+    match opts.writes_in_order() {
+        true => ordered::write_grouped(output_writer, count),
+        false => count(output_writer.clone()),
+    }
+}
+
+/// Counts the given files like `count_file_cached`, but with `--async-io`, the
+/// small files and the inputs that aren't regular files, like FIFOs, are read
+/// concurrently on an async runtime, and they're counted as soon as they've
+/// been read, or as soon as the start of them has been for longer inputs that
+/// aren't regular files. This way, slow inputs don't hold up the counting
+/// threads while they're read. The rest are counted as usual alongside them.
+pub fn count_files(
+    file_names: &[String],
+    opts: &Opt,
+    cache: Option<&Cache>,
+    timings: Option<&Timings>,
+    output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Vec<Result<InputCounts, Error>> {
+    let (async_files, sync_files): (Vec<String>, Vec<String>) =
+        file_names.iter().cloned().partition(|file_name| {
+            opts.byte_range.is_none()
+                && reads_async(file_name, opts)
+                && cache.is_none_or(|cache| cache.get(file_name).is_none())
+        });
+
+    // the files that can be open at once are split between the ones that are
+    // read on the runtime and the ones that are counted as usual
+    let max_open = open_files::limit(opts);
+    let max_reads = (max_open / 2).clamp(1, MAX_CONCURRENT_READS);
+    let read = read_all(async_files, max_reads);
+
+    let (mut results, async_results) = rayon::join(
+        || {
+            open_files::count_limited(&sync_files, max_open - max_reads, |_, file_name| {
+                timings::time(timings, file_name, || {
+                    write_rows(opts, &output_writer, |writer| {
+                        output_dir::count_to_file(file_name, opts, writer, |writer| {
+                            count_file_cached(file_name, opts, cache, writer)
+                        })
+                    })
+                })
+            })
+        },
+        || {
+            read.into_iter()
+                .par_bridge()
+                .map(|(file_name, read)| {
+                    timings::time(timings, &file_name, || {
+                        write_rows(opts, &output_writer, |writer| {
+                            output_dir::count_to_file(&file_name, opts, writer, |writer| {
+                                count_read(&file_name, read, opts, cache, writer)
+                            })
+                        })
+                    })
+                })
+                .collect::<Vec<_>>()
+        },
+    );
+
+    results.extend(async_results);
+    results
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::Counter;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_count_files() {
+        let _ = env_logger::try_init();
+
+        let dir = std::env::temp_dir().join(format!("uwc-async-io-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut file_names: Vec<String> = (0..20)
+            .map(|i| {
+                let path = dir.join(format!("input{}", i));
+                fs::write(&path, "word\n".repeat(i)).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+
+        file_names.push(dir.join("missing").to_str().unwrap().to_string());
+
+        let opts = Opt::from_iter(&["uwc", "-l", "--async-io"]);
+        let sink: Arc<Mutex<dyn Write + Send + Sync>> = Arc::new(Mutex::new(io::sink()));
+
+        let results = count_files(&file_names, &opts, None, None, sink);
+        assert_eq!(file_names.len(), results.len());
+
+        let results: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(1, results.iter().filter(|(success, _)| !success).count());
+
+        let mut lines: Vec<usize> = results
+            .iter()
+            .flat_map(|(_, rows)| rows.iter().map(|(_, counts)| counts[&Counter::Line]))
+            .collect();
+        lines.sort_unstable();
+        assert_eq!((0..20).collect::<Vec<_>>(), lines);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_input() {
+        let (chunks, rest) = tokio::sync::mpsc::channel(MAX_CHUNKS_AHEAD);
+
+        let sent = std::thread::spawn(move || {
+            for chunk in &["c", "", "de"] {
+                chunks.blocking_send(Ok(chunk.as_bytes().to_vec())).unwrap();
+            }
+        });
+
+        let read = io::Cursor::new(b"ab".to_vec());
+        let mut input = ReadInput {
+            read,
+            rest: Some(rest),
+        };
+        let mut text = String::new();
+        input.read_to_string(&mut text).unwrap();
+        assert_eq!("abcde", text);
+
+        sent.join().unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use failure::{format_err, Error};
+use tabwriter::TabWriter;
+
+use crate::counter::{self, Counted, Counter};
+use crate::opt::Opt;
+use crate::{write_header, TOTAL};
+
+/// Saved counts to compare later runs against, by row name.
+pub type Baseline = BTreeMap<String, Counted>;
+
+/// The most a counter may change by for any file when comparing against a
+/// baseline, given like `words=500`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaLimit {
+    pub counter: Counter,
+    pub max: usize,
+}
+
+impl FromStr for DeltaLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DeltaLimit, String> {
+        let (counter, max) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected COUNTER=N: {}", s))?;
+
+        Ok(DeltaLimit {
+            counter: counter.parse()?,
+            max: max.parse().map_err(|e| format!("{}: {}", max, e))?,
+        })
+    }
+}
+
+/// Writes the counts to the baseline file at the given path.
+pub fn save(path: &Path, counts: &Baseline) -> Result<(), Error> {
+    let write = || -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, counts)?;
+        writeln!(writer)?;
+        Ok(writer.flush()?)
+    };
+
+    write().map_err(|e| format_err!("{}: {}", path.display(), e))
+}
+
+/// Reads the baseline file at the given path.
+pub fn load(path: &Path) -> Result<Baseline, Error> {
+    let read = || -> Result<Baseline, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    };
+
+    read().map_err(|e| format_err!("{}: {}", path.display(), e))
+}
+
+/// The signed changes of some counts.
+type Deltas = BTreeMap<Counter, i64>;
+
+/// Gets how much each of the given counters changed from `old` to `new`.
+/// Counters missing from either side are taken as 0.
+fn deltas(counters: &BTreeSet<Counter>, old: Option<&Counted>, new: Option<&Counted>) -> Deltas {
+    let get = |counts: Option<&Counted>, counter| {
+        counts.and_then(|c| c.get(counter)).copied().unwrap_or(0) as i64
+    };
+
+    counters
+        .iter()
+        .map(|counter| (*counter, get(new, counter) - get(old, counter)))
+        .collect()
+}
+
+fn write_deltas<W: Write>(mut writer: W, deltas: &Deltas, name: &str) -> io::Result<()> {
+    let mut out_str = String::new();
+
+    for delta in deltas.values() {
+        match delta {
+            0 => out_str.push('0'),
+            _ => out_str.push_str(&format!("{:+}", delta)),
+        }
+        out_str.push('\t');
+    }
+
+    out_str.push_str(name);
+    out_str.push('\n');
+
+    writer.write_all(out_str.as_bytes())
+}
+
+/// Writes how much the counts of every row changed since the baseline, along
+/// with the change of the total, and checks the changes against the limits.
+/// Rows that only exist on one side are compared against 0. Returns whether
+/// every change is within the limits; the ones that aren't are printed.
+pub fn compare(baseline: &Baseline, counts: &Baseline, opts: &Opt) -> Result<bool, Error> {
+    let counters = opts.get_counters();
+
+    let stdout = io::stdout();
+    let mut writer: Box<dyn Write> = if opts.no_elastic {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(TabWriter::new(stdout.lock()))
+    };
+
+    if !opts.no_header {
+        write_header(&mut writer, &counters, false, false)?;
+    }
+
+    let names: BTreeSet<&String> = baseline.keys().chain(counts.keys()).collect();
+    let mut within_limits = true;
+
+    for name in names {
+        let row_deltas = deltas(&counters, baseline.get(name), counts.get(name));
+        write_deltas(&mut writer, &row_deltas, name)?;
+
+        for limit in &opts.max_delta {
+            let delta = row_deltas.get(&limit.counter).copied().unwrap_or(0);
+
+            if delta.unsigned_abs() > limit.max as u64 {
+                eprintln!(
+                    "{}: {} changed by {:+}, more than {}",
+                    name, limit.counter, delta, limit.max
+                );
+                within_limits = false;
+            }
+        }
+    }
+
+    let old_total = counter::sum_all_counts(baseline.values());
+    let new_total = counter::sum_all_counts(counts.values());
+    let total_deltas = deltas(&counters, Some(&old_total), Some(&new_total));
+    write_deltas(&mut writer, &total_deltas, TOTAL)?;
+
+    writer.flush()?;
+
+    Ok(within_limits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deltas() {
+        let _ = env_logger::try_init();
+
+        let counters: BTreeSet<_> = vec![Counter::Line, Counter::Words].into_iter().collect();
+        let old: Counted = vec![(Counter::Line, 3), (Counter::Words, 10)]
+            .into_iter()
+            .collect();
+        let new: Counted = vec![(Counter::Line, 5), (Counter::Words, 4)]
+            .into_iter()
+            .collect();
+
+        let expected: Deltas = vec![(Counter::Line, 2), (Counter::Words, -6)]
+            .into_iter()
+            .collect();
+        assert_eq!(expected, deltas(&counters, Some(&old), Some(&new)));
+
+        let expected: Deltas = vec![(Counter::Line, -3), (Counter::Words, -10)]
+            .into_iter()
+            .collect();
+        assert_eq!(expected, deltas(&counters, Some(&old), None));
+    }
+
+    #[test]
+    fn test_delta_limit() {
+        let _ = env_logger::try_init();
+
+        assert_eq!(
+            Ok(DeltaLimit {
+                counter: Counter::Words,
+                max: 500
+            }),
+            "words=500".parse()
+        );
+        assert!("words".parse::<DeltaLimit>().is_err());
+        assert!("sentences=1".parse::<DeltaLimit>().is_err());
+        assert!("words=-1".parse::<DeltaLimit>().is_err());
+    }
+}
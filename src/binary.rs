@@ -0,0 +1,122 @@
+use std::io::{self, Cursor, Read};
+use std::str::{self, FromStr};
+
+/// The number of bytes at the start of an input that are checked to decide if
+/// it's binary.
+pub const SNIFF_LENGTH: usize = 8192;
+
+/// The fraction of invalid UTF-8 bytes at the start of an input above which
+/// it's considered binary.
+const MAX_INVALID_RATIO: f64 = 0.1;
+
+/// What to do with inputs that look binary.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BinaryPolicy {
+    /// Don't count binary inputs at all.
+    Skip,
+
+    /// Only count the bytes of binary inputs.
+    Bytes,
+
+    /// Count binary inputs like any other input.
+    #[default]
+    Count,
+}
+
+impl FromStr for BinaryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BinaryPolicy, String> {
+        match s {
+            "skip" => Ok(BinaryPolicy::Skip),
+            "bytes" => Ok(BinaryPolicy::Bytes),
+            "count" => Ok(BinaryPolicy::Count),
+            _ => Err(format!("Unknown binary policy: {}", s)),
+        }
+    }
+}
+
+/// Whether the inputs are decoded as UTF-8, with `--bytes-only-decode`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Decoding {
+    /// Decode the inputs, so that everything can be counted.
+    #[default]
+    On,
+
+    /// Count only the lines and bytes of the inputs, over their raw bytes,
+    /// like `wc -lc`, so that binary inputs or inputs with invalid UTF-8 are
+    /// counted in full.
+    Off,
+}
+
+impl FromStr for Decoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Decoding, String> {
+        match s {
+            "on" => Ok(Decoding::On),
+            "off" => Ok(Decoding::Off),
+            _ => Err(format!("Unknown decoding: {}", s)),
+        }
+    }
+}
+
+/// Checks whether the first bytes of an input look binary, i.e., they contain
+/// a NUL byte, or too many bytes that aren't valid UTF-8.
+pub fn is_binary(header: &[u8]) -> bool {
+    if header.is_empty() {
+        return false;
+    }
+
+    if header.contains(&0) {
+        return true;
+    }
+
+    let mut invalid = 0;
+    let mut rest = header;
+
+    while let Err(e) = str::from_utf8(rest) {
+        match e.error_len() {
+            Some(len) => {
+                invalid += len;
+                rest = &rest[e.valid_up_to() + len..];
+            }
+            // The header ended in the middle of a character.
+            None => break,
+        }
+    }
+
+    invalid as f64 / header.len() as f64 > MAX_INVALID_RATIO
+}
+
+/// Reads the first bytes of the given reader to detect if it's binary. The
+/// returned reader yields the whole stream, including the bytes that were read
+/// to detect it.
+pub fn sniff<R: Read>(mut reader: R) -> io::Result<(bool, impl Read)> {
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    (&mut reader)
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut header)?;
+
+    let binary = is_binary(&header);
+
+    Ok((binary, Cursor::new(header).chain(reader)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_binary() {
+        let _ = env_logger::try_init();
+
+        assert!(!is_binary(b""));
+        assert!(!is_binary("hello, wörld\n".as_bytes()));
+        assert!(is_binary(b"hello\0world"));
+        assert!(is_binary(&[0xff, 0xfe, b'a', b'b', 0xc0]));
+
+        // A character cut off at the end of the header isn't invalid.
+        assert!(!is_binary(&"wörld".as_bytes()[..2]));
+    }
+}
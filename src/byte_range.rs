@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::str::{self, FromStr};
+use std::sync::{Arc, Mutex};
+
+use failure::Error;
+use log::*;
+use unicode_segmentation::GraphemeCursor;
+
+use crate::binary::{self, BinaryPolicy};
+use crate::constants::BOM;
+use crate::counter::Counted;
+use crate::input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+use crate::{count_binary, count_records, write_input_counts};
+
+/// How many bytes before a bound are read to find the grapheme cluster it's
+/// in.
+const MAX_CONTEXT_LEN: u64 = 256;
+
+/// How many bytes after a bound are read to find the end of the grapheme
+/// cluster it's in. Longer clusters are split at a code point boundary.
+const MAX_LOOKAHEAD_LEN: u64 = 256;
+
+/// A range of bytes of a file to count, given with `--byte-range`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ByteRange {
+    /// The offset of the first byte to count.
+    pub start: u64,
+
+    /// The offset after the last byte to count, if it isn't the end of the
+    /// file.
+    pub end: Option<u64>,
+}
+
+impl FromStr for ByteRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ByteRange, String> {
+        let err = || format!("Expected a byte range like 0:1048576: {}", s);
+
+        let (start, end) = s.split_once(':').ok_or_else(err)?;
+
+        let start = match start {
+            "" => 0,
+            start => start.parse().map_err(|_| err())?,
+        };
+
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().map_err(|_| err())?),
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return Err(err());
+        }
+
+        Ok(ByteRange { start, end })
+    }
+}
+
+/// Moves `pos` forward to the start of the next grapheme cluster in `buf`,
+/// unless it's already at the start of one, and returns where it ends up.
+/// `buf` can start and end in the middle of a code point, or have invalid
+/// UTF-8; if the grapheme clusters around `pos` can't be found, it's moved
+/// to the start of the next code point instead.
+fn snap_in(buf: &[u8], pos: usize) -> usize {
+    let char_start = (pos..buf.len())
+        .take(3)
+        .find(|i| buf[*i] & 0xc0 != 0x80)
+        .unwrap_or_else(|| buf.len().min(pos + 3));
+
+    // the valid text right before and after the code point
+    let before = (char_start.saturating_sub(MAX_CONTEXT_LEN as usize)..char_start)
+        .find_map(|i| str::from_utf8(&buf[i..char_start]).ok())
+        .unwrap_or("");
+
+    let after = match str::from_utf8(&buf[char_start..]) {
+        Ok(after) => after,
+        Err(e) => str::from_utf8(&buf[char_start..char_start + e.valid_up_to()]).unwrap(),
+    };
+
+    let text = format!("{}{}", before, after);
+    let mut cursor = GraphemeCursor::new(before.len(), text.len(), true);
+
+    match cursor.is_boundary(&text, 0) {
+        Ok(true) => char_start,
+        Ok(false) => match cursor.next_boundary(&text, 0) {
+            Ok(Some(boundary)) => char_start + boundary - before.len(),
+            _ => char_start,
+        },
+        Err(_) => char_start,
+    }
+}
+
+/// Moves `pos` in the file forward to the start of the next grapheme cluster,
+/// like `snap_in`.
+fn snap(file: &mut File, pos: u64, len: u64) -> io::Result<u64> {
+    if pos == 0 || pos >= len {
+        return Ok(pos.min(len));
+    }
+
+    let buf_start = pos.saturating_sub(MAX_CONTEXT_LEN);
+    let mut buf = Vec::new();
+
+    file.seek(SeekFrom::Start(buf_start))?;
+    (&mut *file)
+        .take(pos - buf_start + MAX_LOOKAHEAD_LEN)
+        .read_to_end(&mut buf)?;
+
+    Ok(buf_start + snap_in(&buf, (pos - buf_start) as usize) as u64)
+}
+
+/// Counts the bytes of the given file in `range`. Both of its ends are moved
+/// forward to the start of the next grapheme cluster, so that ranges that
+/// follow each other never split one. This only works for regular files that
+/// are read as they are.
+pub fn count(
+    file_name: &str,
+    range: ByteRange,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    let len = match input::plain_file_size(file_name, opts.decompress) {
+        Some(len) if opts.input_encoding(file_name).is_none() => len,
+        _ => {
+            let message = "--byte-range can only be used on regular files that aren't \
+                           compressed or transcoded";
+            report::input_error(
+                opts,
+                InputError::new(Some(file_name), "invalid-input", message),
+            );
+            return Ok((false, None));
+        }
+    };
+
+    let open = || -> io::Result<(File, u64, u64)> {
+        let mut file = File::open(file_name)?;
+        let start = snap(&mut file, range.start, len)?;
+        let end = snap(&mut file, range.end.unwrap_or(len), len)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok((file, start, end))
+    };
+
+    let (mut file, start, end) = match open() {
+        Ok(opened) => opened,
+        Err(e) => {
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok((false, None));
+        }
+    };
+
+    info!("counting bytes {}:{} of {}", start, end, file_name);
+
+    if opts.binary != BinaryPolicy::Count {
+        let mut header = Vec::with_capacity(binary::SNIFF_LENGTH);
+        let sniffed = (&mut file)
+            .take(binary::SNIFF_LENGTH as u64)
+            .read_to_end(&mut header)
+            .and_then(|_| file.seek(SeekFrom::Start(start)));
+
+        if let Err(e) = sniffed {
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok((false, None));
+        }
+
+        if binary::is_binary(&header) {
+            if opts.binary == BinaryPolicy::Skip {
+                info!("skipping binary input: {}", file_name);
+                return Ok((true, None));
+            }
+
+            return count_binary(file_name, (end - start) as usize, opts, output_writer);
+        }
+    }
+
+    let mut reader = BufReader::new(file.take(end - start));
+    let mut first_offset = start;
+
+    if opts.strip_bom && start == 0 {
+        match input::skip_bom(&mut reader) {
+            Ok(true) => first_offset = BOM.len() as u64,
+            Ok(false) => {}
+            Err(e) => {
+                report::input_error(opts, InputError::io(file_name, &e));
+                return Ok((false, None));
+            }
+        }
+    }
+
+    let start = (1, first_offset);
+    let (success, counts) = count_records(file_name, reader, start, opts, output_writer)?;
+
+    if let Some(ref counts) = counts {
+        write_input_counts(file_name, counts, opts, output_writer)?;
+    }
+
+    Ok((success, counts))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let _ = env_logger::try_init();
+
+        let range: ByteRange = "10:20".parse().unwrap();
+        assert_eq!(
+            ByteRange {
+                start: 10,
+                end: Some(20)
+            },
+            range
+        );
+        assert_eq!(
+            ByteRange {
+                start: 10,
+                end: None
+            },
+            "10:".parse().unwrap()
+        );
+        assert_eq!(
+            ByteRange {
+                start: 0,
+                end: Some(20)
+            },
+            ":20".parse().unwrap()
+        );
+
+        for s in &["", "10", "20:10", "a:", "-1:"] {
+            assert!(s.parse::<ByteRange>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_snap_in() {
+        let _ = env_logger::try_init();
+
+        let text = "ab é x\u{301}y 🇫🇷🇩🇪";
+        let bytes = text.as_bytes();
+        let snapped = |pos| snap_in(bytes, pos);
+
+        // already on a boundary
+        assert_eq!(0, snapped(0));
+        assert_eq!(1, snapped(1));
+
+        // in the middle of a code point
+        let e = text.find('é').unwrap();
+        assert_eq!(e + 2, snapped(e + 1));
+
+        // in the middle of a grapheme cluster
+        let accent = text.find('\u{301}').unwrap();
+        assert_eq!(accent + 2, snapped(accent));
+
+        // between the regional indicators of a flag
+        let flags = text.find('🇫').unwrap();
+        assert_eq!(flags + 8, snapped(flags + 4));
+        assert_eq!(flags + 8, snapped(flags + 8 - 1));
+
+        // at the end
+        assert_eq!(bytes.len(), snapped(bytes.len()));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use failure::{format_err, Error};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::counter::Counted;
+use crate::input::STDIN_IDENTIFIER;
+use crate::opt::Opt;
+
+/// The cache file that's used if `--cache` is given without a path.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("uwc").join("cache.json"))
+}
+
+/// The counts of a file, along with the size and modification time it had
+/// when it was counted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+
+    /// The rows of counts for the file, with the file's name taken out of
+    /// their names. This is usually just one row with an empty name, but
+    /// archives have a row for every member.
+    rows: Vec<(String, Counted)>,
+}
+
+impl Entry {
+    /// Checks whether the file still has the size and modification time it had
+    /// when it was counted.
+    fn is_fresh(&self, stamp: (u64, u64, u32)) -> bool {
+        (self.size, self.mtime_secs, self.mtime_nanos) == stamp
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    /// Describes the options that change the counts. The cache is only used
+    /// if they're the same as when it was written.
+    options: String,
+
+    /// The entries for every file, by canonical path.
+    files: HashMap<PathBuf, Entry>,
+}
+
+/// A cache of the counts of files, which lets files that haven't changed since
+/// the last run skip being counted again.
+pub struct Cache {
+    path: PathBuf,
+    options: String,
+    cached: HashMap<PathBuf, Entry>,
+    counted: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+/// Gets the size and modification time of a file. Only regular files have
+/// one, since what's read from FIFOs and devices can change without either of
+/// them changing.
+fn stamp(path: &Path) -> io::Result<(u64, u64, u32)> {
+    let metadata = fs::metadata(path)?;
+
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok((metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+/// Describes the options that change the counts.
+fn options_key(opts: &Opt) -> String {
+    format!(
+        "{} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        opts.get_counters(),
+        opts.count_regex.iter().map(|regex| regex.to_string()).collect::<Vec<_>>(),
+        opts.word_algorithm,
+        opts.locale,
+        opts.grapheme_mode,
+        opts.newlines.pattern().as_str(),
+        opts.record_pattern().as_str(),
+        opts.encoding.map(|e| e.name()),
+        opts.encoding_for,
+        opts.strip_bom,
+        opts.invalid_utf8,
+        opts.binary,
+        opts.bytes_only_decode,
+        opts.decompress,
+        opts.archive,
+        opts.line_range,
+        opts.byte_range,
+        opts.markup,
+        opts.exclude_code,
+        opts.decode_entities,
+        opts.field,
+        opts.field_sep,
+        opts.json_field.as_ref().map(|path| path.to_string()),
+        opts.comment_prefix,
+        opts.block_comment,
+    )
+}
+
+impl Cache {
+    /// Loads the cache at the given path. If it doesn't exist yet, or was
+    /// written with different options, the cache starts out empty.
+    pub fn load(path: PathBuf, opts: &Opt) -> Result<Cache, Error> {
+        let options = options_key(opts);
+
+        let cache_file: CacheFile = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|e| {
+                warn!("{}: ignoring invalid cache: {}", path.display(), e);
+                CacheFile::default()
+            }),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => CacheFile::default(),
+            Err(e) => return Err(format_err!("{}: {}", path.display(), e)),
+        };
+
+        let cached = if cache_file.options == options {
+            cache_file.files
+        } else {
+            info!("options changed, not using the cache");
+            HashMap::new()
+        };
+
+        Ok(Cache {
+            path,
+            options,
+            cached,
+            counted: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Gets the rows of counts for the given file, if it hasn't changed since
+    /// it was cached.
+    pub fn get(&self, file_name: &str) -> Option<Vec<(String, Counted)>> {
+        if file_name == STDIN_IDENTIFIER {
+            return None;
+        }
+
+        let path = fs::canonicalize(file_name).ok()?;
+        let entry = self.cached.get(&path)?;
+
+        if !entry.is_fresh(stamp(&path).ok()?) {
+            return None;
+        }
+
+        debug!("using cached counts: {}", file_name);
+        self.counted.lock().unwrap().insert(path, entry.clone());
+
+        let rows = entry
+            .rows
+            .iter()
+            .map(|(suffix, counts)| (format!("{}{}", file_name, suffix), counts.clone()))
+            .collect();
+
+        Some(rows)
+    }
+
+    /// Caches the rows of counts for the given file.
+    pub fn insert(&self, file_name: &str, rows: &[(String, Counted)]) {
+        if file_name == STDIN_IDENTIFIER {
+            return;
+        }
+
+        let path = match fs::canonicalize(file_name) {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+
+        let (size, mtime_secs, mtime_nanos) = match stamp(&path) {
+            Ok(stamp) => stamp,
+            Err(_) => return,
+        };
+
+        let rows = rows
+            .iter()
+            .map(|(name, counts)| {
+                let suffix = name.strip_prefix(file_name).unwrap_or_default();
+                (suffix.to_string(), counts.clone())
+            })
+            .collect();
+
+        let entry = Entry {
+            size,
+            mtime_secs,
+            mtime_nanos,
+            rows,
+        };
+
+        self.counted.lock().unwrap().insert(path, entry);
+    }
+
+    /// Writes the cache with the files counted in this run, replacing the
+    /// entries of the files that were counted again. Entries for files that
+    /// weren't counted in this run are kept.
+    pub fn save(self) -> Result<(), Error> {
+        let path = self.path;
+        let mut files = self.cached;
+        files.extend(self.counted.into_inner().unwrap());
+
+        let cache_file = CacheFile {
+            options: self.options,
+            files,
+        };
+
+        let write = || -> Result<(), Error> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            let file = BufWriter::new(File::create(&path)?);
+            serde_json::to_writer(file, &cache_file)?;
+            Ok(())
+        };
+
+        write().map_err(|e| format_err!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::Counter;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_cache() {
+        let _ = env_logger::try_init();
+
+        let dir = std::env::temp_dir().join(format!("uwc-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let input = dir.join("input.txt");
+        fs::write(&input, "one two\n").unwrap();
+        let input = input.to_str().unwrap();
+        let cache_path = dir.join("cache.json");
+
+        let opts = Opt::from_iter(&["uwc", "-w"]);
+        let counts: Counted = vec![(Counter::Words, 2)].into_iter().collect();
+
+        let cache = Cache::load(cache_path.clone(), &opts).unwrap();
+        assert!(cache.get(input).is_none());
+        cache.insert(input, &[(input.to_string(), counts.clone())]);
+        cache.save().unwrap();
+
+        let cache = Cache::load(cache_path.clone(), &opts).unwrap();
+        assert_eq!(Some(vec![(input.to_string(), counts)]), cache.get(input));
+
+        // different options don't use the cache
+        let other_opts = Opt::from_iter(&["uwc", "-l"]);
+        let cache = Cache::load(cache_path.clone(), &other_opts).unwrap();
+        assert!(cache.get(input).is_none());
+
+        // neither do files that changed
+        fs::write(input, "one two three\n").unwrap();
+        let cache = Cache::load(cache_path, &opts).unwrap();
+        assert!(cache.get(input).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str;
+
+use failure::Error;
+use rayon::prelude::*;
+
+use crate::input::Input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+
+/// An invalid UTF-8 sequence that was found in an input.
+#[derive(Debug, PartialEq)]
+struct Invalid {
+    /// The line it's on, counting from 1.
+    line: usize,
+
+    /// The offset of its first byte in the input.
+    offset: u64,
+
+    bytes: Vec<u8>,
+}
+
+impl Invalid {
+    fn describe(&self, name: &str) -> String {
+        let mut bytes = String::new();
+
+        for b in &self.bytes {
+            let _ = write!(bytes, "\\x{:02x}", b);
+        }
+
+        format!(
+            "{}:{}: invalid UTF-8 at byte {}: {}",
+            name, self.line, self.offset, bytes
+        )
+    }
+}
+
+/// Finds the invalid UTF-8 sequences in everything read from `reader`, without
+/// holding more than a buffer of it at a time. With `all`, every one of them
+/// is found, and otherwise only the first. A character that's cut off at the
+/// end counts as invalid too.
+fn find_invalid<R: Read>(reader: R, all: bool) -> io::Result<Vec<Invalid>> {
+    let mut reader = BufReader::new(reader);
+    let mut invalid = Vec::new();
+
+    // the bytes that have been read, but not checked yet, since they could be
+    // the start of a character that goes on in the next buffer
+    let mut pending = Vec::new();
+    let mut offset = 0;
+    let mut line = 1;
+
+    loop {
+        let buf = reader.fill_buf()?;
+        let done = buf.is_empty();
+        pending.extend_from_slice(buf);
+        let len = buf.len();
+        reader.consume(len);
+
+        let mut pos = 0;
+
+        while pos < pending.len() {
+            let (valid_len, invalid_len) = match str::from_utf8(&pending[pos..]) {
+                Ok(_) => (pending.len() - pos, 0),
+                Err(e) => match e.error_len() {
+                    Some(invalid_len) => (e.valid_up_to(), invalid_len),
+                    None if done => (e.valid_up_to(), pending.len() - pos - e.valid_up_to()),
+                    None => (e.valid_up_to(), 0),
+                },
+            };
+
+            line += count_line_feeds(&pending[pos..pos + valid_len]);
+            pos += valid_len;
+
+            if invalid_len == 0 {
+                break;
+            }
+
+            invalid.push(Invalid {
+                line,
+                offset: offset + pos as u64,
+                bytes: pending[pos..pos + invalid_len].to_vec(),
+            });
+            pos += invalid_len;
+
+            if !all {
+                return Ok(invalid);
+            }
+        }
+
+        if done {
+            return Ok(invalid);
+        }
+
+        offset += pos as u64;
+        pending.drain(..pos);
+    }
+}
+
+/// Counts the line feeds in `bytes`.
+fn count_line_feeds(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| **b == b'\n').count()
+}
+
+/// Checks whether the given inputs are valid UTF-8 with `--check-utf8`,
+/// instead of counting them. For every input, either that it's valid, or
+/// where its invalid sequences are, is written to stdout, in the order the
+/// inputs were given. Returns whether the inputs could all be read, and
+/// whether they were all valid.
+pub fn check(file_names: &[String], opts: &Opt) -> Result<(bool, bool), Error> {
+    let results: Vec<_> = file_names
+        .par_iter()
+        .map(|name| {
+            let input = Input::open(name, opts.decompress)?;
+            find_invalid(input, opts.all_invalid)
+        })
+        .collect();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut success = true;
+    let mut valid = true;
+
+    for (name, result) in file_names.iter().zip(results) {
+        match result {
+            Ok(ref invalid) if invalid.is_empty() => writeln!(stdout, "{}: valid UTF-8", name)?,
+            Ok(invalid) => {
+                valid = false;
+
+                for invalid in invalid {
+                    writeln!(stdout, "{}", invalid.describe(name))?;
+                }
+            }
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                success = false;
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok((success, valid))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reads the bytes a few at a time, so that characters are split between
+    /// buffers.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_find_invalid() {
+        let _ = env_logger::try_init();
+
+        let text = b"caf\xc3\xa9\nok\n\xff \xe2\x82 \xf0\x9f\x98\x80 \xe2";
+        let invalid = |line, offset, bytes: &[u8]| Invalid {
+            line,
+            offset,
+            bytes: bytes.to_vec(),
+        };
+
+        let expected = vec![
+            invalid(3, 9, b"\xff"),
+            invalid(3, 11, b"\xe2\x82"),
+            invalid(3, 19, b"\xe2"),
+        ];
+
+        assert_eq!(expected, find_invalid(&text[..], true).unwrap());
+        assert_eq!(expected, find_invalid(Trickle(text), true).unwrap());
+        assert_eq!(
+            expected[..1],
+            find_invalid(Trickle(text), false).unwrap()[..]
+        );
+        assert!(find_invalid(Trickle("Μπορῶ\n".as_bytes()), true)
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            "a.txt:3: invalid UTF-8 at byte 11: \\xe2\\x82",
+            expected[1].describe("a.txt")
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::counter::{Count, CountOptions};
+
+/// The code points that can be counted with `--replacements`,
+/// `--private-use`, and `--unassigned`, which usually point to something that
+/// went wrong with the text before it got to uwc.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum CodePoint {
+    /// U+FFFD REPLACEMENT CHARACTER, which decoders put in for what they
+    /// couldn't decode. With `--invalid-utf8 replace`, these include the ones
+    /// uwc put in for the invalid sequences it read, one for every sequence.
+    Replacement,
+
+    /// The code points of the private use areas, U+E000 to U+F8FF, and planes
+    /// 15 and 16, which have no meaning of their own. Icon fonts and legacy
+    /// encodings leave them in text.
+    PrivateUse,
+
+    /// The code points that aren't assigned to anything in [`UNICODE_VERSION`],
+    /// which are either from a newer version of Unicode or corrupted.
+    Unassigned,
+}
+
+/// A code point first assigned in each version of Unicode, newest first, to
+/// find which one the regex crate's tables are from.
+const NEW_IN_VERSION: [(&str, char); 5] = [
+    ("17.0.0", '\u{20C1}'),
+    ("16.0.0", '\u{10D40}'),
+    ("15.1.0", '\u{2EBF0}'),
+    ("15.0.0", '\u{11F00}'),
+    ("14.0.0", '\u{1E290}'),
+];
+
+lazy_static! {
+    static ref UNASSIGNED: Regex = Regex::new(r"\p{Cn}").unwrap();
+
+    /// The version of Unicode that the general categories of characters are
+    /// looked up in, which is the one of the regex crate's tables. What's
+    /// unassigned depends on it. It's the newest one whose code point in
+    /// `NEW_IN_VERSION` is assigned.
+    pub static ref UNICODE_VERSION: &'static str = NEW_IN_VERSION
+        .iter()
+        .find(|(_, c)| !UNASSIGNED.is_match(c.encode_utf8(&mut [0; 4])))
+        .map_or("older than 14.0.0", |(version, _)| version);
+}
+
+impl CodePoint {
+    /// The name of the code point's counter, as shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            CodePoint::Replacement => "replacements",
+            CodePoint::PrivateUse => "private-use",
+            CodePoint::Unassigned => "unassigned",
+        }
+    }
+}
+
+/// Determines if the given character is in one of the private use areas.
+fn is_private_use(c: char) -> bool {
+    matches!(c, '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{FFFFD}' | '\u{100000}'..='\u{10FFFD}')
+}
+
+impl Count for CodePoint {
+    fn count(&self, s: &str, _options: &CountOptions) -> usize {
+        // none of them are ASCII
+        if s.is_ascii() {
+            return 0;
+        }
+
+        match self {
+            CodePoint::Replacement => s.matches(char::REPLACEMENT_CHARACTER).count(),
+            CodePoint::PrivateUse => s.chars().filter(|c| is_private_use(*c)).count(),
+            CodePoint::Unassigned => UNASSIGNED.find_iter(s).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let options = CountOptions::default();
+        let count = |s: &str| CodePoint::Replacement.count(s, &options);
+
+        assert_eq!(0, count("plain ASCII text"));
+        assert_eq!(2, count("caf\u{FFFD} na\u{FFFD}ve"));
+        assert_eq!(1, count(&String::from_utf8_lossy(b"caf\xc3 au lait")));
+
+        let count = |s: &str| CodePoint::PrivateUse.count(s, &options);
+
+        assert_eq!(0, count("plain ASCII text, \u{D7FF}\u{F900}\u{FFFFE}"));
+        assert_eq!(4, count("\u{E000} icon \u{F8FF}\u{F0000}\u{10FFFD}"));
+
+        // Garay was added in Unicode 16.0, with a gap after its first letters
+        let count = |s: &str| CodePoint::Unassigned.count(s, &options);
+
+        assert_eq!(0, count("plain ASCII text, \u{10D40}\u{E000}"));
+        assert_eq!(3, count("\u{0378}\u{10D66}\u{FFFF}"));
+
+        // the code points of newer versions than the tables are from aren't
+        // assigned yet
+        for (version, c) in &NEW_IN_VERSION {
+            let unassigned = count(c.encode_utf8(&mut [0; 4]));
+            assert_eq!(*version > *UNICODE_VERSION, unassigned == 1);
+        }
+    }
+}
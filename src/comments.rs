@@ -0,0 +1,267 @@
+use std::str::FromStr;
+
+use crate::counter::LineClass;
+
+/// Parses a line comment prefix given with `--comment-prefix`.
+pub fn parse_prefix(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        return Err("Expected a comment prefix, like '//'".to_string());
+    }
+
+    Ok(s.to_string())
+}
+
+/// The start and end of a block comment, given like `/* */` with
+/// `--block-comment`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockComment {
+    pub start: String,
+    pub end: String,
+}
+
+impl FromStr for BlockComment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BlockComment, String> {
+        let delimiters: Vec<&str> = s.split_whitespace().collect();
+
+        let (start, end) = match delimiters[..] {
+            [start, end] => (start, end),
+            _ => {
+                return Err(format!(
+                    "Expected the start and end of a block comment, like '/* */': {}",
+                    s
+                ))
+            }
+        };
+
+        Ok(BlockComment {
+            start: start.to_string(),
+            end: end.to_string(),
+        })
+    }
+}
+
+/// Classifies lines as code, comments, or blank, like cloc. The text of a line
+/// is given with `update`, which can be called more than once if the line is
+/// read in pieces, and the line's class is taken with `end_line`. Whether a
+/// block comment is open carries over to the next lines.
+#[derive(Debug, Clone)]
+pub struct LineClassifier {
+    prefixes: Vec<String>,
+    blocks: Vec<BlockComment>,
+
+    /// The block comment that's open, if any.
+    in_block: Option<usize>,
+
+    /// Whether the rest of the line is a line comment.
+    in_line_comment: bool,
+
+    /// The end of the text so far, if it could be the start of a comment
+    /// delimiter that goes on in the next piece of the line.
+    carry: String,
+
+    has_code: bool,
+    has_comment: bool,
+}
+
+impl LineClassifier {
+    pub fn new(prefixes: Vec<String>, blocks: Vec<BlockComment>) -> LineClassifier {
+        LineClassifier {
+            prefixes,
+            blocks,
+            in_block: None,
+            in_line_comment: false,
+            carry: String::new(),
+            has_code: false,
+            has_comment: false,
+        }
+    }
+
+    /// Classifies more of the text of the current line.
+    pub fn update(&mut self, text: &str) {
+        match self.carry.is_empty() {
+            true => self.classify(text, false),
+            false => {
+                let text = std::mem::take(&mut self.carry) + text;
+                self.classify(&text, false);
+            }
+        }
+    }
+
+    /// Classifies the given text. Unless it's the last of the line, a comment
+    /// delimiter it could end in the middle of is carried over to the next.
+    fn classify(&mut self, text: &str, last: bool) {
+        let mut rest = text;
+
+        while !self.in_line_comment {
+            if let Some(i) = self.in_block {
+                let end = &self.blocks[i].end;
+
+                match rest.find(end.as_str()) {
+                    Some(pos) => {
+                        rest = &rest[pos + end.len()..];
+                        self.has_comment = true;
+                        self.in_block = None;
+                        continue;
+                    }
+                    None => {
+                        self.has_comment |= !rest.trim().is_empty();
+
+                        if !last {
+                            let split = (1..end.len())
+                                .rev()
+                                .find(|len| rest.as_bytes().ends_with(&end.as_bytes()[..*len]));
+
+                            if let Some(len) = split {
+                                self.carry = rest[rest.len() - len..].to_string();
+                            }
+                        }
+
+                        return;
+                    }
+                }
+            }
+
+            rest = rest.trim_start();
+
+            if rest.is_empty() {
+                return;
+            }
+
+            let mut starts = self
+                .prefixes
+                .iter()
+                .chain(self.blocks.iter().map(|block| &block.start));
+
+            if !last && starts.any(|start| start.len() > rest.len() && start.starts_with(rest)) {
+                self.carry = rest.to_string();
+                return;
+            }
+
+            if self
+                .prefixes
+                .iter()
+                .any(|prefix| rest.starts_with(prefix.as_str()))
+            {
+                self.has_comment = true;
+                self.in_line_comment = true;
+                return;
+            }
+
+            let block = self
+                .blocks
+                .iter()
+                .position(|block| rest.starts_with(block.start.as_str()));
+
+            if let Some(i) = block {
+                rest = &rest[self.blocks[i].start.len()..];
+                self.has_comment = true;
+                self.in_block = Some(i);
+                continue;
+            }
+
+            // anything else is code, up to where a comment could start
+            let c = rest.chars().next().unwrap();
+            rest = &rest[c.len_utf8()..];
+            self.has_code = true;
+        }
+    }
+
+    /// Ends the current line, and returns its class.
+    pub fn end_line(&mut self) -> LineClass {
+        let carry = std::mem::take(&mut self.carry);
+        self.classify(&carry, true);
+
+        let class = match (self.has_code, self.has_comment) {
+            (true, _) => LineClass::Code,
+            (false, true) => LineClass::Comment,
+            (false, false) => LineClass::Blank,
+        };
+
+        self.skip_line();
+
+        class
+    }
+
+    /// Forgets the current line without classifying it, like when it can't be
+    /// read.
+    pub fn skip_line(&mut self) {
+        self.carry.clear();
+        self.in_line_comment = false;
+        self.has_code = false;
+        self.has_comment = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let _ = env_logger::try_init();
+
+        let mut classifier = LineClassifier::new(
+            vec!["//".to_string(), "#".to_string()],
+            vec!["/* */".parse().unwrap()],
+        );
+
+        fn classify(classifier: &mut LineClassifier, line: &str) -> LineClass {
+            classifier.update(line);
+            classifier.end_line()
+        }
+
+        assert_eq!(LineClass::Code, classify(&mut classifier, "fn main() {\n"));
+        assert_eq!(
+            LineClass::Comment,
+            classify(&mut classifier, "    // a comment\n")
+        );
+        assert_eq!(LineClass::Comment, classify(&mut classifier, "# another\n"));
+        assert_eq!(LineClass::Blank, classify(&mut classifier, "  \t\n"));
+        assert_eq!(
+            LineClass::Code,
+            classify(&mut classifier, "x = 1; // trailing\n")
+        );
+        assert_eq!(LineClass::Comment, classify(&mut classifier, "/* start\n"));
+        assert_eq!(LineClass::Blank, classify(&mut classifier, "\n"));
+        assert_eq!(
+            LineClass::Comment,
+            classify(&mut classifier, "  // still in it\n")
+        );
+        assert_eq!(LineClass::Comment, classify(&mut classifier, "end */\n"));
+        assert_eq!(
+            LineClass::Code,
+            classify(&mut classifier, "/* a */ y = 2;\n")
+        );
+        assert_eq!(LineClass::Code, classify(&mut classifier, "z = 3; /* b\n"));
+        assert_eq!(LineClass::Code, classify(&mut classifier, "*/ w = 4;\n"));
+        assert_eq!(LineClass::Code, classify(&mut classifier, "a/b\n"));
+
+        // a line read in pieces
+        classifier.update("  /");
+        classifier.update("/ split\n");
+        assert_eq!(LineClass::Comment, classifier.end_line());
+
+        classifier.update("/");
+        assert_eq!(LineClass::Code, classifier.end_line());
+
+        classifier.update("/* a *");
+        classifier.update("/\n");
+        assert_eq!(LineClass::Comment, classifier.end_line());
+        assert_eq!(LineClass::Code, classify(&mut classifier, "b\n"));
+    }
+
+    #[test]
+    fn test_parse_block_comment() {
+        let _ = env_logger::try_init();
+
+        let block: BlockComment = "<!-- -->".parse().unwrap();
+        assert_eq!("<!--", block.start);
+        assert_eq!("-->", block.end);
+
+        assert!("/*".parse::<BlockComment>().is_err());
+        assert!("/* */ x".parse::<BlockComment>().is_err());
+        assert!(parse_prefix(" ").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, BufRead, Read};
+use std::str::FromStr;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+/// When to decompress inputs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Decompress {
+    /// Decompress inputs that start with the magic bytes of a supported
+    /// compression format, and read the rest as they are.
+    #[default]
+    Auto,
+
+    /// Never decompress inputs.
+    Off,
+
+    /// Decompress every input, and fail on inputs that aren't compressed with
+    /// a supported format.
+    Force,
+}
+
+impl FromStr for Decompress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Decompress, String> {
+        match s {
+            "auto" => Ok(Decompress::Auto),
+            "off" => Ok(Decompress::Off),
+            "force" => Ok(Decompress::Force),
+            _ => Err(format!("Unknown decompression mode: {}", s)),
+        }
+    }
+}
+
+/// The supported compression formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+/// The length of the longest magic bytes, i.e., how many bytes are needed to
+/// detect any of the formats.
+pub const MAX_MAGIC_LEN: usize = 6;
+
+/// The magic bytes that every stream in a compression format starts with.
+const MAGIC_BYTES: [(Compression, &[u8]); 4] = [
+    (Compression::Gzip, &[0x1f, 0x8b]),
+    (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+    (Compression::Bzip2, b"BZh"),
+];
+
+impl Compression {
+    /// Detects the compression format from the first bytes of a stream.
+    pub fn detect(header: &[u8]) -> Option<Compression> {
+        MAGIC_BYTES
+            .iter()
+            .find(|(_, magic)| header.starts_with(magic))
+            .map(|(compression, _)| *compression)
+    }
+
+    /// Wraps the given reader in a decoder for this format. Concatenated
+    /// streams, like those made by appending to a gzipped log, are decoded in
+    /// full.
+    pub fn decoder<R>(self, reader: R) -> io::Result<Box<dyn Read + Send>>
+    where
+        R: BufRead + Send + 'static,
+    {
+        Ok(match self {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Some(Compression::Gzip),
+            Compression::detect(&[0x1f, 0x8b, 0x08])
+        );
+        assert_eq!(Some(Compression::Bzip2), Compression::detect(b"BZh91AY"));
+        assert_eq!(None, Compression::detect(b"hello"));
+        assert_eq!(None, Compression::detect(b""));
+    }
+}
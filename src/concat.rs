@@ -0,0 +1,91 @@
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use failure::Error;
+
+use crate::count_reader;
+use crate::counter::Counted;
+use crate::input::Input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+
+/// The name of the row of counts of the inputs with `--concat`.
+pub const NAME: &str = "concat";
+
+/// Reads the inputs one after the other, as if they were one, opening each of
+/// them once the one before it ends. The inputs that can't be opened are
+/// reported and left out.
+struct ConcatReader<'a> {
+    file_names: &'a [String],
+    opts: &'a Opt,
+
+    /// The input that's being read, and its name.
+    current: Option<(&'a str, Input)>,
+
+    /// Whether an input couldn't be opened.
+    failed: bool,
+}
+
+impl<'a> ConcatReader<'a> {
+    /// Opens the next input that can be opened, if there are any left.
+    fn open_next(&mut self) -> Option<(&'a str, Input)> {
+        while let Some((name, rest)) = self.file_names.split_first() {
+            self.file_names = rest;
+
+            match Input::open(name, self.opts.decompress) {
+                Ok(input) => return Some((name, input)),
+                Err(e) => {
+                    report::input_error(self.opts, InputError::io(name, &e));
+                    self.failed = true;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a> Read for ConcatReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.current.is_none() {
+                self.current = self.open_next();
+            }
+
+            let (name, input) = match self.current {
+                Some((name, ref mut input)) => (name, input),
+                None => return Ok(0),
+            };
+
+            // the errors are reported under the name of the whole stream, so
+            // they say which input they came from
+            match input.read(buf) {
+                Ok(0) if !buf.is_empty() => self.current = None,
+                Ok(n) => return Ok(n),
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", name, e))),
+            }
+        }
+    }
+}
+
+/// Counts all the given inputs as one with `--concat`, writing their counts
+/// under [`NAME`]. Since nothing separates the inputs, a word or a line that
+/// spans the end of one input and the start of the next one is counted once,
+/// and in line mode, the lines are numbered on from one input to the next.
+pub fn count(
+    file_names: &[String],
+    opts: &Opt,
+    mut output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Vec<(String, Counted)>), Error> {
+    let mut reader = ConcatReader {
+        file_names,
+        opts,
+        current: None,
+        failed: false,
+    };
+
+    let (success, counts) = count_reader(NAME, &mut reader, opts, &mut output_writer)?;
+    let rows = counts.map(|counts| (NAME.to_string(), counts));
+
+    Ok((success && !reader.failed, rows.into_iter().collect()))
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use failure::{format_err, Error};
+use log::*;
+use serde::Deserialize;
+
+use crate::counter::Counter;
+
+/// The environment variable with the path to the config file. If it's set but
+/// empty, no config file is read.
+pub const CONFIG_VAR: &str = "UWC_CONFIG";
+
+/// The environment variable with extra arguments, which are given before the
+/// ones on the command line.
+pub const OPTS_VAR: &str = "UWC_OPTS";
+
+/// The config file that's used if `UWC_CONFIG` isn't set.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("uwc").join("config.toml"))
+}
+
+/// Defaults for the options, read from a TOML file. Options that are given on
+/// the command line win over these.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The counters that are counted when none are given.
+    pub counters: Option<Vec<Counter>>,
+
+    /// Whether to print the header.
+    pub header: Option<bool>,
+
+    /// Whether to print the output with elastic tabstops.
+    pub elastic: Option<bool>,
+
+    pub chunk_size: Option<usize>,
+
+    /// Named sets of options, like `[profile.prose]`, which are used with
+    /// `--profile`.
+    #[serde(rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named set of options in the config file, which is used with `--profile`.
+/// Its options win over the ones outside of any profile.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub counters: Option<Vec<Counter>>,
+    pub header: Option<bool>,
+    pub elastic: Option<bool>,
+    pub chunk_size: Option<usize>,
+
+    /// Any other options, given like on the command line, e.g.,
+    /// `["--mode", "line"]`. These go before the ones in `UWC_OPTS`.
+    pub args: Vec<String>,
+}
+
+/// Gets the extra arguments in `UWC_OPTS`, which are split up like a shell
+/// would, so that they can be quoted.
+pub fn env_args() -> Result<Vec<String>, Error> {
+    let opts = match env::var(OPTS_VAR) {
+        Ok(opts) => opts,
+        Err(env::VarError::NotPresent) => return Ok(Vec::new()),
+        Err(e) => return Err(format_err!("{}: {}", OPTS_VAR, e)),
+    };
+
+    shell_words::split(&opts).map_err(|e| format_err!("{}: {}", OPTS_VAR, e))
+}
+
+impl Config {
+    /// Loads the config file at `UWC_CONFIG`, or at the default path. It's no
+    /// error if there's no config file at the default path, but there has to
+    /// be one at `UWC_CONFIG`.
+    pub fn load() -> Result<Config, Error> {
+        let (path, required) = match env::var_os(CONFIG_VAR) {
+            Some(ref path) if path.is_empty() => return Ok(Config::default()),
+            Some(path) => (PathBuf::from(path), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format_err!("{}: {}", path.display(), e)),
+        };
+
+        debug!("using config file: {}", path.display());
+
+        contents
+            .parse()
+            .map_err(|e| format_err!("{}: {}", path.display(), e))
+    }
+
+    /// Uses the options of the profile with the given name, returning the
+    /// arguments it gives.
+    pub fn apply_profile(&mut self, name: &str) -> Result<Vec<String>, Error> {
+        let profile = match self.profiles.remove(name) {
+            Some(profile) => profile,
+            None if self.profiles.is_empty() => {
+                return Err(format_err!(
+                    "unknown profile: {} (none are configured)",
+                    name
+                ))
+            }
+            None => {
+                let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                return Err(format_err!(
+                    "unknown profile: {} (expected one of: {})",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        };
+
+        debug!("using profile: {}", name);
+
+        self.counters = profile.counters.or_else(|| self.counters.take());
+        self.header = profile.header.or(self.header);
+        self.elastic = profile.elastic.or(self.elastic);
+        self.chunk_size = profile.chunk_size.or(self.chunk_size);
+
+        Ok(profile.args)
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Config, String> {
+        toml::from_str(s).map_err(|e: toml::de::Error| e.to_string().trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let _ = env_logger::try_init();
+
+        let config: Config = r#"
+            counters = ["lines", "graphemes"]
+            elastic = false
+            chunk-size = 100
+        "#
+        .parse()
+        .unwrap();
+
+        let expected = Config {
+            counters: Some(vec![Counter::Line, Counter::GraphemeCluster]),
+            header: None,
+            elastic: Some(false),
+            chunk_size: Some(100),
+            profiles: BTreeMap::new(),
+        };
+        assert_eq!(expected, config);
+
+        assert_eq!(Ok(Config::default()), "".parse());
+        assert!("counters = [\"sentences\"]".parse::<Config>().is_err());
+        assert!("colour = true".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let _ = env_logger::try_init();
+
+        let mut config: Config = r#"
+            counters = ["lines"]
+            elastic = false
+
+            [profile.prose]
+            counters = ["words", "graphemes"]
+            args = ["--locale", "fr"]
+
+            [profile.logs]
+            header = false
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(2, config.profiles.len());
+        assert!(config.clone().apply_profile("code").is_err());
+
+        let args = config.apply_profile("prose").unwrap();
+        assert_eq!(vec!["--locale", "fr"], args);
+        assert_eq!(
+            Some(vec![Counter::Words, Counter::GraphemeCluster]),
+            config.counters
+        );
+        assert_eq!(Some(false), config.elastic);
+        assert_eq!(None, config.header);
+
+        assert!("[profile.prose]\ncolour = true".parse::<Config>().is_err());
+    }
+}
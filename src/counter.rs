@@ -1,14 +1,45 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::str;
+use std::io::{self, Read};
+use std::str::{self, FromStr};
+use std::sync::{Mutex, RwLock};
 
+use icu_segmenter::options::WordBreakInvariantOptions;
+use icu_segmenter::{WordSegmenter, WordSegmenterBorrowed};
+use lazy_static::*;
 use log::*;
+use regex::Regex;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::constants::NEWLINES;
+use crate::ascii;
+use crate::code_points::CodePoint;
+use crate::constants::FF;
+use crate::error::{self, UwcError};
+use crate::letter_case::LetterCase;
+use crate::links::Link;
+use crate::locale::Locale;
+use crate::newline::Newlines;
+use crate::width::WidthClass;
 
 pub type Counted = BTreeMap<Counter, usize>;
 
+/// The counts of a list of `Counter`s, in the same order as them. These are for
+/// counting lots of little things, like lines, where allocating `Counted` for
+/// every one of them would add up.
+pub type CountArray = [usize; MAX_COUNTERS];
+
+/// The most counters that can be registered at runtime.
+pub const MAX_CUSTOM_COUNTERS: usize = 16;
+
+/// The most counters there can be, built in or registered at runtime.
+pub const MAX_COUNTERS: usize = BUILTIN_COUNTERS.len() + MAX_CUSTOM_COUNTERS;
+
+/// How many grapheme clusters on either side of a split in the middle of a word
+/// are looked at to find what spans it.
+pub const CONTEXT_GRAPHEMES: usize = 16;
+
 /// Take all the counts in `other_counts` and sum them into `accum`.
 pub fn sum_counts(accum: &mut Counted, other_counts: &Counted) {
     for (counter, count) in other_counts {
@@ -31,28 +62,212 @@ where
     totals
 }
 
+/// Averages each counter over all the `Counted` instances. There are no
+/// averages if there are no instances.
+pub fn mean_counts<'a, I>(counts: I) -> BTreeMap<Counter, f64>
+where
+    I: IntoIterator<Item = &'a Counted>,
+{
+    let mut num_counts = 0;
+    let mut totals = BTreeMap::new();
+
+    for counts in counts {
+        sum_counts(&mut totals, counts);
+        num_counts += 1;
+    }
+
+    totals
+        .into_iter()
+        .map(|(counter, total)| (counter, total as f64 / num_counts as f64))
+        .collect()
+}
+
+lazy_static! {
+    /// ICU's word segmenter, with the dictionary and LSTM models for complex
+    /// scripts loaded.
+    static ref ICU_WORD_SEGMENTER: WordSegmenterBorrowed<'static> =
+        WordSegmenter::new_auto(WordBreakInvariantOptions::default());
+}
+
+/// The algorithm used to find word boundaries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum WordAlgorithm {
+    /// The default word boundary rules of [UAX #29]. These can't find word
+    /// boundaries in scripts that don't separate words with spaces, like Thai,
+    /// Lao, Khmer, or Japanese.
+    ///
+    /// [UAX #29]: https://www.unicode.org/reports/tr29/#Word_Boundaries
+    #[default]
+    Unicode,
+
+    /// ICU's segmenter, which falls back to dictionary and LSTM models for
+    /// scripts without spaces between words.
+    Icu,
+}
+
+/// Iterates over the words in the given `&str` with ICU's segmenter.
+fn icu_words(s: &str) -> impl Iterator<Item = &str> {
+    let mut start = 0;
+
+    ICU_WORD_SEGMENTER
+        .segment_str(s)
+        .iter_with_word_type()
+        .filter_map(move |(end, word_type)| {
+            let word = &s[start..end];
+            start = end;
+            Some(word).filter(|_| word_type.is_word_like())
+        })
+}
+
+impl FromStr for WordAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WordAlgorithm, String> {
+        match s {
+            "unicode" => Ok(WordAlgorithm::Unicode),
+            "icu" => Ok(WordAlgorithm::Icu),
+            _ => Err(format!("Unknown word algorithm: {}", s)),
+        }
+    }
+}
+
+/// The flavor of grapheme clusters to count.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GraphemeMode {
+    /// Extended grapheme clusters, which keep things like spacing marks and
+    /// prepended characters with their base character.
+    #[default]
+    Extended,
+
+    /// Legacy grapheme clusters, as segmented by some older systems.
+    Legacy,
+}
+
+impl GraphemeMode {
+    /// Counts the grapheme clusters in the given `&str` in this mode.
+    fn count_graphemes(self, s: &str) -> usize {
+        s.graphemes(self == GraphemeMode::Extended).count()
+    }
+}
+
+impl FromStr for GraphemeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GraphemeMode, String> {
+        match s {
+            "extended" => Ok(GraphemeMode::Extended),
+            "legacy" => Ok(GraphemeMode::Legacy),
+            _ => Err(format!("Unknown grapheme mode: {}", s)),
+        }
+    }
+}
+
+/// Options that change how the counters find the things they count.
+#[derive(Debug, Clone, Default)]
+pub struct CountOptions {
+    /// The algorithm used to find word boundaries.
+    pub word_algorithm: WordAlgorithm,
+
+    /// The language to tailor segmentation to, if any.
+    pub locale: Option<Locale>,
+
+    /// The flavor of grapheme clusters to count.
+    pub grapheme_mode: GraphemeMode,
+
+    /// The newline sequences that count as line breaks.
+    pub newlines: Newlines,
+}
+
+impl CountOptions {
+    /// Counts the words in the given `&str`.
+    fn count_words(&self, s: &str) -> usize {
+        match self.word_algorithm {
+            WordAlgorithm::Unicode => self.count_segmented_words(s.unicode_words()),
+            WordAlgorithm::Icu => self.count_segmented_words(icu_words(s)),
+        }
+    }
+
+    /// Counts the words found by a word segmenter.
+    fn count_segmented_words<'a, I: Iterator<Item = &'a str>>(&self, words: I) -> usize {
+        match self.locale {
+            Some(ref locale) => words.map(|word| locale.count_words_in(word)).sum(),
+            None => words.count(),
+        }
+    }
+}
+
 /// Something that counts things in `&str`s.
 pub trait Count {
     /// Counts something in the given `&str`.
-    fn count(&self, s: &str) -> usize;
+    fn count(&self, s: &str, options: &CountOptions) -> usize;
 }
 
 impl Count for Counter {
-    fn count(&self, s: &str) -> usize {
-        match *self {
-            Counter::GraphemeCluster => s.graphemes(true).count(),
+    fn count(&self, s: &str, options: &CountOptions) -> usize {
+        if s.is_ascii() {
+            if let Some(count) = ascii::count(*self, s, options) {
+                return count;
+            }
+        }
+
+        self.count_segmented(s, options)
+    }
+}
+
+impl Counter {
+    /// Counts this in the given `&str` with the full Unicode segmentation
+    /// rules, even if it's all ASCII.
+    pub fn count_segmented(self, s: &str, options: &CountOptions) -> usize {
+        match self {
+            Counter::GraphemeCluster => options.grapheme_mode.count_graphemes(s),
             Counter::NumByte => s.len(),
             Counter::Line => s
                 .graphemes(true)
-                .filter(|grapheme| NEWLINES.contains(grapheme))
+                .filter(|grapheme| options.newlines.contains(grapheme))
                 .count(),
-            Counter::Words => s.unicode_words().count(),
+            Counter::Words => options.count_words(s),
             Counter::CodePoints => s.chars().count(),
+            Counter::Pages => s.matches(FF).count(),
+            Counter::Link(link) => link.count(s, options),
+            Counter::LetterCase(case) => case.count(s, options),
+            Counter::Width(class) => class.count(s, options),
+            Counter::CodePoint(code_point) => code_point.count(s, options),
+            // a line's class depends on the lines before it, so the lines are
+            // classified as they're read instead
+            Counter::LineClass(_) => 0,
+            Counter::Custom(i) => custom_counter(i).count.count(s, options),
         }
     }
 }
 
-/// Different types of counters.
+/// What a line is made of, as found by the `uwc` command with `--comment-prefix`
+/// and `--block-comment`.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum LineClass {
+    /// A line with any code on it, even if it has a comment too.
+    Code,
+
+    /// A line with only comments on it.
+    Comment,
+
+    /// A line with only whitespace on it, which includes the lines in block
+    /// comments that have nothing else on them.
+    Blank,
+}
+
+impl LineClass {
+    /// The name of the class's counter, as shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineClass::Code => "code-lines",
+            LineClass::Comment => "comment-lines",
+            LineClass::Blank => "blank-lines",
+        }
+    }
+}
+
+/// Different types of counters. They're identified by their names, as shown in
+/// the header, wherever they're written or read.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 pub enum Counter {
     /// Counts lines.
@@ -69,6 +284,28 @@ pub enum Counter {
 
     /// Counts unicode code points
     CodePoints,
+
+    /// Counts page breaks, i.e., form feeds.
+    Pages,
+
+    /// Counts links of the given kind, like URLs or email addresses.
+    Link(Link),
+
+    /// Counts letters of the given case, like uppercase letters.
+    LetterCase(LetterCase),
+
+    /// Counts characters of the given East Asian width class, like wide ones.
+    Width(WidthClass),
+
+    /// Counts the given code points, like U+FFFD REPLACEMENT CHARACTER.
+    CodePoint(CodePoint),
+
+    /// Counts lines of the given class, like comment lines.
+    LineClass(LineClass),
+
+    /// A counter that was registered at runtime with `register`, by the order
+    /// it was registered in.
+    Custom(usize),
 }
 
 /// A convenience array of all counter types.
@@ -80,6 +317,34 @@ pub const ALL_COUNTERS: [Counter; 5] = [
     Counter::CodePoints,
 ];
 
+/// Every built-in counter, in the order they're counted in. Unlike
+/// `ALL_COUNTERS`, this has the ones that are only counted when they're asked
+/// for by name, or with an option of their own.
+pub const BUILTIN_COUNTERS: [Counter; 22] = [
+    Counter::Line,
+    Counter::Words,
+    Counter::NumByte,
+    Counter::GraphemeCluster,
+    Counter::CodePoints,
+    Counter::Pages,
+    Counter::Link(Link::Url),
+    Counter::Link(Link::Email),
+    Counter::Link(Link::Hashtag),
+    Counter::Link(Link::Mention),
+    Counter::LetterCase(LetterCase::Upper),
+    Counter::LetterCase(LetterCase::Lower),
+    Counter::LetterCase(LetterCase::Title),
+    Counter::Width(WidthClass::Narrow),
+    Counter::Width(WidthClass::Wide),
+    Counter::Width(WidthClass::Ambiguous),
+    Counter::CodePoint(CodePoint::Replacement),
+    Counter::CodePoint(CodePoint::PrivateUse),
+    Counter::CodePoint(CodePoint::Unassigned),
+    Counter::LineClass(LineClass::Code),
+    Counter::LineClass(LineClass::Comment),
+    Counter::LineClass(LineClass::Blank),
+];
+
 /// A convenience array of the default counter types.
 pub const DEFAULT_COUNTERS: [Counter; 3] = [Counter::Line, Counter::Words, Counter::NumByte];
 
@@ -91,33 +356,522 @@ impl fmt::Display for Counter {
             Counter::Line => "lines",
             Counter::Words => "words",
             Counter::CodePoints => "codepoints",
+            Counter::Pages => "pages",
+            Counter::Link(link) => link.name(),
+            Counter::LetterCase(case) => case.name(),
+            Counter::Width(class) => class.name(),
+            Counter::CodePoint(code_point) => code_point.name(),
+            Counter::LineClass(class) => class.name(),
+            Counter::Custom(i) => &custom_counter(i).name,
         };
 
         write!(f, "{}", s)
     }
 }
 
+impl FromStr for Counter {
+    type Err = String;
+
+    /// Parses a counter from its name, as shown in the header.
+    fn from_str(s: &str) -> Result<Counter, String> {
+        let builtin = BUILTIN_COUNTERS
+            .iter()
+            .find(|counter| counter.to_string() == s);
+
+        if let Some(counter) = builtin {
+            return Ok(*counter);
+        }
+
+        let custom = CUSTOM_COUNTERS
+            .read()
+            .unwrap()
+            .iter()
+            .position(|counter| counter.name == s);
+
+        custom.map(Counter::Custom).ok_or_else(|| {
+            format!(
+                "Unknown counter: {} (expected one of: {})",
+                s,
+                names().join(", ")
+            )
+        })
+    }
+}
+
+/// Gets the names of all the counters, including the ones registered at
+/// runtime, in the order they're counted in.
+pub fn names() -> Vec<String> {
+    let custom_counters = CUSTOM_COUNTERS.read().unwrap();
+    let custom_names = custom_counters.iter().map(|counter| counter.name.clone());
+
+    BUILTIN_COUNTERS
+        .iter()
+        .map(Counter::to_string)
+        .chain(custom_names)
+        .collect()
+}
+
+impl Serialize for Counter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Counter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Counter, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A counter that's registered at runtime, rather than built in.
+struct CustomCounter {
+    name: String,
+    count: Box<dyn Count + Send + Sync>,
+}
+
+lazy_static! {
+    /// The counters registered at runtime. They're never unregistered, so
+    /// they're leaked, to be counted with without holding the lock.
+    static ref CUSTOM_COUNTERS: RwLock<Vec<&'static CustomCounter>> = RwLock::new(Vec::new());
+}
+
+fn custom_counter(i: usize) -> &'static CustomCounter {
+    CUSTOM_COUNTERS.read().unwrap()[i]
+}
+
+/// Registers a counter under the given name, so that it can be selected,
+/// counted, and written like the built-in ones. Returns an error if there's
+/// already a counter with the name, or if `MAX_CUSTOM_COUNTERS` have already
+/// been registered.
+pub fn register(name: &str, count: Box<dyn Count + Send + Sync>) -> Result<Counter, String> {
+    check_name(name)?;
+
+    if name.parse::<Counter>().is_ok() {
+        return Err(format!("Counter already exists: {}", name));
+    }
+
+    let mut custom_counters = CUSTOM_COUNTERS.write().unwrap();
+
+    if custom_counters.len() == MAX_CUSTOM_COUNTERS {
+        return Err(format!(
+            "Too many counters, at most {} can be added",
+            MAX_CUSTOM_COUNTERS
+        ));
+    }
+
+    let name = name.to_string();
+    custom_counters.push(Box::leak(Box::new(CustomCounter { name, count })));
+
+    Ok(Counter::Custom(custom_counters.len() - 1))
+}
+
+/// Checks that a counter can be registered under the given name, other than
+/// it being taken.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+    if !valid_name {
+        return Err(format!(
+            "Invalid counter name, expected letters, digits, '_', or '-': {}",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+lazy_static! {
+    /// The regex counters that have been registered, by how they were given,
+    /// so that registering the same one again gives the same counter.
+    static ref REGEX_COUNTERS: Mutex<HashMap<String, Counter>> = Mutex::new(HashMap::new());
+}
+
+/// Counts the matches of a regex.
+struct RegexCount(Regex);
+
+impl Count for RegexCount {
+    fn count(&self, s: &str, _options: &CountOptions) -> usize {
+        self.0.find_iter(s).count()
+    }
+}
+
+/// A counter of the matches of a regex, given like `todo=TODO|FIXME` with
+/// `--count-regex`. It's only counted once it's been registered with
+/// [`RegexCounter::register`].
+#[derive(Debug, Clone)]
+pub struct RegexCounter {
+    name: String,
+    regex: Regex,
+}
+
+impl RegexCounter {
+    /// Registers the counter, like [`register`]. Registering the same one
+    /// again gives the same counter, but another one with the same name is an
+    /// error.
+    pub fn register(&self) -> Result<Counter, String> {
+        let spec = self.to_string();
+        let mut registered = REGEX_COUNTERS.lock().unwrap();
+
+        if let Some(counter) = registered.get(&spec) {
+            return Ok(*counter);
+        }
+
+        let counter = register(&self.name, Box::new(RegexCount(self.regex.clone())))?;
+        registered.insert(spec, counter);
+
+        Ok(counter)
+    }
+
+    /// Gets the counter that this was registered as, if it's been registered.
+    pub fn counter(&self) -> Option<Counter> {
+        REGEX_COUNTERS
+            .lock()
+            .unwrap()
+            .get(&self.to_string())
+            .copied()
+    }
+}
+
+impl fmt::Display for RegexCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.regex)
+    }
+}
+
+impl FromStr for RegexCounter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RegexCounter, String> {
+        let (name, pattern) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=REGEX: {}", s))?;
+
+        check_name(name)?;
+
+        if BUILTIN_COUNTERS
+            .iter()
+            .any(|counter| counter.to_string() == name)
+        {
+            return Err(format!("Counter already exists: {}", name));
+        }
+
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+
+        Ok(RegexCounter {
+            name: name.to_string(),
+            regex,
+        })
+    }
+}
+
+/// The counts of the counters that only need the extended grapheme clusters of
+/// a `&str`, so that they can all be counted in a single pass over them.
+#[derive(Debug, Default)]
+struct GraphemeCounts {
+    graphemes: usize,
+    lines: usize,
+    code_points: usize,
+    pages: usize,
+}
+
+impl GraphemeCounts {
+    fn new(s: &str, options: &CountOptions) -> GraphemeCounts {
+        let mut counts = GraphemeCounts::default();
+
+        for grapheme in s.graphemes(true) {
+            counts.graphemes += 1;
+            counts.code_points += grapheme.chars().count();
+
+            if options.newlines.contains(grapheme) {
+                counts.lines += 1;
+            }
+
+            // form feeds are control characters, which are always grapheme
+            // clusters of their own
+            if grapheme == FF {
+                counts.pages += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Determines if the given counter can be counted in the pass over the
+    /// grapheme clusters.
+    fn counts(counter: Counter, options: &CountOptions) -> bool {
+        match counter {
+            Counter::GraphemeCluster => options.grapheme_mode == GraphemeMode::Extended,
+            Counter::Line | Counter::CodePoints | Counter::Pages => true,
+            Counter::NumByte
+            | Counter::Words
+            | Counter::Link(_)
+            | Counter::LetterCase(_)
+            | Counter::Width(_)
+            | Counter::CodePoint(_)
+            | Counter::LineClass(_)
+            | Counter::Custom(_) => false,
+        }
+    }
+
+    fn get(&self, counter: Counter) -> usize {
+        match counter {
+            Counter::GraphemeCluster => self.graphemes,
+            Counter::Line => self.lines,
+            Counter::CodePoints => self.code_points,
+            Counter::Pages => self.pages,
+            Counter::NumByte
+            | Counter::Words
+            | Counter::Link(_)
+            | Counter::LetterCase(_)
+            | Counter::Width(_)
+            | Counter::CodePoint(_)
+            | Counter::LineClass(_)
+            | Counter::Custom(_) => unreachable!(),
+        }
+    }
+}
+
 /// Counts the given `Counter`s in the given `&str`.
-pub fn count<'a, I>(counters: I, s: &str) -> Counted
+pub fn count<'a, I>(counters: I, s: &str, options: &CountOptions) -> Counted
 where
     I: IntoIterator<Item = &'a Counter>,
 {
-    let counts: Counted = counters.into_iter().map(|c| (*c, c.count(s))).collect();
+    let counters: Vec<Counter> = counters.into_iter().copied().collect();
+    let counts = counted_from_array(&counters, &count_array(&counters, s, options));
     debug!("s: {}, counted: {:#?}", s, counts);
     counts
 }
 
+/// Counts the given `Counter`s in `s`, which continues the text that ended in
+/// `before` without a word boundary in between, e.g., because a long record
+/// was split in the middle of a word. Whatever spans the split, like the rest
+/// of that word, was already counted in the text before, so it isn't counted
+/// again.
+pub fn count_continued(
+    counters: &[Counter],
+    before: &str,
+    s: &str,
+    options: &CountOptions,
+) -> CountArray {
+    let after_end = s
+        .grapheme_indices(true)
+        .nth(CONTEXT_GRAPHEMES)
+        .map_or(s.len(), |(i, _)| i);
+    let after = &s[..after_end];
+    let joined = format!("{}{}", before, after);
+
+    let mut counts = [0; MAX_COUNTERS];
+
+    for (count, c) in counts.iter_mut().zip(counters) {
+        let spanning = (c.count(before, options) + c.count(after, options))
+            .saturating_sub(c.count(&joined, options));
+        *count = c.count(s, options).saturating_sub(spanning);
+    }
+
+    counts
+}
+
+/// Something that counts things in text that arrives in chunks, e.g., from a
+/// socket, where a chunk can end anywhere, even in the middle of a word or a
+/// grapheme cluster.
+pub trait StreamingCount {
+    /// Counts the next chunk of the text.
+    fn update(&mut self, chunk: &str);
+
+    /// Gets the count of all the text.
+    fn finalize(self) -> usize;
+}
+
+/// Counts a `Counter` in text that arrives in chunks. The last few grapheme
+/// clusters of the text are kept, so that whatever spans the end of a chunk is
+/// only counted once, like with `count_continued`.
+#[derive(Debug, Clone)]
+pub struct StreamingCounter<'a> {
+    counter: Counter,
+    options: &'a CountOptions,
+    count: usize,
+
+    /// The last `CONTEXT_GRAPHEMES` grapheme clusters of the text so far.
+    context: String,
+}
+
+impl<'a> StreamingCounter<'a> {
+    pub fn new(counter: Counter, options: &'a CountOptions) -> StreamingCounter<'a> {
+        StreamingCounter {
+            counter,
+            options,
+            count: 0,
+            context: String::new(),
+        }
+    }
+
+    pub fn counter(&self) -> Counter {
+        self.counter
+    }
+}
+
+impl StreamingCount for StreamingCounter<'_> {
+    fn update(&mut self, chunk: &str) {
+        if chunk.is_empty() {
+            return;
+        }
+
+        let counters = [self.counter];
+
+        self.count += match self.context.is_empty() {
+            true => count_array(&counters, chunk, self.options)[0],
+            false => count_continued(&counters, &self.context, chunk, self.options)[0],
+        };
+
+        let last_graphemes = |s: &str| {
+            s.grapheme_indices(true)
+                .rev()
+                .nth(CONTEXT_GRAPHEMES - 1)
+                .map(|(i, _)| i)
+        };
+
+        self.context = match last_graphemes(chunk) {
+            Some(start) => chunk[start..].to_string(),
+            None => {
+                let joined = format!("{}{}", self.context, chunk);
+                let start = last_graphemes(&joined).unwrap_or(0);
+                joined[start..].to_string()
+            }
+        };
+    }
+
+    fn finalize(self) -> usize {
+        self.count
+    }
+}
+
+/// How much is read at a time by `count_reader`.
+const READ_LEN: usize = 64 << 10;
+
+/// Counts the given `Counter`s in the UTF-8 text read from `reader`, a chunk at
+/// a time, so that it doesn't have to be held in memory whole. Errors have the
+/// byte offset they occurred at.
+pub fn count_reader<R: Read>(
+    mut reader: R,
+    counters: &[Counter],
+    options: &CountOptions,
+) -> error::Result<Counted> {
+    let mut streams: Vec<StreamingCounter> = counters
+        .iter()
+        .map(|c| StreamingCounter::new(*c, options))
+        .collect();
+
+    let mut buf = vec![0; READ_LEN];
+
+    // the bytes of an incomplete character at the end of the last read
+    let mut pending = 0;
+
+    // the offset in the input of the start of `buf`
+    let mut offset = 0;
+
+    loop {
+        let read = match reader.read(&mut buf[pending..]) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(UwcError::io(e).at_byte(offset + pending as u64)),
+        };
+
+        let filled = pending + read;
+
+        let valid = match str::from_utf8(&buf[..filled]) {
+            Ok(_) => filled,
+            Err(e) if e.error_len().is_none() && read > 0 => e.valid_up_to(),
+            Err(e) => return Err(UwcError::utf8(e).at_byte(offset + e.valid_up_to() as u64)),
+        };
+
+        let text = str::from_utf8(&buf[..valid]).unwrap();
+
+        for stream in streams.iter_mut() {
+            stream.update(text);
+        }
+
+        if read == 0 {
+            break;
+        }
+
+        buf.copy_within(valid..filled, 0);
+        pending = filled - valid;
+        offset += valid as u64;
+    }
+
+    Ok(streams
+        .into_iter()
+        .map(|stream| (stream.counter(), stream.finalize()))
+        .collect())
+}
+
+/// Counts the given `Counter`s in `s` like `count`, but into an array, which
+/// doesn't have to be allocated. When more than one of them only needs the
+/// grapheme clusters, they're all counted in the same pass over them, rather
+/// than segmenting the text again for each one. Text that's all ASCII is
+/// counted byte by byte instead.
+pub fn count_array(counters: &[Counter], s: &str, options: &CountOptions) -> CountArray {
+    let mut counts = [0; MAX_COUNTERS];
+
+    if s.is_ascii() {
+        for (count, c) in counts.iter_mut().zip(counters) {
+            *count = ascii::count(*c, s, options).unwrap_or_else(|| c.count_segmented(s, options));
+        }
+
+        return counts;
+    }
+
+    let fused = counters
+        .iter()
+        .filter(|c| GraphemeCounts::counts(**c, options))
+        .count();
+    let grapheme_counts = Some(fused)
+        .filter(|fused| *fused > 1)
+        .map(|_| GraphemeCounts::new(s, options));
+
+    for (count, c) in counts.iter_mut().zip(counters) {
+        *count = match grapheme_counts {
+            Some(ref grapheme_counts) if GraphemeCounts::counts(*c, options) => {
+                grapheme_counts.get(*c)
+            }
+            _ => c.count_segmented(s, options),
+        };
+    }
+
+    counts
+}
+
+/// Adds the counts in `other` to `accum`.
+pub fn sum_arrays(accum: &mut CountArray, other: &CountArray) {
+    for (count, other) in accum.iter_mut().zip(other) {
+        *count += other;
+    }
+}
+
+/// Gets the counts in an array of the given counters as `Counted`.
+pub fn counted_from_array(counters: &[Counter], counts: &CountArray) -> Counted {
+    counters
+        .iter()
+        .copied()
+        .zip(counts.iter().copied())
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::counter;
     use crate::constants::*;
-    use env_logger;
+    use crate::counter;
 
     #[test]
     fn test_count_hello() {
         let s = "hello";
-        let counts = count(&counter::ALL_COUNTERS[..], s);
+        let counts = count(&counter::ALL_COUNTERS[..], s, &CountOptions::default());
 
         let mut correct_counts = BTreeMap::new();
         correct_counts.insert(Counter::GraphemeCluster, 5);
@@ -159,7 +913,7 @@ mod test {
             debug!("grapheme: {}", grapheme);
         }
 
-        let counts = count(&counter::ALL_COUNTERS[..], &s);
+        let counts = count(&counter::ALL_COUNTERS[..], &s, &CountOptions::default());
 
         let mut correct_counts = BTreeMap::new();
         correct_counts.insert(Counter::GraphemeCluster, 23);
@@ -171,19 +925,19 @@ mod test {
         correct_counts.insert(Counter::CodePoints, 24);
 
         assert_eq!(correct_counts, counts);
+        assert_eq!(1, Counter::Pages.count(&s, &CountOptions::default()));
     }
 
     #[test]
     fn test_count_counts_words() {
         let _ = env_logger::try_init();
 
-        let i_can_eat_glass =
-            "Μπορῶ νὰ φάω σπασμένα γυαλιὰ χωρὶς νὰ πάθω τίποτα.";
+        let i_can_eat_glass = "Μπορῶ νὰ φάω σπασμένα γυαλιὰ χωρὶς νὰ πάθω τίποτα.";
         let s = String::from(i_can_eat_glass);
 
         //debug!("words: {:?}", i_can_eat_glass.unicode_words().collect::<Vec<&str>>());
 
-        let counts = count(&counter::ALL_COUNTERS[..], &s);
+        let counts = count(&counter::ALL_COUNTERS[..], &s, &CountOptions::default());
 
         let mut correct_counts = BTreeMap::new();
         correct_counts.insert(Counter::GraphemeCluster, 50);
@@ -205,18 +959,214 @@ mod test {
 
         let counters = [Counter::CodePoints];
 
-        let counts = count(&counters[..], &one);
+        let counts = count(&counters[..], one, &CountOptions::default());
 
         let mut correct_counts = BTreeMap::new();
         correct_counts.insert(Counter::CodePoints, 1);
 
         assert_eq!(correct_counts, counts);
 
-        let counts = count(&counters[..], &two);
+        let counts = count(&counters[..], two, &CountOptions::default());
 
         let mut correct_counts = BTreeMap::new();
         correct_counts.insert(Counter::CodePoints, 2);
 
         assert_eq!(correct_counts, counts);
     }
+
+    #[test]
+    fn test_count_words_icu() {
+        let _ = env_logger::try_init();
+
+        // "every two weeks" in Thai, which has no spaces between words
+        let s = "ทุกสองสัปดาห์";
+
+        let counters = [Counter::Words];
+        let options = CountOptions {
+            word_algorithm: WordAlgorithm::Icu,
+            ..CountOptions::default()
+        };
+
+        let counts = count(&counters[..], s, &options);
+        assert_eq!(Some(&3), counts.get(&Counter::Words));
+    }
+
+    #[test]
+    fn test_count_words_locale() {
+        let _ = env_logger::try_init();
+
+        let s = "L'homme est allé jusqu'à l'école d'été.";
+
+        let counters = [Counter::Words];
+
+        let counts = count(&counters[..], s, &CountOptions::default());
+        assert_eq!(Some(&6), counts.get(&Counter::Words));
+
+        let options = CountOptions {
+            locale: Some("fr".parse().unwrap()),
+            ..CountOptions::default()
+        };
+
+        let counts = count(&counters[..], s, &options);
+        assert_eq!(Some(&10), counts.get(&Counter::Words));
+    }
+
+    #[test]
+    fn test_count_graphemes_legacy() {
+        let _ = env_logger::try_init();
+
+        // "Hindi" in Devanagari; only extended grapheme clusters keep the
+        // spacing vowel signs with their base and join the conjunct "न्द"
+        let s = "हिन्दी";
+
+        let counters = [Counter::GraphemeCluster];
+
+        let counts = count(&counters[..], s, &CountOptions::default());
+        assert_eq!(Some(&2), counts.get(&Counter::GraphemeCluster));
+
+        let options = CountOptions {
+            grapheme_mode: GraphemeMode::Legacy,
+            ..CountOptions::default()
+        };
+
+        let counts = count(&counters[..], s, &options);
+        assert_eq!(Some(&5), counts.get(&Counter::GraphemeCluster));
+    }
+
+    #[test]
+    fn test_count_lines_restricted_newlines() {
+        let _ = env_logger::try_init();
+
+        let mut s = String::from("foo\r\nbar\n\nbaz");
+        s += NEL;
+        s += FF;
+        s += LS;
+        s += "\r";
+
+        let counters = [Counter::Line];
+
+        let counts = count(&counters[..], &s, &CountOptions::default());
+        assert_eq!(Some(&7), counts.get(&Counter::Line));
+
+        let options = CountOptions {
+            newlines: "lf".parse().unwrap(),
+            ..CountOptions::default()
+        };
+
+        let counts = count(&counters[..], &s, &options);
+        assert_eq!(Some(&3), counts.get(&Counter::Line));
+    }
+
+    #[test]
+    fn test_count_array_fused() {
+        let _ = env_logger::try_init();
+
+        let mut s = String::from("Μπορῶ νὰ\r\nφάω 🇫🇷 x\u{301}");
+        s += FF;
+        s += "हिन्दी\n";
+
+        for grapheme_mode in &[GraphemeMode::Extended, GraphemeMode::Legacy] {
+            let options = CountOptions {
+                grapheme_mode: *grapheme_mode,
+                ..CountOptions::default()
+            };
+
+            let fused = count_array(&ALL_COUNTERS, &s, &options);
+
+            for (c, count) in ALL_COUNTERS.iter().zip(&fused) {
+                assert_eq!(c.count(&s, &options), *count, "{}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_counter() {
+        let _ = env_logger::try_init();
+
+        let mut s = String::from("Μπορῶ νὰ φάω\r\n🇫🇷🇩🇪 x\u{301} can't 3.14 ");
+        s += FF;
+        s += "हिन्दी\r";
+        let s = s.repeat(3);
+        let options = CountOptions::default();
+
+        let boundaries: Vec<usize> = s.char_indices().map(|(i, _)| i).collect();
+
+        for chunk_len in 1..8 {
+            for c in &ALL_COUNTERS {
+                let mut counter = StreamingCounter::new(*c, &options);
+
+                for (i, start) in boundaries.iter().enumerate().step_by(chunk_len) {
+                    let end = boundaries.get(i + chunk_len).copied().unwrap_or(s.len());
+                    counter.update(&s[*start..end]);
+                }
+
+                assert_eq!(c.count(&s, &options), counter.finalize(), "{}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let _ = env_logger::try_init();
+
+        let regex: RegexCounter = "todos=TODO|FIXME".parse().unwrap();
+        assert_eq!(None, regex.counter());
+        assert!("todos".parse::<Counter>().is_err());
+
+        let counter = regex.register().unwrap();
+        assert_eq!(Some(counter), regex.counter());
+        assert_eq!("todos", counter.to_string());
+        assert_eq!(Ok(counter), "todos".parse());
+        assert_eq!(Ok(counter), regex.register());
+
+        // the name of a counter can't be used again, or be invalid
+        let again: RegexCounter = "todos=TODO".parse().unwrap();
+        assert!(again.register().is_err());
+        assert_eq!(None, again.counter());
+        assert!("lines=x".parse::<RegexCounter>().is_err());
+        assert!("to dos=x".parse::<RegexCounter>().is_err());
+        assert!("bad=(".parse::<RegexCounter>().is_err());
+
+        // unknown counters are reported with the names of the ones there are
+        let e = "todo".parse::<Counter>().unwrap_err();
+        assert!(e.starts_with("Unknown counter: todo (expected one of: lines, words, bytes,"));
+        assert!(e.contains(", todos"), "{}", e);
+
+        for builtin in &BUILTIN_COUNTERS {
+            assert_eq!(Ok(*builtin), builtin.to_string().parse());
+            assert!(e.contains(&builtin.to_string()), "{}", e);
+        }
+
+        let counters = [Counter::Line, counter];
+        let counts = count(
+            &counters[..],
+            "TODO: FIXME\nTODO\n",
+            &CountOptions::default(),
+        );
+        assert_eq!(Some(&3), counts.get(&counter));
+        assert_eq!(Some(&2), counts.get(&Counter::Line));
+
+        // counters are written and read by name
+        let json = serde_json::to_string(&counts).unwrap();
+        assert_eq!(r#"{"lines":2,"todos":3}"#, json);
+        assert_eq!(counts, serde_json::from_str::<Counted>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_count_reader() {
+        let _ = env_logger::try_init();
+
+        let s = "Μπορῶ νὰ φάω σπασμένα γυαλιὰ\r\n🇫🇷 x\u{301} can't\n".repeat(5000);
+        let options = CountOptions::default();
+
+        let counts = count_reader(s.as_bytes(), &ALL_COUNTERS, &options).unwrap();
+        assert_eq!(count(&ALL_COUNTERS, &s, &options), counts);
+
+        // a character that's cut off at the end is invalid
+        let mut cut = s.into_bytes();
+        cut.extend_from_slice(&"é".as_bytes()[..1]);
+        let e = count_reader(&cut[..], &ALL_COUNTERS, &options).unwrap_err();
+        assert!(e.is_utf8());
+        assert_eq!(Some(cut.len() as u64 - 1), e.location().byte_offset);
+    }
 }
@@ -0,0 +1,109 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use globset::{Glob, GlobMatcher};
+
+/// Parses an encoding from one of its WHATWG labels, e.g., "utf-16le",
+/// "latin1", or "shift_jis".
+pub fn parse_encoding(s: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(s.as_bytes()).ok_or_else(|| format!("Unknown encoding: {}", s))
+}
+
+/// An encoding to use for the inputs whose names match a glob, given like
+/// `*.sjis.txt=shift_jis`.
+#[derive(Debug, Clone)]
+pub struct EncodingOverride {
+    glob: GlobMatcher,
+    encoding: &'static Encoding,
+}
+
+impl EncodingOverride {
+    /// Finds the encoding for the input with the given name. When more than
+    /// one override matches, the last one wins.
+    pub fn find(overrides: &[EncodingOverride], name: &str) -> Option<&'static Encoding> {
+        overrides
+            .iter()
+            .rev()
+            .find(|o| o.glob.is_match(name))
+            .map(|o| o.encoding)
+    }
+}
+
+impl FromStr for EncodingOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EncodingOverride, String> {
+        let (glob, encoding) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected GLOB=ENCODING: {}", s))?;
+
+        let glob = Glob::new(glob)
+            .map_err(|e| e.to_string())?
+            .compile_matcher();
+        let encoding = parse_encoding(encoding)?;
+
+        Ok(EncodingOverride { glob, encoding })
+    }
+}
+
+/// Wraps the reader so that it reads the input transcoded from the given
+/// encoding into UTF-8. UTF-8 inputs are read as they are, so that invalid
+/// UTF-8 is still handled as given by `--invalid-utf8`.
+pub fn transcode<'a, R: Read + 'a>(reader: R, encoding: &'static Encoding) -> Box<dyn Read + 'a> {
+    if encoding == UTF_8 {
+        return Box::new(reader);
+    }
+
+    Box::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding))
+            .build(reader),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_transcode() {
+        let _ = env_logger::try_init();
+
+        let cases: [(&str, &[u8], &str); 3] = [
+            ("utf-16le", b"h\0i\0\n\0", "hi\n"),
+            ("latin1", b"caf\xe9", "café"),
+            ("shift_jis", b"\x83\x4e\x83\x8a\x83\x58", "クリス"),
+        ];
+
+        for (label, input, expected) in cases.iter() {
+            let encoding = parse_encoding(label).unwrap();
+            let mut decoded = String::new();
+            transcode(*input, encoding)
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(*expected, decoded);
+        }
+
+        assert!(parse_encoding("klingon").is_err());
+    }
+
+    #[test]
+    fn test_encoding_override() {
+        let _ = env_logger::try_init();
+
+        let overrides: Vec<EncodingOverride> = ["*.txt=latin1", "*.sjis.txt=shift_jis"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        let find = |name| EncodingOverride::find(&overrides, name).map(Encoding::name);
+        assert_eq!(Some("Shift_JIS"), find("corpus/a.sjis.txt"));
+        assert_eq!(Some("windows-1252"), find("corpus/b.txt"));
+        assert_eq!(None, find("corpus/c.md"));
+
+        assert!("*.txt".parse::<EncodingOverride>().is_err());
+        assert!("*.txt=klingon".parse::<EncodingOverride>().is_err());
+    }
+}
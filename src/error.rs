@@ -1,16 +1,195 @@
-use crate::io;
-use std;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
 
-use failure::Fail;
+/// Where in an input an error occurred, as far as it's known. The parts are
+/// filled in by whatever knows them: the reader knows the byte offset, and the
+/// caller knows the file name and the line number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<String>,
 
-/// An error that can occur during a run of `uwc`.
-#[derive(Debug, Fail)]
+    /// The number of the line (or record) the error is in, starting from 1.
+    pub line: Option<usize>,
+
+    /// The offset from the start of the input of the byte where the error
+    /// occurred.
+    pub byte_offset: Option<u64>,
+}
+
+impl fmt::Display for Location {
+    /// Formats the file name and line number like `file:line: `, for the start
+    /// of an error message, or nothing if neither is known. The byte offset is
+    /// left to the message.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+        }
+
+        if self.file.is_some() || self.line.is_some() {
+            write!(f, " ")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error that can occur while reading an input, along with where in the
+/// input it occurred.
+#[derive(Debug)]
 pub enum UwcError {
-    #[fail(display = "io error occurred: {}", _0)]
-    IoError(io::Error),
+    /// Reading the input failed.
+    Io {
+        source: io::Error,
+        location: Location,
+    },
 
-    #[fail(display = "read non-utf8 bytes: {}", _0)]
-    Utf8Error(std::string::FromUtf8Error),
+    /// The input isn't valid UTF-8. The byte offset is that of the first
+    /// invalid byte.
+    Utf8 {
+        source: Utf8Error,
+        location: Location,
+    },
+}
+
+impl UwcError {
+    pub fn io(source: io::Error) -> UwcError {
+        UwcError::Io {
+            source,
+            location: Location::default(),
+        }
+    }
+
+    pub fn utf8(source: Utf8Error) -> UwcError {
+        UwcError::Utf8 {
+            source,
+            location: Location::default(),
+        }
+    }
+
+    pub fn location(&self) -> &Location {
+        match self {
+            UwcError::Io { location, .. } | UwcError::Utf8 { location, .. } => location,
+        }
+    }
+
+    fn location_mut(&mut self) -> &mut Location {
+        match self {
+            UwcError::Io { location, .. } | UwcError::Utf8 { location, .. } => location,
+        }
+    }
+
+    /// Sets the name of the file the error occurred in.
+    pub fn with_file(mut self, file: &str) -> UwcError {
+        self.location_mut().file = Some(file.to_string());
+        self
+    }
+
+    /// Sets the number of the line the error occurred in.
+    pub fn with_line(mut self, line: usize) -> UwcError {
+        self.location_mut().line = Some(line);
+        self
+    }
+
+    /// Sets the byte offset the error occurred at.
+    pub fn at_byte(mut self, byte_offset: u64) -> UwcError {
+        self.location_mut().byte_offset = Some(byte_offset);
+        self
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        matches!(self, UwcError::Utf8 { .. })
+    }
+
+    /// Converts the error into an `io::Error`, for callers that only deal in
+    /// those. Invalid UTF-8 is `InvalidData`, and the location is kept in its
+    /// message.
+    pub fn into_io(self) -> io::Error {
+        match self {
+            UwcError::Io { source, .. } => source,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+
+    /// Formats the error without the file name and line number, which are
+    /// left to whoever shows them. The byte offset is kept.
+    pub fn message(&self) -> String {
+        let mut message = match self {
+            UwcError::Io { .. } => "io error occurred".to_string(),
+            UwcError::Utf8 { .. } => "read non-utf8 bytes".to_string(),
+        };
+
+        if let Some(byte_offset) = self.location().byte_offset {
+            message += &format!(" at byte {}", byte_offset);
+        }
+
+        match self {
+            UwcError::Io { source, .. } => message + &format!(": {}", source),
+            UwcError::Utf8 { source, .. } => message + &format!(": {}", source),
+        }
+    }
+}
+
+impl fmt::Display for UwcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.location(), self.message())
+    }
+}
+
+impl Error for UwcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UwcError::Io { source, .. } => Some(source),
+            UwcError::Utf8 { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<io::Error> for UwcError {
+    fn from(source: io::Error) -> UwcError {
+        UwcError::io(source)
+    }
 }
 
 pub type Result<T> = std::result::Result<T, UwcError>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let _ = env_logger::try_init();
+
+        let bytes = vec![b'a', 0xff];
+        let source = std::str::from_utf8(&bytes).unwrap_err();
+        let e = UwcError::utf8(source).at_byte(12);
+        assert_eq!(
+            "read non-utf8 bytes at byte 12: invalid utf-8 sequence of 1 bytes from index 1",
+            e.to_string()
+        );
+
+        let e = e.with_file("input").with_line(2);
+        assert_eq!(
+            &Location {
+                file: Some("input".to_string()),
+                line: Some(2),
+                byte_offset: Some(12),
+            },
+            e.location()
+        );
+        assert!(e
+            .to_string()
+            .starts_with("input:2: read non-utf8 bytes at byte 12: "));
+        assert!(e.message().starts_with("read non-utf8 bytes at byte 12: "));
+
+        let e = UwcError::io(io::Error::other("oops")).with_file("input");
+        assert_eq!("input: io error occurred: oops", e.to_string());
+        assert_eq!(io::ErrorKind::Other, e.into_io().kind());
+    }
+}
@@ -0,0 +1,116 @@
+use std::os::raw::c_int;
+use std::panic;
+use std::slice;
+use std::str;
+
+use crate::counter::{self, CountOptions, Counter};
+
+/// The counters, by their bit in the mask given to `uwc_count_utf8`, which is
+/// also where their counts are written to. These are the `UWC_*` constants in
+/// `include/uwc.h`, so they can't change.
+const MASK_COUNTERS: [Counter; 6] = [
+    Counter::Line,
+    Counter::Words,
+    Counter::NumByte,
+    Counter::GraphemeCluster,
+    Counter::CodePoints,
+    Counter::Pages,
+];
+
+// the `UWC_OK` and `UWC_ERR_*` codes in `include/uwc.h`
+const UWC_OK: c_int = 0;
+const UWC_INVALID_ARGUMENT: c_int = -1;
+const UWC_INVALID_UTF8: c_int = -2;
+const UWC_PANIC: c_int = -3;
+
+/// Counts the counters in `counter_mask` in the `len` bytes of UTF-8 text at
+/// `ptr`, and writes their counts to `out_counts`, at the index of their bit in
+/// the mask. The counts of the other counters are left as they are. Returns 0
+/// on success, or one of the negative error codes.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes, unless `len` is 0, and
+/// `out_counts` must point to 6 writable `size_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn uwc_count_utf8(
+    ptr: *const u8,
+    len: usize,
+    counter_mask: u32,
+    out_counts: *mut usize,
+) -> c_int {
+    if (ptr.is_null() && len > 0)
+        || out_counts.is_null()
+        || counter_mask >> MASK_COUNTERS.len() != 0
+    {
+        return UWC_INVALID_ARGUMENT;
+    }
+
+    let bytes = match len {
+        0 => &[][..],
+        len => slice::from_raw_parts(ptr, len),
+    };
+
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return UWC_INVALID_UTF8,
+    };
+
+    let counters: Vec<Counter> = MASK_COUNTERS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| counter_mask & (1 << i) != 0)
+        .map(|(_, c)| *c)
+        .collect();
+
+    // panics can't unwind into C
+    let counts = match panic::catch_unwind(|| {
+        counter::count_array(&counters, text, &CountOptions::default())
+    }) {
+        Ok(counts) => counts,
+        Err(_) => return UWC_PANIC,
+    };
+
+    let out_counts = slice::from_raw_parts_mut(out_counts, MASK_COUNTERS.len());
+
+    for (c, count) in counters.iter().zip(counts.iter()) {
+        let i = MASK_COUNTERS.iter().position(|mask_c| mask_c == c).unwrap();
+        out_counts[i] = *count;
+    }
+
+    UWC_OK
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count_utf8() {
+        let _ = env_logger::try_init();
+
+        let text = "Μπορῶ νὰ\nφάω\n";
+        let mut counts = [usize::MAX; 6];
+        let mask = 0b11011; // everything but bytes and pages
+
+        let result =
+            unsafe { uwc_count_utf8(text.as_ptr(), text.len(), mask, counts.as_mut_ptr()) };
+        assert_eq!(UWC_OK, result);
+        assert_eq!([2, 3, usize::MAX, 13, 13, usize::MAX], counts);
+
+        let result = unsafe { uwc_count_utf8(std::ptr::null(), 0, 0b100, counts.as_mut_ptr()) };
+        assert_eq!(UWC_OK, result);
+        assert_eq!(0, counts[2]);
+
+        let invalid = b"\xff";
+        let result = unsafe { uwc_count_utf8(invalid.as_ptr(), 1, 1, counts.as_mut_ptr()) };
+        assert_eq!(UWC_INVALID_UTF8, result);
+
+        let result =
+            unsafe { uwc_count_utf8(text.as_ptr(), text.len(), 1 << 6, counts.as_mut_ptr()) };
+        assert_eq!(UWC_INVALID_ARGUMENT, result);
+
+        let result = unsafe { uwc_count_utf8(text.as_ptr(), text.len(), 1, std::ptr::null_mut()) };
+        assert_eq!(UWC_INVALID_ARGUMENT, result);
+    }
+}
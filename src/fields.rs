@@ -0,0 +1,201 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Parses the number of the field to count with `--field`, which starts at 1.
+pub fn parse_field(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(field) if field > 0 => Ok(field),
+        _ => Err(format!("Expected a field number, starting at 1: {}", s)),
+    }
+}
+
+/// Parses the separator of the fields given with `--field-sep`, which has to
+/// be a single ASCII character. `\t` and "tab" stand for a tab.
+pub fn parse_separator(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() && s != "\"" => Ok(s.as_bytes()[0]),
+        _ => Err(format!(
+            "Expected a single ASCII character other than '\"': {}",
+            s
+        )),
+    }
+}
+
+/// Reads only one field of every record of delimited data, like CSV or TSV.
+/// Fields in double quotes can have the separator and line breaks in them, and
+/// two double quotes in them stand for one. The quotes themselves aren't read.
+/// Every line is kept, so lines are numbered as in the input, even if a quoted
+/// field goes on over more than one.
+struct FieldReader<R> {
+    reader: BufReader<R>,
+
+    /// The index of the field to read.
+    selected: usize,
+    separator: u8,
+
+    /// The index of the field that's being read, which can go on in the next
+    /// line if it's quoted.
+    field: usize,
+
+    /// Whether nothing of the current field has been read yet, i.e., whether a
+    /// double quote would start a quoted field.
+    field_start: bool,
+
+    in_quotes: bool,
+
+    /// The selected field of the line that's being read.
+    line: Vec<u8>,
+
+    /// How much of `line` has been read.
+    pos: usize,
+}
+
+impl<R: Read> FieldReader<R> {
+    /// Gets the bytes of the selected field in a line without its line break.
+    fn select(&mut self, line: &[u8]) -> Vec<u8> {
+        let mut selected = Vec::new();
+        let mut bytes = line.iter().copied().peekable();
+
+        while let Some(b) = bytes.next() {
+            let keep = match b {
+                b'"' if self.in_quotes => match bytes.peek() {
+                    Some(b'"') => bytes.next().is_some(),
+                    _ => {
+                        self.in_quotes = false;
+                        false
+                    }
+                },
+                _ if self.in_quotes => true,
+                _ if b == self.separator => {
+                    self.field += 1;
+                    self.field_start = true;
+                    continue;
+                }
+                b'"' if self.field_start => {
+                    self.in_quotes = true;
+                    false
+                }
+                _ => true,
+            };
+
+            self.field_start = false;
+
+            if keep && self.field == self.selected {
+                selected.push(b);
+            }
+        }
+
+        // a record ends at the end of the line, unless it's in a quoted field
+        if !self.in_quotes {
+            self.field = 0;
+            self.field_start = true;
+        }
+
+        selected
+    }
+}
+
+impl<R: Read> Read for FieldReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            let mut line = Vec::new();
+            self.pos = 0;
+
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                self.line.clear();
+                return Ok(0);
+            }
+
+            let text_len = match line.strip_suffix(b"\r\n") {
+                Some(text) => text.len(),
+                None => line.strip_suffix(b"\n").unwrap_or(&line).len(),
+            };
+
+            self.line = self.select(&line[..text_len]);
+            self.line.extend_from_slice(&line[text_len..]);
+        }
+
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// Wraps the reader so that it reads only the given field of every record,
+/// counting from 1, with fields separated by `separator`. The fields of every
+/// line are read without decoding them, so invalid UTF-8 in the other fields
+/// is never read.
+pub fn select<'a, R: Read + 'a>(reader: R, field: usize, separator: u8) -> Box<dyn Read + 'a> {
+    Box::new(FieldReader {
+        reader: BufReader::new(reader),
+        selected: field - 1,
+        separator,
+        field: 0,
+        field_start: true,
+        in_quotes: false,
+        line: Vec::new(),
+        pos: 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn select_field(text: &[u8], field: usize, separator: u8) -> Vec<u8> {
+        let mut selected = Vec::new();
+        select(text, field, separator)
+            .read_to_end(&mut selected)
+            .unwrap();
+        selected
+    }
+
+    #[test]
+    fn test_select() {
+        let _ = env_logger::try_init();
+
+        let csv = b"id,name,description\r\n\
+                    1,a,\"one, two\"\r\n\
+                    2,\"b\",\"say \"\"hi\"\"\nthere\",x\n\
+                    3,c\"d\"\n\
+                    4,d,\"\",\"x\"y\n";
+
+        assert_eq!(
+            &b"description\r\none, two\r\nsay \"hi\"\nthere\n\n\n"[..],
+            &select_field(csv, 3, b',')[..]
+        );
+        assert_eq!(
+            &b"id\r\n1\r\n2\n\n3\n4\n"[..],
+            &select_field(csv, 1, b',')[..]
+        );
+
+        // quotes in the middle of a field are kept
+        assert_eq!(
+            &b"name\r\na\r\nb\n\nc\"d\"\nd\n"[..],
+            &select_field(csv, 2, b',')[..]
+        );
+
+        // and so is the rest of a field after its closing quote
+        assert_eq!(&b"\r\n\r\n\nx\n\nxy\n"[..], &select_field(csv, 4, b',')[..]);
+
+        // other fields don't have to be valid UTF-8
+        let tsv = b"\xff\tword\n";
+        assert_eq!(&b"word\n"[..], &select_field(tsv, 2, b'\t')[..]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let _ = env_logger::try_init();
+
+        assert_eq!(Ok(b'\t'), parse_separator("\\t"));
+        assert_eq!(Ok(b';'), parse_separator(";"));
+        assert!(parse_separator(";;").is_err());
+        assert!(parse_separator("\"").is_err());
+        assert!(parse_separator("é").is_err());
+
+        assert_eq!(Ok(2), parse_field("2"));
+        assert!(parse_field("0").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{self, BufReader, Write};
+
+use failure::Error;
+use rayon::prelude::*;
+
+use crate::counter;
+use crate::file_name_with_line;
+use crate::input::Input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+use crate::threshold::Threshold;
+use crate::ubufreader::{InvalidUtf8, UStrChunksIter};
+
+/// Finds the lines read from `input` whose counts satisfy `filter`, giving
+/// each of them after its file name and line number, like `file:12:text`.
+/// The line breaks aren't counted, or part of the lines that are given. With
+/// `--line-range`, only the lines in the range are looked at. The lines that
+/// can't be read are reported and left out, and with `--invalid-utf8
+/// skip-file`, no lines are given for inputs that aren't valid UTF-8. Returns
+/// whether the input was read without errors.
+fn matching_lines(name: &str, input: Input, filter: Threshold, opts: &Opt) -> (bool, Vec<String>) {
+    let mut reader = BufReader::new(input);
+    let count_options = opts.get_count_options();
+    let max_len = Some(opts.split_len).filter(|len| *len > 0);
+
+    let (lines_before, num_lines) = match opts.line_range {
+        Some(range) => (range.lines_before(), range.num_lines()),
+        None => (0, None),
+    };
+
+    let pieces = UStrChunksIter::new(&mut reader, opts.record_delimiter(), false)
+        .skip_records(lines_before)
+        .max_records(num_lines)
+        .invalid_utf8(opts.invalid_utf8)
+        .max_len(max_len);
+
+    let mut success = true;
+    let mut matches = Vec::new();
+    let mut line_no = lines_before + 1;
+    let mut line = String::new();
+    let mut count = 0;
+
+    for piece in pieces {
+        let piece = match piece {
+            Ok(piece) => piece,
+            Err(e) => {
+                let e = e.with_file(name).with_line(line_no);
+
+                if e.is_utf8() && opts.invalid_utf8 == InvalidUtf8::SkipFile {
+                    report::input_error(opts, InputError::read(&e).skipping());
+                    return (false, Vec::new());
+                }
+
+                report::input_error(opts, InputError::read(&e));
+                success = false;
+                line_no += 1;
+                line.clear();
+                count = 0;
+                continue;
+            }
+        };
+
+        count += counter::count_array(&[filter.counter()], &piece.text, &count_options)[0];
+        line.push_str(&piece.text);
+
+        if piece.ends_record {
+            if filter.holds(count) {
+                matches.push(format!("{}:{}", file_name_with_line(name, line_no), line));
+            }
+
+            line_no += 1;
+            line.clear();
+            count = 0;
+        }
+    }
+
+    (success, matches)
+}
+
+/// Writes the lines of every given input whose counts satisfy the condition
+/// given with `--filter`, like `words>30`, instead of counting them. Every
+/// line is given after its input's name and its line number, like `grep -n`
+/// does. Returns whether the inputs could all be read.
+pub fn report(file_names: &[String], filter: Threshold, opts: &Opt) -> Result<bool, Error> {
+    let results: Vec<_> = file_names
+        .par_iter()
+        .map(|name| match Input::open(name, opts.decompress) {
+            Ok(input) => matching_lines(name, input, filter, opts),
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                (false, Vec::new())
+            }
+        })
+        .collect();
+
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    let mut success = true;
+
+    for (input_success, lines) in results {
+        success &= input_success;
+
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(success)
+}
@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str;
+use std::thread;
+use std::time::Duration;
+
+use failure::{format_err, Error};
+use log::*;
+
+use crate::counter::{CountOptions, Counted, Counter, StreamingCount, StreamingCounter};
+use crate::input::STDIN_IDENTIFIER;
+use crate::opt::{CountMode, Opt};
+use crate::report::{self, InputError};
+use crate::write_table;
+
+/// A file that is kept open to count the data appended to it.
+///
+/// What's appended is fed to streaming counters as it's read, so it's only
+/// counted once, even if it ends in the middle of a word that's still being
+/// written.
+pub struct FollowedFile<'a> {
+    name: String,
+    file: File,
+    count_options: &'a CountOptions,
+
+    /// How far into the file has been read.
+    offset: u64,
+
+    /// The counters of everything read so far.
+    counters: Vec<StreamingCounter<'a>>,
+
+    /// The bytes of an incomplete character at the end of what's been read.
+    pending: Vec<u8>,
+}
+
+/// Finds where an incomplete character at the end of `bytes` starts, or the
+/// end of them if there isn't one.
+fn incomplete_char_start(bytes: &[u8]) -> usize {
+    (bytes.len().saturating_sub(3)..bytes.len())
+        .rev()
+        .find(|i| bytes[*i] & 0xc0 != 0x80)
+        .filter(|i| matches!(str::from_utf8(&bytes[*i..]), Err(e) if e.error_len().is_none()))
+        .unwrap_or(bytes.len())
+}
+
+impl<'a> FollowedFile<'a> {
+    pub fn open(
+        name: &str,
+        counters: &[Counter],
+        count_options: &'a CountOptions,
+    ) -> io::Result<FollowedFile<'a>> {
+        Ok(FollowedFile {
+            name: name.to_string(),
+            file: File::open(name)?,
+            count_options,
+            offset: 0,
+            counters: counters
+                .iter()
+                .map(|c| StreamingCounter::new(*c, count_options))
+                .collect(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Reads what was appended to the file since the last poll, and returns
+    /// whether there was anything. If the file was truncated, e.g., by log
+    /// rotation, it's counted again from the start.
+    pub fn poll(&mut self) -> io::Result<bool> {
+        let len = self.file.metadata()?.len();
+
+        let truncated = len < self.offset;
+
+        if truncated {
+            info!("{}: file truncated", self.name);
+            self.file.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+            self.pending.clear();
+
+            let count_options = self.count_options;
+            for counter in self.counters.iter_mut() {
+                *counter = StreamingCounter::new(counter.counter(), count_options);
+            }
+        }
+
+        let read = self.file.read_to_end(&mut self.pending)?;
+        self.offset += read as u64;
+
+        if read == 0 && !truncated {
+            return Ok(false);
+        }
+
+        // an incomplete character at the end will be counted once the rest of
+        // it is appended
+        let end = incomplete_char_start(&self.pending);
+        let text = String::from_utf8_lossy(&self.pending[..end]);
+
+        for counter in self.counters.iter_mut() {
+            counter.update(&text);
+        }
+
+        self.pending.drain(..end);
+
+        Ok(true)
+    }
+
+    /// Gets the counts of everything read so far.
+    pub fn counts(&self) -> Counted {
+        self.counters
+            .iter()
+            .map(|counter| (counter.counter(), counter.clone().finalize()))
+            .collect()
+    }
+}
+
+/// Counts the given files, then keeps them open and waits for data to be
+/// appended to them, like `tail -f`. Whenever any of them grows, the table of
+/// counts is written again. This only returns if there's an error.
+pub fn follow(file_names: &[String], opts: &Opt) -> Result<bool, Error> {
+    if opts.mode != CountMode::File {
+        return Err(format_err!("--follow can only be used in file mode"));
+    }
+
+    if file_names.iter().any(|name| name == STDIN_IDENTIFIER) {
+        return Err(format_err!("--follow can't be used with stdin"));
+    }
+
+    let counters: Vec<Counter> = opts.get_counters().into_iter().collect();
+    let count_options = opts.get_count_options();
+    let interval = Duration::from_secs_f64(opts.follow_interval);
+
+    let mut success = true;
+    let mut files = Vec::new();
+
+    for name in file_names {
+        match FollowedFile::open(name, &counters, &count_options) {
+            Ok(file) => files.push(file),
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                success = false;
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Ok(success);
+    }
+
+    let mut first = true;
+
+    loop {
+        let mut changed = false;
+
+        for file in files.iter_mut() {
+            match file.poll() {
+                Ok(file_changed) => changed |= file_changed,
+                Err(e) => report::input_error(opts, InputError::io(&file.name, &e)),
+            }
+        }
+
+        if changed || first {
+            let counts: Vec<Counted> = files.iter().map(|f| f.counts()).collect();
+            let rows = files.iter().zip(&counts).map(|(f, c)| (f.name.as_str(), c));
+            write_table(rows, opts)?;
+            first = false;
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_follow_appends() {
+        let _ = env_logger::try_init();
+
+        let path = std::env::temp_dir().join(format!("uwc-follow-{}", std::process::id()));
+        fs::write(&path, "hello wor").unwrap();
+
+        let opts = Opt::from_iter(&["uwc", "-lw"]);
+        let count_options = opts.get_count_options();
+        let counters: Vec<Counter> = opts.get_counters().into_iter().collect();
+        let name = path.to_str().unwrap();
+
+        let mut file = FollowedFile::open(name, &counters, &count_options).unwrap();
+        assert!(file.poll().unwrap());
+        let counts = file.counts();
+        assert_eq!(0, counts[&Counter::Line]);
+        assert_eq!(2, counts[&Counter::Words]);
+
+        assert!(!file.poll().unwrap());
+
+        let mut appender = OpenOptions::new().append(true).open(&path).unwrap();
+        appender.write_all(b"ld\nsecond line\nthi").unwrap();
+
+        assert!(file.poll().unwrap());
+        let counts = file.counts();
+        assert_eq!(2, counts[&Counter::Line]);
+        assert_eq!(5, counts[&Counter::Words]);
+
+        // truncating the file counts it again from the start
+        fs::write(&path, "new\n").unwrap();
+
+        assert!(file.poll().unwrap());
+        let counts = file.counts();
+        assert_eq!(1, counts[&Counter::Line]);
+        assert_eq!(1, counts[&Counter::Words]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
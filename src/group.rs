@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::counter::{self, Counted};
+
+/// The name of the group of the inputs whose paths don't match the pattern of
+/// `--group-by-path`, or that have no file extension.
+const NO_GROUP: &str = "(none)";
+
+/// How to group the counts of the inputs together with `--group-by` or
+/// `--group-by-path`.
+#[derive(Debug, Clone)]
+pub enum GroupBy {
+    /// Groups inputs by their file extension.
+    Ext,
+
+    /// Groups inputs by the first directory in their path.
+    Dir,
+
+    /// Groups inputs by what the first capture group of a pattern matches in
+    /// their path, like the crate in `src/(?P<crate>[^/]+)/`, or by the
+    /// whole match if there are no groups.
+    Path(Regex),
+}
+
+impl GroupBy {
+    /// Gets the name of the group that the input with the given name belongs
+    /// to.
+    pub fn group_of(&self, name: &str) -> String {
+        let path = Path::new(name);
+
+        match self {
+            GroupBy::Ext => match path.extension() {
+                Some(ext) => format!("*.{}", ext.to_string_lossy()),
+                None => NO_GROUP.to_string(),
+            },
+            GroupBy::Dir => {
+                let mut components = path
+                    .components()
+                    .filter(|c| *c != Component::CurDir)
+                    .peekable();
+
+                let mut dir = String::new();
+
+                // keep the root of absolute paths as part of the directory
+                while let Some(Component::RootDir) | Some(Component::Prefix(_)) = components.peek()
+                {
+                    dir.push_str(&components.next().unwrap().as_os_str().to_string_lossy());
+                }
+
+                match (components.next(), components.peek()) {
+                    (Some(first), Some(_)) => {
+                        dir.push_str(&first.as_os_str().to_string_lossy());
+                        dir
+                    }
+                    // the input isn't in any directory
+                    _ => ".".to_string(),
+                }
+            }
+            GroupBy::Path(pattern) => pattern
+                .captures(name)
+                .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                .map_or_else(|| NO_GROUP.to_string(), |m| m.as_str().to_string()),
+        }
+    }
+
+    /// Sums the counts of each input into the counts of its group.
+    pub fn group(&self, counts: &BTreeMap<String, Counted>) -> BTreeMap<String, Counted> {
+        let mut groups: BTreeMap<String, Counted> = BTreeMap::new();
+
+        for (name, input_counts) in counts {
+            let group_counts = groups.entry(self.group_of(name)).or_default();
+            counter::sum_counts(group_counts, input_counts);
+        }
+
+        groups
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GroupBy, String> {
+        match s {
+            "ext" => Ok(GroupBy::Ext),
+            "dir" => Ok(GroupBy::Dir),
+            _ => Err(format!("Unknown grouping: {}", s)),
+        }
+    }
+}
+
+/// Parses the pattern of `--group-by-path`, given like `re:src/([^/]+)/`.
+pub fn parse_path(s: &str) -> Result<GroupBy, String> {
+    let pattern = s
+        .strip_prefix("re:")
+        .ok_or_else(|| format!("Expected re:PATTERN, like re:src/([^/]+)/: {}", s))?;
+
+    Regex::new(pattern)
+        .map(GroupBy::Path)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_of() {
+        let _ = env_logger::try_init();
+
+        assert_eq!("*.rs", GroupBy::Ext.group_of("src/main.rs"));
+        assert_eq!("*.gz", GroupBy::Ext.group_of("data.tar.gz"));
+        assert_eq!("(none)", GroupBy::Ext.group_of("Makefile"));
+
+        assert_eq!("src", GroupBy::Dir.group_of("src/bin/main.rs"));
+        assert_eq!("src", GroupBy::Dir.group_of("./src/main.rs"));
+        assert_eq!("/usr", GroupBy::Dir.group_of("/usr/share/dict/words"));
+        assert_eq!(".", GroupBy::Dir.group_of("README.md"));
+
+        let by_crate = parse_path("re:src/(?P<crate>[^/]+)/").unwrap();
+        assert_eq!("uwc", by_crate.group_of("crates/src/uwc/lib.rs"));
+        assert_eq!("(none)", by_crate.group_of("src/main.rs"));
+
+        let by_chapter = parse_path(r"re:ch\d+").unwrap();
+        assert_eq!("ch02", by_chapter.group_of("book/ch02/intro.md"));
+
+        assert!(parse_path("src/([^/]+)/").is_err());
+        assert!(parse_path("re:src/(").is_err());
+    }
+}
@@ -1,14 +1,37 @@
-use std::fs::{self, File};
-use std::io::{self, Read};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use log::*;
+use memmap2::Mmap;
+
+use crate::compression::{Compression, Decompress, MAX_MAGIC_LEN};
+use crate::constants::BOM;
 
 /// The string used to identify stdin.
 pub const STDIN_IDENTIFIER: &str = "-";
 
+/// Regular files at least this large are opened with a hint to the OS that
+/// they're read from start to end, so that it reads further ahead.
+const SEQUENTIAL_HINT_LEN: u64 = 64 << 20;
+
+/// How long to wait before reading again from an input that had nothing to
+/// read yet, like a non-blocking pipe.
+const WOULD_BLOCK_WAIT: Duration = Duration::from_millis(10);
+
+/// Windows' flag for files that are read sequentially.
+#[cfg(windows)]
+const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
 /// Choose between a regular file and stdin.
 pub enum Input {
     File(fs::File),
     Stdin(io::Stdin),
+
+    /// A file or stdin behind another reader, e.g., a decompressor.
+    Reader(Box<dyn Read + Send>),
 }
 
 impl Input {
@@ -19,16 +42,314 @@ impl Input {
             return Ok(Input::Stdin(io::stdin()));
         }
 
-        let file = File::open(path)?;
-        Ok(Input::File(file))
+        Ok(Input::File(open_file(path)?))
+    }
+
+    /// Opens the input like [`Input::new`], and decompresses it on the fly if
+    /// it is compressed. The compression format is detected by its magic
+    /// bytes.
+    pub fn open<P: AsRef<Path>>(path: P, decompress: Decompress) -> io::Result<Input> {
+        Input::new(path)?.decompressed(decompress)
+    }
+
+    /// Makes an input of what's read from `reader`, decompressing it like
+    /// [`Input::open`] if it's compressed.
+    #[cfg(feature = "async-io")]
+    pub fn from_reader<R>(reader: R, decompress: Decompress) -> io::Result<Input>
+    where
+        R: Read + Send + 'static,
+    {
+        Input::Reader(Box::new(reader)).decompressed(decompress)
+    }
+
+    /// Decompresses the input on the fly, as given by `decompress`.
+    fn decompressed(self, decompress: Decompress) -> io::Result<Input> {
+        if decompress == Decompress::Off {
+            return Ok(self);
+        }
+
+        let mut reader = BufReader::new(self);
+
+        match (Compression::detect(reader.fill_buf()?), decompress) {
+            (Some(compression), _) => Ok(Input::Reader(compression.decoder(reader)?)),
+            (None, Decompress::Force) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not compressed with a supported format",
+            )),
+            (None, _) => Ok(Input::Reader(Box::new(reader))),
+        }
+    }
+}
+
+/// Opens the file at the given path for reading. Large regular files are
+/// opened with a hint that they're read sequentially, which makes cold reads
+/// of them faster.
+fn open_file(path: &Path) -> io::Result<File> {
+    let is_large = fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.len() >= SEQUENTIAL_HINT_LEN)
+        .unwrap_or(false);
+
+    let mut options = OpenOptions::new();
+    options.read(true);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        if is_large {
+            options.custom_flags(FILE_FLAG_SEQUENTIAL_SCAN);
+        }
+    }
+
+    let file = options.open(path)?;
+
+    if is_large {
+        if let Err(e) = advise_sequential(&file) {
+            debug!(
+                "{}: couldn't advise sequential reads: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    Ok(file)
+}
+
+/// Tells the OS that the given file is read from start to end.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise_sequential(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor is open for as long as `file` is, and the advice
+    // doesn't change what's read from it.
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Tells the OS that the given file is read from start to end.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn advise_sequential(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor is open for as long as `file` is, and read-ahead
+    // doesn't change what's read from it.
+    match unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDAHEAD, 1) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Tells the OS that the given file is read from start to end. Elsewhere, this
+/// is done when it's opened, if it can be done at all.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+fn advise_sequential(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Opens the file at the given path if it's a regular file that
+/// [`Input::open`] would read as it is, i.e., without decompressing it.
+/// Returns `None` if the file can't be read, so that the error is reported
+/// when it's opened.
+///
+/// Other kinds of files, like FIFOs and character devices, aren't opened at
+/// all, since opening a FIFO waits for a writer, and closing it again would
+/// throw away what the writer had written.
+fn open_plain_file(path: &Path, decompress: Decompress) -> Option<(File, Metadata)> {
+    if path.as_os_str() == STDIN_IDENTIFIER || decompress == Decompress::Force {
+        return None;
+    }
+
+    if !fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+
+    let mut file = open_file(path).ok()?;
+    let metadata = file.metadata().ok()?;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    if decompress == Decompress::Auto {
+        let mut header = Vec::with_capacity(MAX_MAGIC_LEN);
+        (&mut file)
+            .take(MAX_MAGIC_LEN as u64)
+            .read_to_end(&mut header)
+            .ok()?;
+
+        if Compression::detect(&header).is_some() {
+            return None;
+        }
+    }
+
+    Some((file, metadata))
+}
+
+/// Gets the size of the file at the given path, if it's a regular file that
+/// [`Input::open`] would read as it is, i.e., without decompressing it.
+pub fn plain_file_size<P: AsRef<Path>>(path: P, decompress: Decompress) -> Option<u64> {
+    open_plain_file(path.as_ref(), decompress).map(|(_, metadata)| metadata.len())
+}
+
+/// Maps the file at the given path into memory, if it's a regular file that
+/// [`Input::open`] would read as it is, i.e., without decompressing it.
+///
+/// If the file is truncated while it's mapped, reading the missing part of the
+/// map crashes the process, so this is only used when it's asked for.
+pub fn map_plain_file<P: AsRef<Path>>(path: P, decompress: Decompress) -> Option<Mmap> {
+    let (file, _) = open_plain_file(path.as_ref(), decompress)?;
+
+    // SAFETY: the map is only read, and it's up to the user not to truncate
+    // the file while it's counted.
+    let map = unsafe { Mmap::map(&file) }.ok()?;
+
+    #[cfg(unix)]
+    {
+        if map.len() as u64 >= SEQUENTIAL_HINT_LEN {
+            if let Err(e) = map.advise(memmap2::Advice::Sequential) {
+                debug!(
+                    "{}: couldn't advise sequential reads: {}",
+                    path.as_ref().display(),
+                    e
+                );
+            }
+        }
     }
+
+    Some(map)
 }
 
 impl Read for Input {
+    /// Reads from the input, waiting for it if it has nothing to read yet,
+    /// like a FIFO or stdin that was left non-blocking by the program writing
+    /// to it, instead of failing. Reads that are interrupted are tried again.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match *self {
-            Input::File(ref mut file) => file.read(buf),
-            Input::Stdin(ref mut stdin) => stdin.read(buf),
+        loop {
+            let read = match *self {
+                Input::File(ref mut file) => file.read(buf),
+                Input::Stdin(ref mut stdin) => stdin.read(buf),
+                Input::Reader(ref mut reader) => reader.read(buf),
+            };
+
+            match read {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(WOULD_BLOCK_WAIT)
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                read => return read,
+            }
         }
     }
 }
+
+/// Skips the UTF-8 byte order mark at the start of the reader, if there is one.
+/// Returns whether one was skipped.
+pub fn skip_bom<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    let has_bom = reader.fill_buf()?.starts_with(BOM.as_bytes());
+
+    if has_bom {
+        reader.consume(BOM.len());
+    }
+
+    Ok(has_bom)
+}
+
+/// Reads a list of file names from the file at the given path, separated by the
+/// given delimiter byte. Empty names are skipped. "-" reads the list from stdin.
+pub fn read_file_list<P: AsRef<Path>>(path: P, delimiter: u8) -> io::Result<Vec<String>> {
+    let reader = BufReader::new(Input::new(path)?);
+    let mut names = Vec::new();
+
+    for name in reader.split(delimiter) {
+        let name = name?;
+
+        if name.is_empty() {
+            continue;
+        }
+
+        let name =
+            String::from_utf8(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A reader that has nothing to read every other time it's read from.
+    struct Blocking<'a> {
+        bytes: &'a [u8],
+        ready: bool,
+    }
+
+    impl<'a> Read for Blocking<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.ready = !self.ready;
+
+            if !self.ready {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            let len = buf.len().min(self.bytes.len()).min(2);
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_read_would_block() {
+        let _ = env_logger::try_init();
+
+        let reader = Blocking {
+            bytes: b"hello\nworld\n",
+            ready: true,
+        };
+
+        let mut read = String::new();
+        Input::Reader(Box::new(reader))
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!("hello\nworld\n", read);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo() {
+        use std::process::Command;
+        use std::sync::mpsc;
+
+        let _ = env_logger::try_init();
+
+        let path = std::env::temp_dir().join(format!("uwc-fifo-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let status = Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+
+        // opening the FIFO would wait for a writer that never comes
+        let (tx, rx) = mpsc::channel();
+        let fifo = path.clone();
+        thread::spawn(move || {
+            let size = plain_file_size(&fifo, Decompress::Auto);
+            let map = map_plain_file(&fifo, Decompress::Off);
+            tx.send((size, map.is_none())).unwrap();
+        });
+
+        let result = rx.recv_timeout(Duration::from_secs(5));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Ok((None, true)), result);
+    }
+}
@@ -0,0 +1,136 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::counter::Counted;
+
+/// Which counts the snapshots written with `--interval` have.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IntervalCounts {
+    /// Everything that's been counted since the input started.
+    #[default]
+    Cumulative,
+
+    /// Only what's been counted since the last snapshot.
+    Delta,
+}
+
+impl FromStr for IntervalCounts {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IntervalCounts, String> {
+        match s {
+            "cumulative" => Ok(IntervalCounts::Cumulative),
+            "delta" => Ok(IntervalCounts::Delta),
+            _ => Err(format!("Unknown interval counts: {}", s)),
+        }
+    }
+}
+
+/// Gets when the next snapshot is due after `now`, for snapshots taken every
+/// `every` since `start`. Snapshots that were missed, since counting took
+/// longer than the interval, aren't caught up on.
+pub fn next_tick(start: Instant, every: Duration, now: Instant) -> Instant {
+    let ticks = now.saturating_duration_since(start).as_nanos() / every.as_nanos().max(1);
+    start + every * (ticks as u32 + 1)
+}
+
+/// Takes snapshots of an input's counts every interval, with `--interval`.
+pub struct Snapshots {
+    start: Instant,
+    every: Duration,
+    counts: IntervalCounts,
+
+    /// When the next snapshot is due.
+    next: Instant,
+
+    /// The counts at the last snapshot, for `--interval-counts delta`.
+    last: Counted,
+}
+
+impl Snapshots {
+    pub fn new(start: Instant, every: Duration, counts: IntervalCounts) -> Snapshots {
+        Snapshots {
+            start,
+            every,
+            counts,
+            next: start + every,
+            last: Counted::new(),
+        }
+    }
+
+    /// Takes a snapshot of the given counts if one is due, giving its counts,
+    /// and the time since the start it's for, like `10s` or `2.5s`.
+    pub fn take(&mut self, counts: &Counted) -> Option<(String, Counted)> {
+        let now = Instant::now();
+
+        if now < self.next {
+            return None;
+        }
+
+        let elapsed = self.next - self.start;
+        self.next = next_tick(self.start, self.every, now);
+
+        let snapshot = match self.counts {
+            IntervalCounts::Cumulative => counts.clone(),
+            IntervalCounts::Delta => counts
+                .iter()
+                .map(|(c, n)| (*c, n - self.last.get(c).copied().unwrap_or(0)))
+                .collect(),
+        };
+
+        self.last = counts.clone();
+
+        Some((format_elapsed(elapsed), snapshot))
+    }
+}
+
+/// Formats the time since the start of the input, in seconds, like `10s` or
+/// `2.5s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let millis = elapsed.as_millis() as f64;
+    format!("{}s", millis / 1000.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::Counter;
+
+    #[test]
+    fn test_snapshots() {
+        let _ = env_logger::try_init();
+
+        let every = Duration::from_millis(500);
+        let start = Instant::now() - Duration::from_millis(1200);
+
+        assert_eq!(
+            start + Duration::from_millis(1500),
+            next_tick(start, every, start + Duration::from_millis(1200))
+        );
+        assert_eq!(
+            start + Duration::from_millis(1000),
+            next_tick(start, every, start + Duration::from_millis(500))
+        );
+
+        let counts = |n: usize| -> Counted { vec![(Counter::Line, n)].into_iter().collect() };
+
+        let mut snapshots = Snapshots::new(start, every, IntervalCounts::Delta);
+        assert_eq!(
+            Some(("0.5s".to_string(), counts(3))),
+            snapshots.take(&counts(3))
+        );
+        assert_eq!(None, snapshots.take(&counts(5)));
+
+        snapshots.next = start + Duration::from_millis(1000);
+        assert_eq!(
+            Some(("1s".to_string(), counts(4))),
+            snapshots.take(&counts(7))
+        );
+
+        let mut snapshots = Snapshots::new(start, every, IntervalCounts::Cumulative);
+        assert_eq!(
+            Some(("0.5s".to_string(), counts(3))),
+            snapshots.take(&counts(3))
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+/// The path of a field in a JSON value, given like `.message` or
+/// `.error.details[0]` with `--json-field`. `.` is the whole value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    path: String,
+
+    /// The path as a JSON pointer, like `/error/details/0`.
+    pointer: String,
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<JsonPath, String> {
+        let err = || format!("Expected a path like .message or .error.details[0]: {}", s);
+
+        let mut rest = s.strip_prefix('.').ok_or_else(err)?;
+        let mut pointer = String::new();
+
+        while !rest.is_empty() {
+            let segment = match rest.strip_prefix('[') {
+                Some(index) => {
+                    let (index, after) = index.split_once(']').ok_or_else(err)?;
+                    index.parse::<usize>().map_err(|_| err())?;
+                    rest = match after.strip_prefix('.') {
+                        Some("") => return Err(err()),
+                        Some(after) => after,
+                        None if after.is_empty() || after.starts_with('[') => after,
+                        None => return Err(err()),
+                    };
+                    index
+                }
+                None => {
+                    let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                    let (key, after) = rest.split_at(end);
+
+                    if key.is_empty() {
+                        return Err(err());
+                    }
+
+                    rest = match after.strip_prefix('.') {
+                        Some("") => return Err(err()),
+                        Some(after) => after,
+                        None => after,
+                    };
+                    key
+                }
+            };
+
+            pointer.push('/');
+            pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+        }
+
+        Ok(JsonPath {
+            path: s.to_string(),
+            pointer,
+        })
+    }
+}
+
+impl JsonPath {
+    /// Gets the text of the field in a line of JSON. Strings are their text,
+    /// and other values are their JSON, but if the line isn't JSON or doesn't
+    /// have the field, there's no text.
+    fn extract(&self, line: &[u8]) -> String {
+        let value: Value = match serde_json::from_slice(line) {
+            Ok(value) => value,
+            Err(_) => return String::new(),
+        };
+
+        match value.pointer(&self.pointer) {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        }
+    }
+}
+
+/// Reads only a field of every line of newline-delimited JSON, like structured
+/// logs. Line breaks in the field are read as spaces, so that every line of
+/// the input is still one line.
+struct JsonFieldReader<R> {
+    reader: BufReader<R>,
+    path: JsonPath,
+
+    /// The field of the line that's being read.
+    line: Vec<u8>,
+
+    /// How much of `line` has been read.
+    pos: usize,
+}
+
+impl<R: Read> Read for JsonFieldReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            let mut line = Vec::new();
+            self.pos = 0;
+
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                self.line.clear();
+                return Ok(0);
+            }
+
+            let text_len = match line.strip_suffix(b"\r\n") {
+                Some(text) => text.len(),
+                None => line.strip_suffix(b"\n").unwrap_or(&line).len(),
+            };
+
+            let field = self.path.extract(&line[..text_len]);
+            self.line = field.replace(['\r', '\n'], " ").into_bytes();
+            self.line.extend_from_slice(&line[text_len..]);
+        }
+
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// Wraps the reader so that it reads only the field at `path` of every line of
+/// JSON.
+pub fn extract<'a, R: Read + 'a>(reader: R, path: JsonPath) -> Box<dyn Read + 'a> {
+    Box::new(JsonFieldReader {
+        reader: BufReader::new(reader),
+        path,
+        line: Vec::new(),
+        pos: 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let _ = env_logger::try_init();
+
+        let pointer = |s: &str| s.parse::<JsonPath>().unwrap().pointer;
+
+        assert_eq!("", pointer("."));
+        assert_eq!("/message", pointer(".message"));
+        assert_eq!("/error/details/0/text", pointer(".error.details[0].text"));
+        assert_eq!("/0/1", pointer(".[0][1]"));
+        assert_eq!("/a~1b~0", pointer(".a/b~"));
+
+        for s in &[
+            "", "message", ".a.", ".a..b", ".a[x]", ".a[0", ".a[0]b", ".a[0].",
+        ] {
+            assert!(s.parse::<JsonPath>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        let _ = env_logger::try_init();
+
+        let ndjson = "{\"message\": \"hello, world\", \"level\": \"info\"}\r\n\
+                      {\"message\": \"two\\nlines\"}\n\
+                      {\"level\": \"debug\"}\n\
+                      not json\n\
+                      {\"message\": 42}";
+
+        let mut extracted = String::new();
+        extract(ndjson.as_bytes(), ".message".parse().unwrap())
+            .read_to_string(&mut extracted)
+            .unwrap();
+
+        assert_eq!("hello, world\r\ntwo lines\n\n\n42", extracted);
+    }
+}
@@ -0,0 +1,81 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::counter::{Count, CountOptions};
+
+/// The cases of letters that can be counted with `--uppercase`,
+/// `--lowercase`, and `--titlecase`, by their Unicode general category.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum LetterCase {
+    /// Uppercase letters, like `A` or `Ω` (Lu).
+    Upper,
+
+    /// Lowercase letters, like `a` or `ß` (Ll).
+    Lower,
+
+    /// Titlecase letters, like `ǅ`, which are digraphs with only their first
+    /// letter uppercase (Lt).
+    Title,
+}
+
+lazy_static! {
+    static ref UPPER: Regex = Regex::new(r"\p{Lu}").unwrap();
+    static ref LOWER: Regex = Regex::new(r"\p{Ll}").unwrap();
+    static ref TITLE: Regex = Regex::new(r"\p{Lt}").unwrap();
+}
+
+impl LetterCase {
+    /// The name of the case's counter, as shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            LetterCase::Upper => "uppercase",
+            LetterCase::Lower => "lowercase",
+            LetterCase::Title => "titlecase",
+        }
+    }
+
+    fn pattern(self) -> &'static Regex {
+        match self {
+            LetterCase::Upper => &UPPER,
+            LetterCase::Lower => &LOWER,
+            LetterCase::Title => &TITLE,
+        }
+    }
+}
+
+impl Count for LetterCase {
+    fn count(&self, s: &str, _options: &CountOptions) -> usize {
+        // ASCII text can only have ASCII letters
+        if s.is_ascii() {
+            let bytes = s.bytes();
+
+            return match self {
+                LetterCase::Upper => bytes.filter(u8::is_ascii_uppercase).count(),
+                LetterCase::Lower => bytes.filter(u8::is_ascii_lowercase).count(),
+                LetterCase::Title => 0,
+            };
+        }
+
+        self.pattern().find_iter(s).count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let options = CountOptions::default();
+        let counts = |s: &str| {
+            [LetterCase::Upper, LetterCase::Lower, LetterCase::Title]
+                .map(|case| case.count(s, &options))
+        };
+
+        assert_eq!([4, 7, 0], counts("STOP yelling, 42!"));
+        assert_eq!([5, 6, 1], counts("ΣΟΦΊΑ straße ǅ"));
+        assert_eq!([0, 0, 0], counts("東京 ２０２４ ⓐ"));
+    }
+}
@@ -0,0 +1,26 @@
+//! Counts things in Unicode text, like lines, words, grapheme clusters, and
+//! code points, the way the `uwc` command does.
+//!
+//! ```
+//! use uwc::counter::{self, CountOptions, Counter};
+//!
+//! let counts = counter::count(&[Counter::Words], "Μπορῶ νὰ φάω", &CountOptions::default());
+//! assert_eq!(3, counts[&Counter::Words]);
+//! ```
+
+mod ascii;
+pub mod code_points;
+pub mod constants;
+pub mod counter;
+pub mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+pub mod letter_case;
+pub mod links;
+pub mod locale;
+pub mod newline;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub mod width;
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use failure::Error;
+use rayon::prelude::*;
+
+use crate::input::Input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+
+/// Describes the line endings of an input, by the names of the newline
+/// sequences and how many of each there are, most first. Form feeds aren't
+/// line endings, so they're left out.
+fn describe(name: &str, counts: BTreeMap<&str, usize>) -> (String, bool) {
+    let mut counts: Vec<_> = counts
+        .into_iter()
+        .filter(|(name, _)| *name != "ff")
+        .collect();
+    counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let endings = counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match counts.len() {
+        0 => (format!("{}: no line endings", name), true),
+        1 => (format!("{}: {}", name, endings), true),
+        _ => (format!("{}: mixed line endings: {}", name, endings), false),
+    }
+}
+
+/// Checks whether the given inputs each use a single newline convention with
+/// `--check-line-endings`, instead of counting them. Only the newlines
+/// selected with `--newlines` are looked for. For every input, the line
+/// endings it has are written to stdout, in the order the inputs were given.
+/// Returns whether the inputs could all be read, and whether none of them had
+/// mixed line endings.
+pub fn check(file_names: &[String], opts: &Opt) -> Result<(bool, bool), Error> {
+    let results: Vec<_> = file_names
+        .par_iter()
+        .map(|name| {
+            let input = Input::open(name, opts.decompress)?;
+            opts.newlines.count_raw_by_name(input)
+        })
+        .collect();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut success = true;
+    let mut consistent = true;
+
+    for (name, result) in file_names.iter().zip(results) {
+        match result {
+            Ok(counts) => {
+                let (description, input_consistent) = describe(name, counts);
+                writeln!(stdout, "{}", description)?;
+                consistent &= input_consistent;
+            }
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                success = false;
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok((success, consistent))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let _ = env_logger::try_init();
+
+        let counts = |counts: &[(&'static str, usize)]| counts.iter().copied().collect();
+
+        assert_eq!(
+            ("a.txt: no line endings".to_string(), true),
+            describe("a.txt", counts(&[("ff", 2)]))
+        );
+        assert_eq!(
+            ("a.txt: 3 lf".to_string(), true),
+            describe("a.txt", counts(&[("lf", 3), ("ff", 1)]))
+        );
+        assert_eq!(
+            (
+                "a.txt: mixed line endings: 10 crlf, 2 lf".to_string(),
+                false
+            ),
+            describe("a.txt", counts(&[("lf", 2), ("crlf", 10)]))
+        );
+    }
+}
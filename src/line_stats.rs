@@ -0,0 +1,156 @@
+use std::io::{self, BufReader, Write};
+
+use failure::Error;
+use rayon::prelude::*;
+use tabwriter::TabWriter;
+
+use crate::counter::{self, Counter};
+use crate::input::Input;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+use crate::ubufreader::{InvalidUtf8, UStrChunksIter};
+
+/// The percentiles of the line lengths that are given, by name.
+const PERCENTILES: [(&str, f64); 3] = [("median", 50.0), ("p90", 90.0), ("p99", 99.0)];
+
+/// Gets the percentile of the sorted lengths by the nearest rank, so that it's
+/// always the length of one of the lines, or `None` if there are none.
+fn percentile(sorted: &[usize], percent: f64) -> Option<usize> {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+/// Measures the length of every line read from `input` in `unit`, without
+/// its line break. With `--line-range`, only the lines in the range are
+/// measured. The lines that can't be read are reported and left out, and
+/// with `--invalid-utf8 skip-file`, no lengths are returned for inputs that
+/// aren't valid UTF-8. Returns whether the input was read without errors.
+fn line_lengths(name: &str, input: Input, unit: Counter, opts: &Opt) -> (bool, Option<Vec<usize>>) {
+    let mut reader = BufReader::new(input);
+    let count_options = opts.get_count_options();
+    let max_len = Some(opts.split_len).filter(|len| *len > 0);
+
+    let (lines_before, num_lines) = match opts.line_range {
+        Some(range) => (range.lines_before(), range.num_lines()),
+        None => (0, None),
+    };
+
+    let pieces = UStrChunksIter::new(&mut reader, opts.record_delimiter(), false)
+        .skip_records(lines_before)
+        .max_records(num_lines)
+        .invalid_utf8(opts.invalid_utf8)
+        .max_len(max_len);
+
+    let mut success = true;
+    let mut lengths = Vec::new();
+    let mut length = 0;
+
+    for piece in pieces {
+        let line_no = lines_before + lengths.len() + 1;
+
+        let piece = match piece {
+            Ok(piece) => piece,
+            Err(e) => {
+                let e = e.with_file(name).with_line(line_no);
+
+                if e.is_utf8() && opts.invalid_utf8 == InvalidUtf8::SkipFile {
+                    report::input_error(opts, InputError::read(&e).skipping());
+                    return (false, None);
+                }
+
+                report::input_error(opts, InputError::read(&e));
+                success = false;
+                length = 0;
+                continue;
+            }
+        };
+
+        length += counter::count_array(&[unit], &piece.text, &count_options)[0];
+
+        if piece.ends_record {
+            lengths.push(length);
+            length = 0;
+        }
+    }
+
+    (success, Some(lengths))
+}
+
+/// Writes the median, 90th and 99th percentiles, and longest of the lengths of
+/// the lines of every given input with `--line-stats`, instead of counting
+/// them. The lengths are in the unit given with `--line-stats-unit`. Returns
+/// whether the inputs could all be read.
+pub fn report(file_names: &[String], opts: &Opt) -> Result<bool, Error> {
+    let unit = opts.line_stats_unit;
+
+    let results: Vec<_> = file_names
+        .par_iter()
+        .map(|name| match Input::open(name, opts.decompress) {
+            Ok(input) => line_lengths(name, input, unit, opts),
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                (false, None)
+            }
+        })
+        .collect();
+
+    let stdout = io::stdout();
+    let mut writer: Box<dyn Write> = if opts.no_elastic {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(TabWriter::new(stdout.lock()))
+    };
+
+    if !opts.no_header {
+        let names: Vec<_> = PERCENTILES.iter().map(|(name, _)| *name).collect();
+        writeln!(writer, "{}\tmax\tfilename", names.join("\t"))?;
+    }
+
+    let mut success = true;
+
+    for (name, (input_success, lengths)) in file_names.iter().zip(results) {
+        success &= input_success;
+
+        let mut lengths = match lengths {
+            Some(lengths) => lengths,
+            None => continue,
+        };
+
+        lengths.sort_unstable();
+
+        let stats = PERCENTILES
+            .iter()
+            .map(|(_, percent)| percentile(&lengths, *percent))
+            .chain(Some(lengths.last().copied()))
+            .map(|stat| stat.map_or_else(|| "-".to_string(), |stat| stat.to_string()));
+
+        for stat in stats {
+            write!(writer, "{}\t", stat)?;
+        }
+
+        writeln!(writer, "{}", name)?;
+    }
+
+    writer.flush()?;
+
+    Ok(success)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let _ = env_logger::try_init();
+
+        let lengths: Vec<usize> = (1..=200).collect();
+        assert_eq!(Some(100), percentile(&lengths, 50.0));
+        assert_eq!(Some(180), percentile(&lengths, 90.0));
+        assert_eq!(Some(198), percentile(&lengths, 99.0));
+
+        assert_eq!(Some(7), percentile(&[7], 50.0));
+        assert_eq!(Some(2), percentile(&[1, 2, 3], 50.0));
+        assert_eq!(None, percentile(&[], 99.0));
+    }
+}
@@ -0,0 +1,94 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::counter::{Count, CountOptions};
+
+/// The kinds of links that can be counted with `--urls`, `--emails`,
+/// `--hashtags`, and `--mentions`.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Link {
+    Url,
+    Email,
+    Hashtag,
+    Mention,
+}
+
+lazy_static! {
+    /// URLs with a scheme, like `https://example.com/path`, or that start with
+    /// `www.`. Anything up to the next space or quote is part of the URL, so
+    /// internationalized domain names and paths are matched whole.
+    static ref URL: Regex =
+        Regex::new(r#"(?i)\b(?:(?:https?|ftp)://|www\.)[^\s<>"']+"#).unwrap();
+
+    /// Email addresses. The domain can be internationalized, either in its own
+    /// script, like `例え.jp`, or in punycode, like `xn--r8jz45g.jp`.
+    static ref EMAIL: Regex = Regex::new(
+        r"[\p{L}\p{M}\p{N}._%+-]+@(?:[\p{L}\p{M}\p{N}-]+\.)+(?:[\p{L}\p{M}]{2,}|xn--[a-z0-9-]+)\b"
+    )
+    .unwrap();
+
+    /// Hashtags, like `#rust` or `#東京`. They have to have a letter in them,
+    /// so that `#1` isn't one, and they can't go on from a word, like the
+    /// fragment of `page#section`.
+    static ref HASHTAG: Regex = Regex::new(
+        r"(?:^|[^\p{L}\p{M}\p{N}_&#/])#[\p{L}\p{M}\p{N}_]*[\p{L}\p{M}][\p{L}\p{M}\p{N}_]*"
+    )
+    .unwrap();
+
+    /// Mentions, like `@jane` or `@ユーザー`. They can't go on from a word, so
+    /// that email addresses aren't mentions.
+    static ref MENTION: Regex =
+        Regex::new(r"(?:^|[^\p{L}\p{M}\p{N}_.@+-])@[\p{L}\p{M}\p{N}_]+").unwrap();
+}
+
+impl Link {
+    /// The name of the link's counter, as shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Link::Url => "urls",
+            Link::Email => "emails",
+            Link::Hashtag => "hashtags",
+            Link::Mention => "mentions",
+        }
+    }
+
+    fn pattern(self) -> &'static Regex {
+        match self {
+            Link::Url => &URL,
+            Link::Email => &EMAIL,
+            Link::Hashtag => &HASHTAG,
+            Link::Mention => &MENTION,
+        }
+    }
+}
+
+impl Count for Link {
+    fn count(&self, s: &str, _options: &CountOptions) -> usize {
+        self.pattern().find_iter(s).count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let options = CountOptions::default();
+        let text = "See https://example.com/a?b=c, www.例え.jp, and \
+                    <HTTP://xn--r8jz45g.jp/パス>. Not awww.no or ftp:/x. \
+                    Mail jane.doe+uwc@example.co.uk, 山田@例え.jp, or \
+                    x@xn--r8jz45g.jp, but not @handle or a@b.";
+
+        assert_eq!(3, Link::Url.count(text, &options));
+        assert_eq!(3, Link::Email.count(text, &options));
+
+        let text = "#rust is fun @jane! #東京 #2024 page#section @ユーザー \
+                    jane@example.com @user@mastodon.social #a#b &#39; #";
+
+        assert_eq!(3, Link::Hashtag.count(text, &options));
+        assert_eq!(3, Link::Mention.count(text, &options));
+    }
+}
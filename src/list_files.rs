@@ -0,0 +1,66 @@
+use std::fs;
+use std::io::{self, Write};
+
+use failure::Error;
+use log::*;
+use rayon::prelude::*;
+
+use crate::binary::{self, BinaryPolicy};
+use crate::input::{Input, STDIN_IDENTIFIER};
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+
+/// Determines if the input with the given name would be counted, i.e., if it
+/// exists, unless it looks binary and `--binary skip` is given. Stdin is
+/// always counted, since it can't be looked at without reading it, and so are
+/// the inputs that aren't regular files, like FIFOs.
+fn is_counted(name: &str, opts: &Opt) -> io::Result<bool> {
+    if name == STDIN_IDENTIFIER {
+        return Ok(true);
+    }
+
+    let is_file = fs::metadata(name)?.is_file();
+
+    if opts.binary != BinaryPolicy::Skip || !is_file {
+        return Ok(true);
+    }
+
+    let (is_binary, _) = binary::sniff(Input::open(name, opts.decompress)?)?;
+
+    if is_binary {
+        info!("skipping binary input: {}", name);
+    }
+
+    Ok(!is_binary)
+}
+
+/// Writes the names of the inputs that would be counted with `--list-files`,
+/// one per line, without counting them. These are the files found after
+/// walking the directories, less the ones that are skipped because they look
+/// binary. The inputs that can't be read are reported and left out. Returns
+/// whether the inputs could all be read.
+pub fn list(file_names: &[String], opts: &Opt) -> Result<bool, Error> {
+    let counted: Vec<_> = file_names
+        .par_iter()
+        .map(|name| is_counted(name, opts))
+        .collect();
+
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    let mut success = true;
+
+    for (name, counted) in file_names.iter().zip(counted) {
+        match counted {
+            Ok(true) => writeln!(writer, "{}", name)?,
+            Ok(false) => {}
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                success = false;
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(success)
+}
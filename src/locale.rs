@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+/// Apostrophes that can mark an elision.
+const APOSTROPHES: &[char] = &['\'', '\u{2019}'];
+
+/// Elided French words that are written attached to the following word, e.g.
+/// "l'homme" or "jusqu'ici".
+const FRENCH_ELISIONS: &[&str] = &[
+    "c", "d", "j", "l", "m", "n", "s", "t", "qu", "jusqu", "lorsqu", "puisqu", "quoiqu",
+];
+
+/// Elided Italian words, e.g. "l'anno" or "dell'acqua".
+const ITALIAN_ELISIONS: &[&str] = &[
+    "c", "d", "l", "un", "all", "dall", "dell", "nell", "sull", "quell", "quest",
+];
+
+/// Elided Catalan words, e.g. "l'home" or "d'aigua".
+const CATALAN_ELISIONS: &[&str] = &["d", "l", "m", "n", "s", "t"];
+
+/// A language to tailor segmentation to.
+///
+/// The Unicode segmentation rules are language-agnostic, so some languages
+/// need extra rules to count things the way their speakers would. As of now,
+/// this only affects words: elided articles and prepositions in French,
+/// Italian, and Catalan (like the "l'" in "l'homme") are counted as words of
+/// their own. No locale tailors grapheme clusters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// The lowercased ISO 639 language code.
+    language: String,
+}
+
+impl Locale {
+    /// The elided words of this locale's language, if it has any.
+    fn elisions(&self) -> &'static [&'static str] {
+        match self.language.as_str() {
+            "fr" => FRENCH_ELISIONS,
+            "it" => ITALIAN_ELISIONS,
+            "ca" => CATALAN_ELISIONS,
+            _ => &[],
+        }
+    }
+
+    /// Counts how many words the given word segment is in this locale. This
+    /// is usually 1, but elisions are split off into their own words.
+    pub fn count_words_in(&self, word: &str) -> usize {
+        let elisions = self.elisions();
+
+        if elisions.is_empty() {
+            return 1;
+        }
+
+        let mut count = 1;
+        let mut rest = word;
+
+        while let Some(idx) = rest.find(APOSTROPHES) {
+            let (prefix, tail) = rest.split_at(idx);
+            let apostrophe_len = tail.chars().next().map_or(0, char::len_utf8);
+            let tail = &tail[apostrophe_len..];
+
+            if tail.is_empty() || !elisions.contains(&prefix.to_lowercase().as_str()) {
+                break;
+            }
+
+            count += 1;
+            rest = tail;
+        }
+
+        count
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Parses a BCP 47 language tag, like "fr" or "fr-CA", or a POSIX locale
+    /// name, like "fr_CA.UTF-8". Only the language is used.
+    fn from_str(s: &str) -> Result<Locale, String> {
+        let language = s.split(['-', '_', '.', '@']).next().unwrap_or_default();
+
+        let valid =
+            (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic());
+
+        if !valid {
+            return Err(format!("Invalid locale: {}", s));
+        }
+
+        Ok(Locale {
+            language: language.to_ascii_lowercase(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn locale(language: &str) -> Locale {
+        Locale {
+            language: language.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(locale("fr")), "fr".parse());
+        assert_eq!(Ok(locale("fr")), "FR-ca".parse());
+        assert_eq!(Ok(locale("it")), "it_IT.UTF-8".parse());
+        assert!("".parse::<Locale>().is_err());
+        assert!("french".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn test_count_words_in_elisions() {
+        let fr: Locale = "fr".parse().unwrap();
+        assert_eq!(1, fr.count_words_in("homme"));
+        assert_eq!(2, fr.count_words_in("l'homme"));
+        assert_eq!(2, fr.count_words_in("L\u{2019}homme"));
+        assert_eq!(2, fr.count_words_in("jusqu'aujourd'hui"));
+
+        let en: Locale = "en".parse().unwrap();
+        assert_eq!(1, en.count_words_in("don't"));
+        assert_eq!(1, en.count_words_in("l'homme"));
+    }
+}
@@ -1,71 +1,282 @@
 #[macro_use]
 extern crate structopt_derive;
 
-mod constants;
-mod counter;
-mod error;
+mod archive;
+#[cfg(feature = "async-io")]
+mod async_io;
+mod baseline;
+mod binary;
+mod byte_range;
+mod cache;
+mod check_utf8;
+mod comments;
+mod compression;
+mod concat;
+mod config;
+mod encoding;
+mod fields;
+mod filter;
+mod follow;
+mod group;
 mod input;
+mod interval;
+mod json_field;
+mod line_endings;
+mod line_stats;
+mod list_files;
+mod markup;
+mod open_files;
 mod opt;
+mod ordered;
+mod output_dir;
+mod progress;
+mod ranges;
+mod report;
+mod status_file;
+mod summary;
+mod threshold;
+mod timings;
 mod ubufreader;
+mod unicode_version;
+mod walk;
+mod watch;
 
 use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
 use std::fmt::Display;
-use std::io::{self, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
 use std::iter::IntoIterator;
-use std::sync::{Arc, Mutex};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
-use failure::Error;
+use failure::{format_err, Error};
 use itertools::Itertools;
 use log::*;
+use memmap2::Mmap;
 use rayon::prelude::*;
-use structopt::StructOpt;
 use tabwriter::TabWriter;
+use uwc::{code_points, constants, counter, error, letter_case, links, locale, newline, width};
 
-use crate::counter::{Counted, Counter};
+use crate::binary::{BinaryPolicy, Decoding};
+use crate::cache::Cache;
+use crate::comments::LineClassifier;
+use crate::config::Config;
+use crate::counter::{CountArray, Counted, Counter, MAX_COUNTERS};
 use crate::input::Input;
+use crate::interval::Snapshots;
 use crate::opt::{CountMode, Opt};
-use crate::ubufreader::UStrChunksIter;
+use crate::ordered::OrderedOutput;
+use crate::report::InputError;
+use crate::summary::Summary;
+use crate::timings::Timings;
+use crate::ubufreader::{BufferPool, InvalidUtf8, Piece, UStrChunksIter};
 
-const TOTAL: &'static str = "total";
+const TOTAL: &str = "total";
+
+/// The name of the row of the average counts of the inputs, with `--mean`.
+const MEAN: &str = "mean";
+
+/// The names of the rows of the smallest and largest counts of the inputs,
+/// with `--min-max`.
+const MIN: &str = "min";
+const MAX: &str = "max";
+
+/// The most bytes of input to read before counting them, even if fewer lines
+/// than the chunk size have been read, so that memory use stays bounded.
+const MAX_CHUNK_LEN: usize = 16 << 20;
+
+/// The exit code when the run failed outright, e.g., because an option was
+/// invalid, or because writing to stdout failed.
+const EXIT_FATAL: i32 = 1;
+
+/// The exit code when some inputs couldn't be counted, unless `--lenient` is
+/// given.
+const EXIT_INPUTS_FAILED: i32 = 2;
+
+/// The exit code when a `--fail-if`, `--fail-if-total`, or `--max-delta`
+/// condition held, or an input failed `--check-utf8` or
+/// `--check-line-endings`.
+const EXIT_ASSERTION_FAILED: i32 = 3;
 
 fn main() {
-    env_logger::init();
+    init_logger();
 
     let run_result = run();
 
+    if let Err(ref error) = run_result {
+        eprintln!("Error: {}", error);
+    }
+
+    match exit_code(&run_result) {
+        0 => {}
+        code => std::process::exit(code),
+    }
+}
+
+/// Gets the exit code of a run from how it went.
+fn exit_code(run_result: &Result<Status, Error>) -> i32 {
     match run_result {
-        Err(error) => {
-            eprintln!("Error: {}", error);
-            std::process::exit(1);
+        Err(_) => EXIT_FATAL,
+        Ok(Status::InputsFailed) => EXIT_INPUTS_FAILED,
+        Ok(Status::AssertionFailed) => EXIT_ASSERTION_FAILED,
+        Ok(Status::Success) => 0,
+    }
+}
+
+/// Sets up the logger from RUST_LOG. If it isn't set, only uwc's own errors
+/// are logged, until `--verbose` raises the level.
+fn init_logger() {
+    if env::var_os("RUST_LOG").is_some() {
+        env_logger::init();
+        return;
+    }
+
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Error)
+        .filter_module("uwc", LevelFilter::Trace)
+        .init();
+
+    log::set_max_level(LevelFilter::Error);
+}
+
+/// Raises the log level for uwc's own messages with `--verbose`, unless
+/// RUST_LOG sets it.
+fn set_verbosity(opts: &Opt) {
+    let level = match opts.verbose {
+        _ if env::var_os("RUST_LOG").is_some() => return,
+        0 => return,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    log::set_max_level(level);
+}
+
+/// How a run went, if it didn't fail outright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Success,
+
+    /// Some inputs couldn't be counted.
+    InputsFailed,
+
+    /// A `--fail-if`, `--fail-if-total`, or `--max-delta` condition held, or
+    /// an input failed `--check-utf8` or `--check-line-endings`.
+    AssertionFailed,
+}
+
+impl Status {
+    /// Gets the status of a run from whether all the inputs were counted, and
+    /// whether none of the conditions to fail on held. Inputs that couldn't be
+    /// counted win over the conditions, since the counts they were checked
+    /// against are missing some, and with `--lenient`, they're ignored.
+    fn new(inputs_counted: bool, assertions_passed: bool, opts: &Opt) -> Status {
+        if !inputs_counted && !opts.lenient {
+            Status::InputsFailed
+        } else if !assertions_passed {
+            Status::AssertionFailed
+        } else {
+            Status::Success
         }
-        Ok(success) if success == false => std::process::exit(2),
-        _ => {}
     }
 }
 
 fn write_counts<W: Write>(
-    mut writer: W,
+    writer: W,
     counts: &BTreeMap<Counter, usize>,
     title: Option<&str>,
 ) -> Result<(), Error> {
+    write_selected_counts(writer, counts.keys(), counts, title)
+}
+
+/// Writes the counts of the given counters, like `write_counts`, with a `-`
+/// for the ones that weren't counted, like the ones that need the input to be
+/// decoded with `--bytes-only-decode off`.
+fn write_selected_counts<'a, W, I>(
+    mut writer: W,
+    counters: I,
+    counts: &BTreeMap<Counter, usize>,
+    title: Option<&str>,
+) -> Result<(), Error>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Counter>,
+{
     let mut out_str = String::new();
 
-    for count in counts.values() {
-        out_str.push_str(&count.to_string());
-        out_str.push_str("\t");
+    for counter in counters {
+        match counts.get(counter) {
+            Some(count) => out_str.push_str(&count.to_string()),
+            None => out_str.push('-'),
+        }
+
+        out_str.push('\t');
     }
 
     // remove the trailing tab
     out_str.pop();
 
     if let Some(name) = title {
-        out_str.push_str("\t");
+        out_str.push('\t');
         out_str.push_str(name);
     }
 
-    out_str.push_str("\n");
+    out_str.push('\n');
 
-    Ok(writer.write_all(&out_str.as_bytes())?)
+    Ok(writer.write_all(out_str.as_bytes())?)
+}
+
+/// Writes the average counts of the inputs, with one decimal place, under the
+/// name of the mean row.
+fn write_means<W: Write>(mut writer: W, means: &BTreeMap<Counter, f64>) -> Result<(), Error> {
+    let mut out_str = String::new();
+
+    for mean in means.values() {
+        out_str.push_str(&format!("{:.1}\t", mean));
+    }
+
+    out_str.push_str(MEAN);
+    out_str.push('\n');
+
+    Ok(writer.write_all(out_str.as_bytes())?)
+}
+
+/// Writes the smallest or the largest count of every counter over the given
+/// rows, each followed by the name of the row it's from, in parentheses. If
+/// several rows have it, the first one's name is given.
+fn write_extremes<W: Write>(
+    mut writer: W,
+    rows: &[(&str, &Counted)],
+    title: &str,
+    largest: bool,
+) -> Result<(), Error> {
+    let mut extremes: BTreeMap<Counter, (usize, &str)> = BTreeMap::new();
+
+    for (name, counts) in rows {
+        for (counter, count) in counts.iter() {
+            let extreme = extremes.entry(*counter).or_insert((*count, name));
+
+            if (largest && *count > extreme.0) || (!largest && *count < extreme.0) {
+                *extreme = (*count, name);
+            }
+        }
+    }
+
+    let mut out_str = String::new();
+
+    for (count, name) in extremes.values() {
+        out_str.push_str(&format!("{} ({})\t", count, name));
+    }
+
+    out_str.push_str(title);
+    out_str.push('\n');
+
+    Ok(writer.write_all(out_str.as_bytes())?)
 }
 
 /// Construct the "file name" to display for line mode.
@@ -73,8 +284,26 @@ fn file_name_with_line<D: Display>(fname: &str, thing: D) -> String {
     format!("{}:{}", fname, thing)
 }
 
-/// Write the header that displays counter names in columns.
-fn write_header<'a, W, I>(mut writer: W, counters: I) -> Result<(), Error>
+/// Construct the name of a row in line mode, after the line's byte offset
+/// with `--byte-offset`. Rows that aren't for a single line, like the totals,
+/// have no offset.
+fn line_row_name(name: &str, offset: Option<u64>, opts: &Opt) -> String {
+    match (opts.byte_offset, offset) {
+        (false, _) => name.to_string(),
+        (true, Some(offset)) => format!("{}\t{}", offset, name),
+        (true, None) => format!("-\t{}", name),
+    }
+}
+
+/// Write the header that displays counter names in columns, and the column
+/// of byte offsets if there is one, followed by the column of the rows' names,
+/// which are the lines themselves with `--annotate`.
+fn write_header<'a, W, I>(
+    mut writer: W,
+    counters: I,
+    byte_offset: bool,
+    annotate: bool,
+) -> Result<(), Error>
 where
     W: Write,
     I: IntoIterator<Item = &'a Counter>,
@@ -83,195 +312,1243 @@ where
 
     for counter in counters.into_iter() {
         out_str.push_str(&counter.to_string());
-        out_str.push_str("\t");
+        out_str.push('\t');
     }
 
-    out_str.push_str("filename\n");
+    if byte_offset {
+        out_str.push_str("offset\t");
+    }
 
-    Ok(writer.write_all(&out_str.as_bytes())?)
+    out_str.push_str(if annotate { "line\n" } else { "filename\n" });
+
+    Ok(writer.write_all(out_str.as_bytes())?)
 }
 
+/// Writes a whole table of counts to stdout: the header, a row for every input,
+/// and their total. This is for the modes that give the counts more than once,
+/// so every table ends with an empty line.
+fn write_table<'a, I>(rows: I, opts: &Opt) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (&'a str, &'a Counted)>,
+{
+    let stdout = io::stdout();
+    let mut writer: Box<dyn Write> = if opts.no_elastic {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(TabWriter::new(stdout.lock()))
+    };
+
+    let counters = opts.get_counters();
+
+    if !opts.no_header {
+        write_header(&mut writer, &counters, false, false)?;
+    }
+
+    let rows: Vec<_> = rows.into_iter().collect();
+
+    for (name, counts) in &rows {
+        write_selected_counts(&mut writer, &counters, counts, Some(name))?;
+    }
+
+    if rows.len() > 1 {
+        let totals = counter::sum_all_counts(rows.iter().map(|(_, counts)| *counts));
+        write_selected_counts(&mut writer, &counters, &totals, Some(TOTAL))?;
+    }
+
+    if opts.mean && !rows.is_empty() {
+        let means = counter::mean_counts(rows.iter().map(|(_, counts)| *counts));
+        write_means(&mut writer, &means)?;
+    }
+
+    if opts.min_max && !rows.is_empty() {
+        write_extremes(&mut writer, &rows, MIN, false)?;
+        write_extremes(&mut writer, &rows, MAX, true)?;
+    }
+
+    writeln!(writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Determines if a character is a line break, which is left out of the lines
+/// written with `--annotate`.
+fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{b}' | '\u{c}' | '\u{85}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/// The length of the text in a piece that was read.
+fn piece_len(piece: &error::Result<Piece>) -> usize {
+    piece.as_ref().map_or(0, |piece| piece.text.len())
+}
+
+/// What's known about the record that's being counted, which can go on in the
+/// next chunk.
+struct RecordState {
+    /// The summed up counts of the record's pieces so far, along with the
+    /// record's offset in the input.
+    counts: Option<(u64, CountArray)>,
+
+    /// Classifies the records as code, comments, or blank, with
+    /// `--comment-prefix` and `--block-comment`.
+    classifier: Option<LineClassifier>,
+
+    /// The text of the record so far, with `--annotate`.
+    text: Option<String>,
+}
+
+/// Counts the pieces in a chunk, and returns the total counts of the records
+/// that end in it. The pieces of a record that's split into several are summed
+/// up in `record` until the record ends. In line mode, the counts of every
+/// record are written. The pieces' buffers are given back to the pool once
+/// they've been counted.
 fn count_chunks(
     file_name: &str,
-    chunk: Vec<error::Result<String>>,
+    chunk: Vec<error::Result<Piece>>,
     opts: &Opt,
     line_offset: usize,
+    record: &mut RecordState,
+    buffer_pool: &BufferPool,
     output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
 ) -> Result<(bool, Counted), Error> {
-    let counters = opts.get_counters();
+    let counters: Vec<Counter> = opts.get_counters().into_iter().collect();
+    let count_options = opts.get_count_options();
+
+    // number the pieces by the line they're in
+    let mut line_no = line_offset;
+    let chunk: Vec<_> = chunk
+        .into_iter()
+        .map(|piece| {
+            let piece_line_no = line_no;
+            line_no += ubufreader::ends_record(&piece) as usize;
+            (piece_line_no, piece)
+        })
+        .collect();
 
-    chunk
+    let piece_counts: Vec<_> = chunk
         .into_par_iter()
-        .enumerate()
-        .map(|(line_no, line)| {
-            let line_no = line_no + line_offset;
-            let line = match line {
-                Ok(l) => l,
+        .map(|(line_no, piece)| {
+            let piece = match piece {
+                Ok(piece) => piece,
                 Err(e) => {
-                    eprintln!("{}:{}: {}", file_name, line_no, e);
-                    return Ok((false, BTreeMap::new()));
+                    let e = e.with_file(file_name).with_line(line_no);
+                    report::input_error(opts, InputError::read(&e));
+                    return (line_no, None, true);
                 }
             };
 
-            debug!("line: {:?}", line);
+            debug!("piece: {:?}", piece.text);
+
+            let counts = match piece.split_context {
+                Some(ref before) => {
+                    counter::count_continued(&counters, before, &piece.text, &count_options)
+                }
+                None => counter::count_array(&counters, &piece.text, &count_options),
+            };
+
+            (
+                line_no,
+                Some((piece.offset, counts, piece.text)),
+                piece.ends_record,
+            )
+        })
+        .collect();
+
+    let mut success = true;
+    let mut chunk_counts = [0; MAX_COUNTERS];
+    let mut texts = Vec::with_capacity(piece_counts.len());
 
-            let cur_counts = counter::count(&counters, &line);
+    for (line_no, counts, ends_record) in piece_counts {
+        // a record with an error isn't counted at all
+        let (offset, counts) = match counts {
+            Some((offset, counts, text)) => {
+                if let Some(ref mut classifier) = record.classifier {
+                    classifier.update(&text);
+                }
 
-            if opts.mode == CountMode::Line {
-                let name = file_name_with_line(&file_name, line_no);
-                write_counts(
-                    &mut *output_writer.lock().unwrap(),
-                    &cur_counts,
-                    Some(&name),
-                )?;
+                if let Some(ref mut record_text) = record.text {
+                    record_text.push_str(&text);
+                }
+
+                texts.push(text);
+                (offset, counts)
+            }
+            None => {
+                success = false;
+                record.counts = None;
+
+                if let Some(ref mut classifier) = record.classifier {
+                    classifier.skip_line();
+                }
+
+                if let Some(ref mut record_text) = record.text {
+                    record_text.clear();
+                }
+
+                continue;
             }
+        };
+
+        let (_, record_counts) = record.counts.get_or_insert((offset, [0; MAX_COUNTERS]));
+        counter::sum_arrays(record_counts, &counts);
+
+        if !ends_record {
+            continue;
+        }
+
+        let (record_offset, mut cur_counts) = record.counts.take().unwrap();
+
+        if let Some(ref mut classifier) = record.classifier {
+            let counter = Counter::LineClass(classifier.end_line());
+
+            if let Some(i) = counters.iter().position(|c| *c == counter) {
+                cur_counts[i] += 1;
+            }
+        }
+
+        if opts.mode == CountMode::Line {
+            // with --annotate, the rows are named after the lines themselves
+            let name = match record.text {
+                Some(ref mut text) => {
+                    let line = text.trim_end_matches(is_line_break).to_string();
+                    text.clear();
+                    line
+                }
+                None => file_name_with_line(file_name, line_no),
+            };
+            let name = line_row_name(&name, Some(record_offset), opts);
+            write_counts(
+                &mut *output_writer.lock().unwrap(),
+                &counter::counted_from_array(&counters, &cur_counts),
+                Some(&name),
+            )?;
+        }
+
+        // sum up the counts for each line into the total counts for the file
+        counter::sum_arrays(&mut chunk_counts, &cur_counts);
+    }
+
+    buffer_pool.give_back(texts);
+
+    Ok((
+        success,
+        counter::counted_from_array(&counters, &chunk_counts),
+    ))
+}
+
+/// Writes the total counts of an input under its name. With `--group-by` or
+/// `--group-by-path`, the inputs' counts are written per group at the end
+/// instead.
+fn write_input_counts(
+    name: &str,
+    counts: &Counted,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(), Error> {
+    if opts.grouping().is_some() {
+        return Ok(());
+    }
+
+    match opts.mode {
+        CountMode::File => {
+            let mut writer = output_writer.lock().unwrap();
+            write_selected_counts(&mut *writer, &opts.get_counters(), counts, Some(name))?
+        }
+        CountMode::Line => {
+            let name = line_row_name(&file_name_with_line(name, TOTAL), None, opts);
+            write_counts(&mut *output_writer.lock().unwrap(), counts, Some(&name))?
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts everything read from `reader`, writing the counts under `name`.
+/// The input is transcoded from `--encoding` or `--encoding-for` first, if
+/// they're given, and then it's narrowed to the field given with `--field` or
+/// `--json-field`, and its markup is stripped with `--markup`. Binary inputs
+/// are handled as given by `--binary`; if the input is skipped, no counts are
+/// returned.
+fn count_reader<R: Read>(
+    name: &str,
+    reader: R,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    let reader = match opts.input_encoding(name) {
+        Some(encoding) => encoding::transcode(reader, encoding),
+        None => Box::new(reader),
+    };
+
+    let reader = match opts.json_field {
+        Some(ref path) => json_field::extract(reader, path.clone()),
+        None => reader,
+    };
+
+    let reader = match opts.field {
+        Some(field) => fields::select(reader, field, opts.field_sep.unwrap_or(b',')),
+        None => reader,
+    };
+
+    let reader = match opts.markup {
+        Some(markup) => markup::strip(reader, markup, opts.exclude_code, opts.decode_entities),
+        None => reader,
+    };
+
+    if opts.binary == BinaryPolicy::Count {
+        return count_text(name, BufReader::new(reader), opts, output_writer);
+    }
+
+    let (is_binary, mut reader) = match binary::sniff(reader) {
+        Ok(sniffed) => sniffed,
+        Err(e) => {
+            report::input_error(opts, InputError::io(name, &e));
+            return Ok((false, None));
+        }
+    };
+
+    if !is_binary {
+        return count_text(name, BufReader::new(reader), opts, output_writer);
+    }
+
+    if opts.binary == BinaryPolicy::Skip {
+        info!("skipping binary input: {}", name);
+        return Ok((true, None));
+    }
+
+    let num_bytes = match io::copy(&mut reader, &mut io::sink()) {
+        Ok(n) => n as usize,
+        Err(e) => {
+            report::input_error(opts, InputError::io(name, &e));
+            return Ok((false, None));
+        }
+    };
 
-            Ok((true, cur_counts))
+    count_binary(name, num_bytes, opts, output_writer)
+}
+
+/// Counts an input that's been mapped into memory. This is like
+/// `count_reader`, except the mapped bytes are counted where they are, without
+/// copying them into a buffer.
+fn count_mapped(
+    name: &str,
+    map: Mmap,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    let is_binary = opts.binary != BinaryPolicy::Count
+        && binary::is_binary(&map[..map.len().min(binary::SNIFF_LENGTH)]);
+
+    if !is_binary {
+        return count_text(name, Cursor::new(map), opts, output_writer);
+    }
+
+    if opts.binary == BinaryPolicy::Skip {
+        info!("skipping binary input: {}", name);
+        return Ok((true, None));
+    }
+
+    count_binary(name, map.len(), opts, output_writer)
+}
+
+/// Writes the counts of a binary input that's only had its bytes counted.
+fn count_binary(
+    name: &str,
+    num_bytes: usize,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    let counts: Counted = opts
+        .get_counters()
+        .iter()
+        .map(|c| match c {
+            Counter::NumByte => (*c, num_bytes),
+            _ => (*c, 0),
         })
-        // sum up the counts for each line into the total counts for
-        // the file
-        .reduce(
-            || Ok((true, Counted::new())),
-            |mut acc: Result<_, Error>, r: Result<_, Error>| {
-                if r.is_err() {
-                    return r;
+        .collect();
+
+    write_input_counts(name, &counts, opts, output_writer)?;
+
+    Ok((true, Some(counts)))
+}
+
+/// Counts everything read from `reader` as text, writing the counts under
+/// `name`. A leading byte order mark is skipped with `--strip-bom`. With
+/// `--invalid-utf8 skip-file`, no counts are returned for inputs that aren't
+/// valid UTF-8.
+fn count_text<R: BufRead>(
+    name: &str,
+    mut reader: R,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    let mut first_offset = 0;
+
+    if opts.strip_bom {
+        match input::skip_bom(&mut reader) {
+            Ok(true) => first_offset = constants::BOM.len() as u64,
+            Ok(false) => {}
+            Err(e) => {
+                report::input_error(opts, InputError::io(name, &e));
+                return Ok((false, None));
+            }
+        }
+    }
+
+    let (success, file_counts) =
+        count_records(name, reader, (1, first_offset), opts, output_writer)?;
+
+    if let Some(ref file_counts) = file_counts {
+        write_input_counts(name, file_counts, opts, output_writer)?;
+    }
+
+    Ok((success, file_counts))
+}
+
+/// Counts the records read from `reader`, without writing the total counts.
+/// `start` is the number of the first line and the offset of the first byte
+/// that are read. With `--line-range`, only the lines in the range are
+/// counted, counting from the first one that's read. In line mode, the counts
+/// of every record are written, and with `--interval`, snapshots of the
+/// counts so far. With `--invalid-utf8 skip-file`, no counts are returned for
+/// inputs that aren't valid UTF-8.
+fn count_records<R: BufRead>(
+    name: &str,
+    mut reader: R,
+    start: (usize, u64),
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Option<Counted>), Error> {
+    if counts_raw_bytes(opts) {
+        return Ok(count_raw(name, reader, opts));
+    }
+
+    let keep_newlines = opts.should_keep_newlines();
+    let delimiter = opts.record_delimiter();
+
+    let mut success = true;
+    let mut file_counts: Counted = opts.get_counters().iter().map(|c| (*c, 0)).collect();
+
+    let max_len = Some(opts.split_len).filter(|len| *len > 0);
+
+    // the lines' buffers are reused once they've been counted
+    let buffer_pool = BufferPool::default();
+
+    let (first_line, first_offset) = start;
+
+    let (lines_before, num_lines) = match opts.line_range {
+        Some(range) => (range.lines_before(), range.num_lines()),
+        None => (0, None),
+    };
+
+    let chunks = UStrChunksIter::new(&mut reader, delimiter, keep_newlines)
+        .start_offset(first_offset)
+        .skip_records(lines_before)
+        .max_records(num_lines)
+        .invalid_utf8(opts.invalid_utf8)
+        .max_len(max_len)
+        .buffer_pool(buffer_pool.clone());
+
+    let mut line_no = first_line + lines_before;
+    let mut skipped = false;
+
+    // with --interval, snapshots of the counts are written as they're counted
+    let start_time = Instant::now();
+    let mut snapshots = opts
+        .interval
+        .map(|every| Snapshots::new(start_time, every, opts.interval_counts));
+    let mut record = RecordState {
+        counts: None,
+        classifier: opts.line_classifier(),
+        text: opts.annotate.then(String::new),
+    };
+
+    // when streaming, every line is counted and printed as soon as it's read
+    let chunk_size = match opts.stream {
+        true => 1,
+        false => opts.chunk_size,
+    };
+
+    // Counts a chunk, and returns whether to keep going. With `flush`, the
+    // output is flushed after, so that the lines of a partial chunk show up
+    // right away.
+    let mut count_chunk =
+        |mut chunk: Vec<error::Result<Piece>>, flush: bool| -> Result<bool, Error> {
+            let num_lines = ubufreader::count_records(&chunk);
+            progress::advance(name, chunk.iter().map(piece_len).sum());
+
+            if opts.invalid_utf8 == InvalidUtf8::SkipFile {
+                let invalid = chunk
+                    .iter()
+                    .position(|piece| matches!(piece, Err(e) if e.is_utf8()));
+
+                if let Some(i) = invalid {
+                    let invalid_line = line_no + ubufreader::count_records(&chunk[..i]);
+                    let e = chunk.swap_remove(i).unwrap_err();
+                    let e = e.with_file(name).with_line(invalid_line);
+                    report::input_error(opts, InputError::read(&e).skipping());
+                    skipped = true;
+                    return Ok(false);
+                }
+            }
+
+            let (chunk_success, line_counts) = count_chunks(
+                name,
+                chunk,
+                opts,
+                line_no,
+                &mut record,
+                &buffer_pool,
+                output_writer,
+            )?;
+
+            line_no += num_lines;
+
+            counter::sum_counts(&mut file_counts, &line_counts);
+            success &= chunk_success;
+
+            if let Some((elapsed, counts)) = snapshots.as_mut().and_then(|s| s.take(&file_counts)) {
+                let row = line_row_name(&file_name_with_line(name, elapsed), None, opts);
+                write_counts(&mut *output_writer.lock().unwrap(), &counts, Some(&row))?;
+            }
+
+            if flush || opts.stream {
+                output_writer.lock().unwrap().flush()?;
+            }
+
+            Ok(true)
+        };
+
+    match opts.flush_interval.or(opts.interval) {
+        None => {
+            let chunks = chunks.batching(|pieces| {
+                let mut chunk = Vec::new();
+                let mut len = 0;
+
+                for piece in pieces {
+                    len += piece_len(&piece);
+                    chunk.push(piece);
+
+                    if chunk.len() >= chunk_size || len >= MAX_CHUNK_LEN {
+                        break;
+                    }
                 }
-                match acc {
-                    Err(e) => return Err(e),
-                    Ok(ref mut acc_counts_success) => {
-                        // already guaranteed to be ok by the check above
-                        let (r_success, r_current) = r.unwrap();
-                        let &mut (ref mut acc_success, ref mut acc_counts) = acc_counts_success;
-
-                        for (ctr, total) in r_current {
-                            let entry = acc_counts.entry(ctr).or_insert(0);
-                            *entry += total;
+
+                Some(chunk).filter(|chunk| !chunk.is_empty())
+            });
+
+            for chunk in chunks {
+                if !count_chunk(chunk, false)? {
+                    break;
+                }
+            }
+        }
+
+        // The lines are read on this thread and counted on another, which
+        // counts a partial chunk whenever no line arrives for the interval,
+        // or with --interval, whenever a snapshot is due.
+        Some(interval) => thread::scope(|scope| -> Result<(), Error> {
+            let (tx, rx) = mpsc::sync_channel(chunk_size);
+
+            let counting = scope.spawn(move || -> Result<(), Error> {
+                let mut chunk = Vec::new();
+                let mut len = 0;
+                let mut tick = opts.interval.map(|every| (every, start_time + every));
+
+                loop {
+                    let timeout = match tick {
+                        Some((_, due)) => due.saturating_duration_since(Instant::now()),
+                        None => interval,
+                    };
+
+                    let (done, flush) = match rx.recv_timeout(timeout) {
+                        Ok(piece) => {
+                            len += piece_len(&piece);
+                            chunk.push(piece);
+                            (false, false)
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => (false, true),
+                        Err(mpsc::RecvTimeoutError::Disconnected) => (true, false),
+                    };
+
+                    // the chunk is counted when a snapshot is due, even if
+                    // it's empty, so that the snapshot is still written
+                    let ticked = match tick {
+                        Some((every, due)) if !done && Instant::now() >= due => {
+                            let next = interval::next_tick(start_time, every, Instant::now());
+                            tick = Some((every, next));
+                            true
                         }
+                        _ => false,
+                    };
+                    let flush = flush || ticked;
+
+                    let is_full = chunk.len() >= chunk_size || len >= MAX_CHUNK_LEN;
+
+                    if is_full || flush || done {
+                        len = 0;
+                    }
 
-                        *acc_success &= r_success;
+                    if (!chunk.is_empty() || ticked)
+                        && (is_full || flush || done)
+                        && !count_chunk(mem::take(&mut chunk), flush)?
+                    {
+                        return Ok(());
                     }
+
+                    if done {
+                        return Ok(());
+                    }
+                }
+            });
+
+            for line in chunks {
+                // the counting thread stopped early
+                if tx.send(line).is_err() {
+                    break;
                 }
+            }
+
+            drop(tx);
+            counting.join().unwrap()
+        })?,
+    }
+
+    if skipped {
+        return Ok((false, None));
+    }
+
+    Ok((success, Some(file_counts)))
+}
+
+/// Determines if the files found when counting recursively are counted while
+/// the directories are still being walked. They aren't when every file has to
+/// be known before any of them are counted, like for the progress bar, or when
+/// they aren't counted one by one, like with `--concat`. With `--strict`, the
+/// errors walking the directories have to be found before counting, too.
+fn counts_while_walking(opts: &Opt) -> bool {
+    opts.recursive
+        && !opts.concat
+        && !opts.async_io
+        && !opts.strict
+        && !opts.progress
+        && !opts.summary
+        && !opts.list_files
+        && !opts.check_utf8
+        && !opts.check_line_endings
+        && !opts.line_stats
+        && opts.filter.is_none()
+        && !opts.follow
+}
+
+/// Determines if the input can be counted without decoding it, i.e., in file
+/// mode, when only bytes and lines are counted, or always with
+/// `--bytes-only-decode off`. Lines with invalid UTF-8 are still decoded with
+/// `--invalid-utf8 skip-line` or `skip-file`, since they aren't counted, and
+/// with `--interval`, so that they're counted as they're read.
+fn counts_raw_bytes(opts: &Opt) -> bool {
+    if opts.bytes_only_decode == Some(Decoding::Off) {
+        return true;
+    }
+
+    opts.mode == CountMode::File
+        && opts
+            .get_counters()
+            .iter()
+            .all(|c| *c == Counter::NumByte || *c == Counter::Line)
+        && opts.invalid_utf8 != InvalidUtf8::SkipLine
+        && opts.invalid_utf8 != InvalidUtf8::SkipFile
+        && opts.line_range.is_none()
+        && opts.interval.is_none()
+}
+
+/// Counts the bytes and lines read from `reader` without decoding them, like
+/// `wc -lc` does, which is much faster than segmenting the text. Invalid UTF-8
+/// isn't an error here, since it doesn't change either count. The other
+/// counters are left out, since they can't be counted without decoding.
+fn count_raw<R: Read>(name: &str, reader: R, opts: &Opt) -> (bool, Option<Counted>) {
+    let reader = progress::Tracked { reader, name };
+
+    let (num_bytes, num_lines) = match opts.newlines.count_raw(reader) {
+        Ok(counts) => counts,
+        Err(e) => {
+            report::input_error(opts, InputError::io(name, &e));
+            return (false, None);
+        }
+    };
+
+    let counts = opts
+        .get_counters()
+        .iter()
+        .filter_map(|c| match c {
+            Counter::NumByte => Some((*c, num_bytes)),
+            Counter::Line => Some((*c, num_lines)),
+            _ => None,
+        })
+        .collect();
+
+    (true, Some(counts))
+}
+
+/// Gets the byte count of the given file from its size, without reading it,
+/// when bytes are the only thing counted and nothing would change the bytes as
+/// they're read, like `wc -c` does. Returns `None` if the file has to be read.
+fn byte_count_from_size(file_name: &str, opts: &Opt) -> Option<Counted> {
+    let counters = opts.get_counters();
+
+    let bytes_only = counters.len() == 1 && counters.contains(&Counter::NumByte);
+    let reads_as_is = opts.mode == CountMode::File
+        && !opts.archive
+        && !opts.strip_bom
+        && opts.markup.is_none()
+        && opts.field.is_none()
+        && opts.json_field.is_none()
+        && opts.binary != BinaryPolicy::Skip
+        && opts.line_range.is_none()
+        && opts.input_encoding(file_name).is_none();
+
+    if !bytes_only || !reads_as_is {
+        return None;
+    }
 
-                acc
-            },
-        )
+    let size = input::plain_file_size(file_name, opts.decompress)?;
+    debug!(
+        "using the size of {} as its byte count: {}",
+        file_name, size
+    );
+
+    Some(counters.iter().map(|c| (*c, size as usize)).collect())
 }
 
+/// Counts the given input file, returning whether it was counted without
+/// errors, and the counts for each row that was written for it. This is
+/// usually just one row, named after the file, but archives get a row for each
+/// of their members, named like `archive.tar:path/to/member`.
 fn count_file(
     file_name: &str,
-    mut file_counts: &mut Counted,
     opts: &Opt,
     mut output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
-) -> Result<bool, Error> {
-    let keep_newlines = opts.should_keep_newlines();
-
+) -> Result<(bool, Vec<(String, Counted)>), Error> {
     info!("Counting file: {}", file_name);
 
-    let mut success = true;
+    if let Some(range) = opts.byte_range {
+        let (success, file_counts) = byte_range::count(file_name, range, opts, &mut output_writer)?;
+        let rows = file_counts.map(|counts| (file_name.to_string(), counts));
+        return Ok((success, rows.into_iter().collect()));
+    }
+
+    if let Some(file_counts) = byte_count_from_size(file_name, opts) {
+        write_input_counts(file_name, &file_counts, opts, &mut output_writer)?;
+        return Ok((true, vec![(file_name.to_string(), file_counts)]));
+    }
 
-    let input = match Input::new(&file_name) {
+    if let Some((success, file_counts)) = ranges::count(file_name, opts, &mut output_writer)? {
+        let rows = file_counts.map(|counts| (file_name.to_string(), counts));
+        return Ok((success, rows.into_iter().collect()));
+    }
+
+    if opts.mmap
+        && !opts.archive
+        && opts.input_encoding(file_name).is_none()
+        && opts.markup.is_none()
+        && opts.field.is_none()
+        && opts.json_field.is_none()
+    {
+        if let Some(map) = input::map_plain_file(file_name, opts.decompress) {
+            let (success, file_counts) = count_mapped(file_name, map, opts, &mut output_writer)?;
+            let rows = file_counts.map(|counts| (file_name.to_string(), counts));
+            return Ok((success, rows.into_iter().collect()));
+        }
+    }
+
+    let input = match Input::open(file_name, opts.decompress) {
         Ok(i) => i,
         Err(e) => {
-            eprintln!("{}: {}", &file_name, e);
-            return Ok(false);
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok((false, Vec::new()));
         }
     };
 
-    let mut reader = BufReader::new(input);
-    let chunks = UStrChunksIter::new(&mut reader, keep_newlines);
+    count_input(file_name, input, opts, output_writer)
+}
+
+/// Counts an input that's been opened, like `count_file`.
+fn count_input(
+    file_name: &str,
+    input: Input,
+    opts: &Opt,
+    mut output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Vec<(String, Counted)>), Error> {
+    if !opts.archive {
+        let (success, file_counts) = count_reader(file_name, input, opts, &mut output_writer)?;
+        let rows = file_counts.map(|counts| (file_name.to_string(), counts));
+        return Ok((success, rows.into_iter().collect()));
+    }
+
+    let (format, input) = match archive::sniff(input) {
+        Ok(sniffed) => sniffed,
+        Err(e) => {
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok((false, Vec::new()));
+        }
+    };
 
-    let mut line_no = 1;
-    for chunk in &chunks.chunks(opts.chunk_size) {
-        let chunk: Vec<_> = chunk.collect();
-        let num_lines = chunk.len();
+    let format = match format {
+        Some(format) => format,
+        None => {
+            let (success, file_counts) = count_reader(file_name, input, opts, &mut output_writer)?;
+            let rows = file_counts.map(|counts| (file_name.to_string(), counts));
+            return Ok((success, rows.into_iter().collect()));
+        }
+    };
 
-        let (chunk_success, line_counts) =
-            count_chunks(file_name, chunk, opts, line_no, &mut output_writer)?;
+    let mut success = true;
+    let mut rows = Vec::new();
 
-        // NOTE: Fix this if the chunks are ever a different unit than lines.
-        line_no += num_lines;
+    let archive_result = archive::for_each_member(format, input, |path, member| {
+        let name = file_name_with_line(file_name, path);
+        let (member_success, member_counts) =
+            count_reader(&name, member, opts, &mut output_writer)?;
 
-        counter::sum_counts(&mut file_counts, &line_counts);
-        success &= chunk_success;
+        success &= member_success;
+        rows.extend(member_counts.map(|counts| (name, counts)));
+
+        Ok::<_, Error>(())
+    });
+
+    if let Err(e) = archive_result {
+        match e.downcast::<io::Error>() {
+            Ok(e) => report::input_error(opts, InputError::io(file_name, &e)),
+            Err(e) => return Err(e),
+        }
+
+        success = false;
     }
 
-    match opts.mode {
-        CountMode::File => write_counts(
-            &mut *output_writer.lock().unwrap(),
-            &file_counts,
-            Some(&file_name),
-        )?,
-        CountMode::Line => {
-            let name = file_name_with_line(&file_name, TOTAL);
-            write_counts(
-                &mut *output_writer.lock().unwrap(),
-                &file_counts,
-                Some(&name),
-            )?
+    Ok((success, rows))
+}
+
+/// Checks the counts of every row against `--fail-if`, and their total against
+/// `--fail-if-total`, printing the conditions that hold. Returns whether none
+/// of them did.
+fn check_thresholds(counts: &BTreeMap<String, Counted>, opts: &Opt) -> bool {
+    let totals = counter::sum_all_counts(counts.values());
+
+    let row_checks = counts.iter().flat_map(|(name, row_counts)| {
+        opts.fail_if
+            .iter()
+            .map(move |threshold| (name.as_str(), row_counts, threshold))
+    });
+    let total_checks = opts
+        .fail_if_total
+        .iter()
+        .map(|threshold| (TOTAL, &totals, threshold));
+
+    let mut passed = true;
+
+    for (name, counts, threshold) in row_checks.chain(total_checks) {
+        if threshold.is_violated(counts) {
+            let counter = threshold.counter();
+            eprintln!("{}: {} ({} {})", name, threshold, counts[&counter], counter);
+            passed = false;
+        }
+    }
+
+    passed
+}
+
+/// Counts the given input file like [`count_file`], unless its counts are in
+/// the cache and it hasn't changed since then.
+fn count_file_cached(
+    file_name: &str,
+    opts: &Opt,
+    cache: Option<&Cache>,
+    mut output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<(bool, Vec<(String, Counted)>), Error> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            let counted = count_file(file_name, opts, output_writer);
+            progress::finish(file_name);
+            return counted;
         }
+    };
+
+    if let Some(rows) = cache.get(file_name) {
+        for (name, counts) in &rows {
+            write_input_counts(name, counts, opts, &mut output_writer)?;
+        }
+
+        progress::finish(file_name);
+        return Ok((true, rows));
+    }
+
+    let (success, rows) = count_file(file_name, opts, output_writer)?;
+    progress::finish(file_name);
+
+    if success {
+        cache.insert(file_name, &rows);
     }
 
-    Ok(success)
+    Ok((success, rows))
+}
+
+/// Whether an input was counted without errors, and its rows of counts.
+type InputCounts = (bool, Vec<(String, Counted)>);
+
+/// Counts the given files with `--async-io`.
+#[cfg(feature = "async-io")]
+fn count_files_async(
+    files: &[String],
+    opts: &Opt,
+    cache: Option<&Cache>,
+    timings: Option<&Timings>,
+    output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<Vec<Result<InputCounts, Error>>, Error> {
+    Ok(async_io::count_files(
+        files,
+        opts,
+        cache,
+        timings,
+        output_writer,
+    ))
+}
+
+#[cfg(not(feature = "async-io"))]
+fn count_files_async(
+    _files: &[String],
+    _opts: &Opt,
+    _cache: Option<&Cache>,
+    _timings: Option<&Timings>,
+    _output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<Vec<Result<InputCounts, Error>>, Error> {
+    Err(format_err!(
+        "--async-io needs uwc to be built with the async-io feature"
+    ))
 }
 
 /// The return type indicates error conditions. In some error cases, it will just
 /// print the error and continue counting (e.g., if the user passes a directory
-/// as input). A return value of Ok(true) indicates that the run was successful
-/// with no errors; Ok(false) indicates that there were errors, but not fatal
-/// to the `run` function. A return value of `Err` indicates a fatal error that
-/// needed to exit immediately, e.g., writing to stdout failed.
-fn run() -> Result<bool, Error> {
-    let opts = Opt::from_args();
+/// as input). A return value of `Ok` gives whether the run was successful with
+/// no errors, or whether there were errors that weren't fatal to the `run`
+/// function. A return value of `Err` indicates a fatal error that needed to
+/// exit immediately, e.g., writing to stdout failed.
+fn run() -> Result<Status, Error> {
+    let mut config = Config::load()?;
+
+    // the arguments in UWC_OPTS go right after the program name, so that the
+    // ones on the command line win
+    let mut args = env::args_os();
+    let program = args.next();
+    let args: Vec<OsString> = program
+        .clone()
+        .into_iter()
+        .chain(config::env_args()?.into_iter().map(OsString::from))
+        .chain(args)
+        .collect();
+
+    // and the profile's arguments go before those
+    let profile_args = match Opt::find_profile(&args) {
+        Some(profile) => config.apply_profile(&profile)?,
+        None => Vec::new(),
+    };
+
+    let args: Vec<OsString> = program
+        .into_iter()
+        .chain(profile_args.into_iter().map(OsString::from))
+        .chain(args.into_iter().skip(1))
+        .collect();
+
+    let mut opts = Opt::parse_from(args, &config);
+    opts.recursive |= opts.watch;
+
+    set_verbosity(&opts);
 
     debug!("opts: {:?}", opts);
 
+    // the status file is written however the run went, once it's over
+    let status_file = opts.status_file.clone();
+    let mut summary = Summary::new();
+    let run_result = run_with(opts, &mut summary);
+
+    if let Some(path) = status_file {
+        status_file::write(&path, &run_result, &summary)?;
+    }
+
+    run_result
+}
+
+/// Counts the inputs as given by `opts`, or checks them with the modes that
+/// don't count them, keeping track of what happened to them in `summary`.
+fn run_with(opts: Opt, summary: &mut Summary) -> Result<Status, Error> {
+    if let Some(num_threads) = opts.num_threads() {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    if opts.report_unicode_version {
+        unicode_version::report(&opts)?;
+        return Ok(Status::Success);
+    }
+
     let counters = opts.get_counters();
     let mode = opts.mode;
 
-    let mut counts: BTreeMap<String, Counted> = opts
-        .files
-        .clone()
-        .into_iter()
-        .map(|fname| {
-            (
-                fname,
-                counters.iter().map(|c| (*c, 0usize)).collect::<Counted>(),
-            )
-        })
-        .collect();
+    if opts.stream && mode != CountMode::Line {
+        return Err(format_err!("--stream can only be used in line mode"));
+    }
+
+    if opts.annotate && mode != CountMode::Line {
+        return Err(format_err!("--annotate can only be used in line mode"));
+    }
+
+    if opts.byte_offset && mode != CountMode::Line {
+        return Err(format_err!("--byte-offset can only be used in line mode"));
+    }
+
+    if opts.bytes_only_decode == Some(Decoding::Off) && mode != CountMode::File {
+        return Err(format_err!(
+            "--bytes-only-decode off can only be used in file mode"
+        ));
+    }
+
+    if opts.mean && mode != CountMode::File {
+        return Err(format_err!("--mean can only be used in file mode"));
+    }
+
+    if opts.min_max && mode != CountMode::File {
+        return Err(format_err!("--min-max can only be used in file mode"));
+    }
+
+    let (inputs, given_success) = walk::given_inputs(&opts)?;
+
+    if opts.watch {
+        let watch_success = watch::watch(&inputs, &opts)?;
+        return Ok(Status::new(watch_success && given_success, true, &opts));
+    }
+
+    // the files found in directories are counted as they're found, so they're
+    // only all known up front if something needs them to be
+    let pipelined = counts_while_walking(&opts);
+
+    let (files, mut walk_success) = match pipelined {
+        true => (Vec::new(), true),
+        false => walk::expand(&inputs, &opts),
+    };
+    walk_success &= given_success;
+
+    if opts.strict && !walk_success {
+        return Ok(Status::InputsFailed);
+    }
+
+    if opts.list_files {
+        let list_success = list_files::list(&files, &opts)?;
+        return Ok(Status::new(list_success && walk_success, true, &opts));
+    }
+
+    if opts.check_utf8 {
+        let (check_success, valid) = check_utf8::check(&files, &opts)?;
+        return Ok(Status::new(check_success && walk_success, valid, &opts));
+    }
+
+    if opts.check_line_endings {
+        let (check_success, consistent) = line_endings::check(&files, &opts)?;
+        return Ok(Status::new(
+            check_success && walk_success,
+            consistent,
+            &opts,
+        ));
+    }
+
+    if opts.line_stats {
+        let stats_success = line_stats::report(&files, &opts)?;
+        return Ok(Status::new(stats_success && walk_success, true, &opts));
+    }
+
+    if let Some(filter) = opts.filter {
+        let filter_success = filter::report(&files, filter, &opts)?;
+        return Ok(Status::new(filter_success && walk_success, true, &opts));
+    }
+
+    if opts.follow {
+        let follow_success = follow::follow(&files, &opts)?;
+        return Ok(Status::new(follow_success && walk_success, true, &opts));
+    }
+
+    // when comparing against a baseline, only the changes are written
+    let baseline = opts.compare.as_deref().map(baseline::load).transpose()?;
 
     let stdout = io::stdout();
 
-    let writer: Arc<Mutex<dyn Write + Send + Sync>> = if opts.no_elastic {
+    let writer: Arc<Mutex<dyn Write + Send + Sync>> = if baseline.is_some() {
+        Arc::new(Mutex::new(io::sink()))
+    } else if opts.no_elastic {
         Arc::new(Mutex::new(stdout))
     } else {
         Arc::new(Mutex::new(TabWriter::new(stdout)))
     };
 
     if !opts.no_header {
-        write_header(&mut *writer.lock().unwrap(), &counters)?;
-    }
-
-    let success = counts
-        .par_iter_mut()
-        .map(|(file_name, file_counts)| count_file(file_name, file_counts, &opts, writer.clone()))
-        .reduce(
-            || Ok(true),
-            |acc_result, success_result| {
-                let acc = acc_result?;
-                let success = success_result?;
-                Ok(acc && success)
-            },
+        write_header(
+            &mut *writer.lock().unwrap(),
+            &counters,
+            opts.byte_offset,
+            opts.annotate,
         )?;
+    }
+
+    let cache = match opts.cache {
+        Some(ref path) if mode == CountMode::File => {
+            let path = path
+                .clone()
+                .or_else(cache::default_path)
+                .ok_or_else(|| format_err!("couldn't find a directory for the cache"))?;
+
+            Some(Cache::load(path, &opts)?)
+        }
+        _ => None,
+    };
+
+    let timings = Some(Timings::new()).filter(|_| opts.timings);
+
+    // with --strict, the inputs that haven't started counting yet once one
+    // fails are skipped
+    let failed = AtomicBool::new(false);
+
+    // the rows are written in the order the inputs were given, unless
+    // --unordered is given
+    let ordered = Some(OrderedOutput::new(writer.clone())).filter(|_| opts.writes_in_order());
+
+    let progress_bar = progress::start(&files, &opts);
+
+    let count_input_at = |i: usize, file_name: &str| -> Result<InputCounts, Error> {
+        let input_writer = match ordered {
+            Some(ref ordered) => ordered.input_writer(i),
+            None => writer.clone(),
+        };
+
+        let result = if opts.strict && failed.load(Ordering::Relaxed) {
+            debug!("skipping after an input failed: {}", file_name);
+            Ok((true, Vec::new()))
+        } else {
+            timings::time(timings.as_ref(), file_name, || {
+                output_dir::count_to_file(file_name, &opts, input_writer, |writer| {
+                    count_file_cached(file_name, &opts, cache.as_ref(), writer)
+                })
+            })
+        };
+
+        if !matches!(result, Ok((true, _))) {
+            failed.store(true, Ordering::Relaxed);
+        }
+
+        if let Some(ref ordered) = ordered {
+            ordered.finish(i)?;
+        }
+
+        result
+    };
+
+    let results = match opts.async_io {
+        _ if opts.concat => vec![concat::count(&files, &opts, writer.clone())],
+        _ if pipelined => {
+            let (results, success) = walk::expand_while_counting(&inputs, &opts, count_input_at);
+            walk_success &= success;
+            results
+        }
+        true => count_files_async(
+            &files,
+            &opts,
+            cache.as_ref(),
+            timings.as_ref(),
+            writer.clone(),
+        )?,
+        false => open_files::count_limited(&files, open_files::limit(&opts), count_input_at),
+    };
+
+    let num_inputs = results.len();
+
+    // the bar is cleared before the totals are written
+    drop(progress_bar);
+
+    let mut success = true;
+    let mut counts: BTreeMap<String, Counted> = BTreeMap::new();
+
+    for result in results {
+        let (file_success, rows) = result?;
+        success &= file_success;
+
+        summary.add(file_success, &rows);
+
+        counts.extend(rows);
+    }
 
     info!("final_counts: {:?}", counts);
 
-    if mode == CountMode::File && counts.len() > 1 {
+    if let Some(cache) = cache {
+        cache.save()?;
+    }
+
+    if let Some(group_by) = opts.grouping() {
+        for (group, group_counts) in group_by.group(&counts) {
+            let group = line_row_name(&group, None, &opts);
+            let mut writer = writer.lock().unwrap();
+            write_selected_counts(&mut *writer, &counters, &group_counts, Some(&group))?;
+        }
+    }
+
+    if mode == CountMode::File && !opts.concat && (num_inputs > 1 || counts.len() > 1) {
         let totals = counter::sum_all_counts(counts.values());
-        write_counts(&mut *writer.lock().unwrap(), &totals, Some(TOTAL))?;
+        write_selected_counts(
+            &mut *writer.lock().unwrap(),
+            &counters,
+            &totals,
+            Some(TOTAL),
+        )?;
+    }
+
+    if opts.mean && !counts.is_empty() {
+        let means = counter::mean_counts(counts.values());
+        write_means(&mut *writer.lock().unwrap(), &means)?;
+    }
+
+    if opts.min_max && !counts.is_empty() {
+        let rows: Vec<_> = counts
+            .iter()
+            .map(|(name, counts)| (name.as_str(), counts))
+            .collect();
+        write_extremes(&mut *writer.lock().unwrap(), &rows, MIN, false)?;
+        write_extremes(&mut *writer.lock().unwrap(), &rows, MAX, true)?;
     }
 
     writer.lock().unwrap().flush()?;
 
-    Ok(success)
+    if let Some(timings) = timings {
+        timings.write_report()?;
+    }
+
+    if opts.summary {
+        summary.write()?;
+    }
+
+    let within_limits = match baseline {
+        Some(baseline) => baseline::compare(&baseline, &counts, &opts)?,
+        None => true,
+    };
+
+    if let Some(ref path) = opts.save_baseline {
+        baseline::save(path, &counts)?;
+    }
+
+    let within_thresholds = check_thresholds(&counts, &opts);
+
+    Ok(Status::new(
+        success && walk_success,
+        within_limits && within_thresholds,
+        &opts,
+    ))
 }
@@ -0,0 +1,724 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::str::{self, FromStr};
+
+use lazy_static::*;
+use regex::{Captures, Regex};
+
+/// The markup languages whose syntax can be stripped before counting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Markup {
+    Markdown,
+    Html,
+    Xml,
+}
+
+impl FromStr for Markup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Markup, String> {
+        match s {
+            "markdown" | "md" => Ok(Markup::Markdown),
+            "html" => Ok(Markup::Html),
+            "xml" => Ok(Markup::Xml),
+            _ => Err(format!("Unknown markup language: {}", s)),
+        }
+    }
+}
+
+lazy_static! {
+    static ref FENCE: Regex = Regex::new(r"^ {0,3}(`{3,}|~{3,})").unwrap();
+    static ref LINK_DEFINITION: Regex = Regex::new(r"^ {0,3}\[[^\]]+\]:\s*\S").unwrap();
+    static ref RULE: Regex = Regex::new(r"^ {0,3}(=+|-+|(?:[-*_][ \t]*){3,})\s*$").unwrap();
+    static ref BLOCKQUOTE: Regex = Regex::new(r"^ {0,3}(?:>[ \t]?)+").unwrap();
+    static ref HEADING: Regex = Regex::new(r"^ {0,3}#{1,6}(?:[ \t]+|$)").unwrap();
+    static ref HEADING_END: Regex = Regex::new(r"[ \t]+#+[ \t]*$").unwrap();
+    static ref LIST_ITEM: Regex =
+        Regex::new(r"^[ \t]*(?:[*+-]|\d{1,9}[.)])[ \t]+(?:\[[ xX]\][ \t]+)?").unwrap();
+
+    /// The inline syntax that's replaced with the text in it, in order.
+    static ref INLINE: Vec<Regex> = [
+        // images and links, whose text is kept without their URLs
+        r"!?\[([^\]]*)\]\([^)]*\)",
+        r"!?\[([^\]]+)\]\[[^\]]*\]",
+        r"<((?:https?|ftp|mailto):[^>\s]+)>",
+        // emphasis and strikethrough, longest first
+        r"\*\*\*([^*\s](?:[^*]*[^*\s])?)\*\*\*",
+        r"\*\*([^*\s](?:[^*]*[^*\s])?)\*\*",
+        r"\*([^*\s](?:[^*]*[^*\s])?)\*",
+        r"~~([^~\s](?:[^~]*[^~\s])?)~~",
+    ]
+    .iter()
+    .map(|re| Regex::new(re).unwrap())
+    .collect();
+
+    /// Emphasis with underscores, which only counts outside of words, so the
+    /// characters around it are kept too.
+    static ref UNDERSCORE_EMPHASIS: Vec<Regex> = [
+        r"(^|\W)___([^_\s](?:[^_]*[^_\s])?)___(\W|$)",
+        r"(^|\W)__([^_\s](?:[^_]*[^_\s])?)__(\W|$)",
+        r"(^|\W)_([^_\s](?:[^_]*[^_\s])?)_(\W|$)",
+    ]
+    .iter()
+    .map(|re| Regex::new(re).unwrap())
+    .collect();
+
+    static ref ESCAPE: Regex = Regex::new(r"\\([!-/:-@\[-`{-~])").unwrap();
+
+    static ref ENTITY: Regex =
+        Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[A-Za-z][A-Za-z0-9]{1,31});").unwrap();
+}
+
+/// The HTML elements whose start and end are line breaks or spaces when
+/// they're rendered, so their tags are replaced with a space.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// The HTML elements whose content isn't rendered as part of the page.
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "template", "title"];
+
+/// The HTML elements that are code, whose content is left out with
+/// `--exclude-code`.
+const CODE_ELEMENTS: &[&str] = &["code", "pre"];
+
+/// Strips Markdown syntax from text a line at a time, keeping the text that
+/// would be read in the rendered document. Every line is kept, even if it's
+/// left empty, so that lines are numbered the same as in the source.
+#[derive(Debug, Default)]
+struct MarkdownStripper {
+    /// The fence of the code block the current line is in, if any.
+    fence: Option<String>,
+
+    /// Whether to leave out code blocks and inline code.
+    exclude_code: bool,
+}
+
+impl MarkdownStripper {
+    /// Strips a line, without its line break.
+    fn strip_line(&mut self, line: &str) -> String {
+        if let Some(fence) = FENCE.captures(line).map(|caps| caps[1].to_string()) {
+            match self.fence {
+                None => {
+                    self.fence = Some(fence);
+                    return String::new();
+                }
+                // a fence closes the block if it's at least as long as the one
+                // that opened it, with the same character
+                Some(ref open) if fence.starts_with(open.as_str()) => {
+                    self.fence = None;
+                    return String::new();
+                }
+                Some(_) => {}
+            }
+        }
+
+        if self.fence.is_some() {
+            return match self.exclude_code {
+                true => String::new(),
+                false => line.to_string(),
+            };
+        }
+
+        if LINK_DEFINITION.is_match(line) || RULE.is_match(line) {
+            return String::new();
+        }
+
+        let line = BLOCKQUOTE.replace(line, "");
+
+        let line = match HEADING.find(&line) {
+            Some(heading) => HEADING_END.replace(&line[heading.end()..], "").into_owned(),
+            None => LIST_ITEM.replace(&line, "").into_owned(),
+        };
+
+        strip_inline(&line, self.exclude_code)
+    }
+}
+
+/// Strips the inline syntax of a line. Code spans are kept as they are, or left
+/// out with `exclude_code`.
+fn strip_inline(line: &str, exclude_code: bool) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let after = &rest[start + ticks..];
+
+        // a code span ends with the same number of backticks it starts with
+        let end = after
+            .match_indices(&rest[start..start + ticks])
+            .find(|(i, _)| !after[i + ticks..].starts_with('`') && !after[..*i].ends_with('`'));
+
+        stripped.push_str(&strip_text(&rest[..start]));
+
+        match end {
+            Some((end, _)) => {
+                if !exclude_code {
+                    stripped.push_str(&after[..end]);
+                }
+
+                rest = &after[end + ticks..];
+            }
+            None => {
+                stripped.push_str(&rest[start..start + ticks]);
+                rest = after;
+            }
+        }
+    }
+
+    stripped.push_str(&strip_text(rest));
+    stripped
+}
+
+/// Strips the inline syntax of text that isn't in a code span. Escaped
+/// characters are kept, without their backslashes.
+fn strip_text(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for caps in ESCAPE.captures_iter(text) {
+        let escape = caps.get(0).unwrap();
+        stripped.push_str(&strip_syntax(&text[last..escape.start()]));
+        stripped.push_str(&caps[1]);
+        last = escape.end();
+    }
+
+    stripped.push_str(&strip_syntax(&text[last..]));
+    stripped
+}
+
+/// Strips the inline syntax of text without code spans or escapes.
+fn strip_syntax(text: &str) -> String {
+    let mut text = Cow::Borrowed(text);
+
+    for re in INLINE.iter() {
+        text = Cow::Owned(re.replace_all(&text, "$1").into_owned());
+    }
+
+    // the character after one emphasis can be the one before the next, so
+    // these are replaced until there are none left
+    for re in UNDERSCORE_EMPHASIS.iter() {
+        while let Cow::Owned(replaced) = re.replace_all(&text, "$1$2$3") {
+            text = Cow::Owned(replaced);
+        }
+    }
+
+    text.into_owned()
+}
+
+/// Where in the markup an HTML or XML stripper is.
+#[derive(Debug)]
+enum TagState {
+    /// In text, which is kept.
+    Text,
+
+    /// In a tag, whose name is read until it ends.
+    Tag {
+        name: String,
+        name_done: bool,
+        closing: bool,
+        quote: Option<char>,
+        last: char,
+    },
+
+    /// In a comment.
+    Comment,
+
+    /// In a CDATA section, whose text is kept.
+    CData,
+
+    /// In an element whose content isn't kept, until its end tag.
+    Hidden(String),
+}
+
+/// Strips the tags of HTML or XML from text a line at a time. In HTML, the
+/// tags of block elements are replaced with a space, so that the words on
+/// either side of them aren't joined, and the content of scripts and styles
+/// is left out. Every line is kept, even if it's left empty, so that lines are
+/// numbered the same as in the source.
+#[derive(Debug)]
+struct TagStripper {
+    state: TagState,
+
+    /// Whether this is HTML, rather than XML.
+    html: bool,
+
+    /// Whether to leave out the content of code elements.
+    exclude_code: bool,
+}
+
+impl TagStripper {
+    fn new(html: bool, exclude_code: bool) -> TagStripper {
+        TagStripper {
+            state: TagState::Text,
+            html,
+            exclude_code,
+        }
+    }
+
+    /// Whether the content of the element with the given name is left out.
+    fn hides(&self, name: &str) -> bool {
+        self.html
+            && (HIDDEN_ELEMENTS.contains(&name)
+                || (self.exclude_code && CODE_ELEMENTS.contains(&name)))
+    }
+
+    /// Strips a line, without its line break.
+    fn strip_line(&mut self, line: &str) -> String {
+        let mut stripped = String::with_capacity(line.len());
+        let mut rest = line;
+
+        while !rest.is_empty() {
+            rest = match self.state {
+                TagState::Text => match rest.find('<') {
+                    Some(start) => {
+                        stripped.push_str(&rest[..start]);
+                        self.open(&rest[start..], &mut stripped)
+                    }
+                    None => {
+                        stripped.push_str(rest);
+                        ""
+                    }
+                },
+                TagState::Comment => match rest.find("-->") {
+                    Some(end) => {
+                        self.state = TagState::Text;
+                        &rest[end + 3..]
+                    }
+                    None => "",
+                },
+                TagState::CData => match rest.find("]]>") {
+                    Some(end) => {
+                        stripped.push_str(&rest[..end]);
+                        self.state = TagState::Text;
+                        &rest[end + 3..]
+                    }
+                    None => {
+                        stripped.push_str(rest);
+                        ""
+                    }
+                },
+                TagState::Hidden(ref name) => {
+                    let end_tag = format!("</{}", name);
+
+                    match rest.to_ascii_lowercase().find(&end_tag) {
+                        Some(end) => {
+                            self.state = TagState::Tag {
+                                name: name.clone(),
+                                name_done: true,
+                                closing: true,
+                                quote: None,
+                                last: '<',
+                            };
+                            &rest[end + end_tag.len()..]
+                        }
+                        None => "",
+                    }
+                }
+                TagState::Tag { .. } => self.read_tag(rest, &mut stripped),
+            };
+        }
+
+        // a line break ends the name of a tag
+        if let TagState::Tag {
+            ref mut name_done, ..
+        } = self.state
+        {
+            *name_done = true;
+        }
+
+        stripped
+    }
+
+    /// Starts whatever starts at the `<` at the start of `rest`, and returns
+    /// the rest after it.
+    fn open<'a>(&mut self, rest: &'a str, stripped: &mut String) -> &'a str {
+        if let Some(rest) = rest.strip_prefix("<!--") {
+            self.state = TagState::Comment;
+            return rest;
+        }
+
+        if let Some(rest) = rest.strip_prefix("<![CDATA[") {
+            self.state = TagState::CData;
+            return rest;
+        }
+
+        let after = &rest[1..];
+        let closing = after.starts_with('/');
+
+        match after.chars().next() {
+            Some(c) if c.is_alphabetic() || "/!?".contains(c) => {
+                self.state = TagState::Tag {
+                    name: String::new(),
+                    name_done: false,
+                    closing,
+                    quote: None,
+                    last: '<',
+                };
+                &after[closing as usize..]
+            }
+            // a `<` that doesn't start a tag is text
+            _ => {
+                stripped.push('<');
+                after
+            }
+        }
+    }
+
+    /// Reads the tag that `rest` is in, and returns the rest after it.
+    fn read_tag<'a>(&mut self, rest: &'a str, stripped: &mut String) -> &'a str {
+        let (name, name_done, closing, quote, last) = match self.state {
+            TagState::Tag {
+                ref mut name,
+                ref mut name_done,
+                closing,
+                ref mut quote,
+                ref mut last,
+            } => (name, name_done, closing, quote, last),
+            _ => unreachable!(),
+        };
+
+        for (i, c) in rest.char_indices() {
+            match *quote {
+                Some(q) if c == q => *quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => *quote = Some(c),
+                None if c == '>' => {
+                    let name = mem::take(name);
+                    let self_closing = *last == '/';
+
+                    if self.html && BLOCK_ELEMENTS.contains(&name.as_str()) {
+                        stripped.push(' ');
+                    }
+
+                    self.state = match !closing && !self_closing && self.hides(&name) {
+                        true => TagState::Hidden(name),
+                        false => TagState::Text,
+                    };
+
+                    return &rest[i + 1..];
+                }
+                None if !*name_done && (c.is_alphanumeric() || "-_:.".contains(c)) => {
+                    name.extend(c.to_lowercase());
+                }
+                None => *name_done = true,
+            }
+
+            *last = c;
+        }
+
+        ""
+    }
+}
+
+/// Strips the syntax of a markup language from text a line at a time.
+#[derive(Debug)]
+enum LineStripper {
+    Markdown(MarkdownStripper),
+    Tags(TagStripper),
+}
+
+impl LineStripper {
+    fn strip_line(&mut self, line: &str) -> String {
+        match self {
+            LineStripper::Markdown(stripper) => stripper.strip_line(line),
+            LineStripper::Tags(stripper) => stripper.strip_line(line),
+        }
+    }
+}
+
+/// Decodes the HTML character references in text, like `&amp;` and `&#8212;`.
+/// Named references that aren't known are kept as they are.
+fn decode_entities(text: &str) -> Cow<'_, str> {
+    ENTITY.replace_all(text, |caps: &Captures| {
+        let entity = &caps[1];
+
+        let c = match entity.strip_prefix('#') {
+            Some(num) => match num.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => num.parse().ok().and_then(char::from_u32),
+            },
+            None => named_entity(entity),
+        };
+
+        match c {
+            Some(c) => c.to_string(),
+            None => caps[0].to_string(),
+        }
+    })
+}
+
+/// Gets the character of a named HTML character reference, for the common
+/// ones.
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "times" => '×',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "cent" => '¢',
+        "sect" => '§',
+        "para" => '¶',
+        _ => return None,
+    };
+
+    Some(c)
+}
+
+/// Reads the input with the syntax of the markup language stripped. Lines that
+/// aren't valid UTF-8 are read as they are, so that they're still handled as
+/// given by `--invalid-utf8`.
+struct Stripper<R> {
+    reader: BufReader<R>,
+    stripper: LineStripper,
+
+    /// Whether to decode character references after stripping.
+    decode_entities: bool,
+
+    /// The stripped line that's being read.
+    line: Vec<u8>,
+
+    /// How much of `line` has been read.
+    pos: usize,
+}
+
+impl<R: Read> Read for Stripper<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+
+            if let Ok(line) = str::from_utf8(&self.line) {
+                let text = line.trim_end_matches(['\r', '\n']);
+                let mut stripped = self.stripper.strip_line(text);
+
+                if self.decode_entities {
+                    stripped = decode_entities(&stripped).into_owned();
+                }
+
+                stripped.push_str(&line[text.len()..]);
+                self.line = stripped.into_bytes();
+            }
+        }
+
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// Wraps the reader so that it reads the input with the syntax of the given
+/// markup language stripped, leaving the text that would be read in the
+/// rendered document. With `exclude_code`, code is left out too, and with
+/// `decode_entities`, character references like `&amp;` are decoded.
+pub fn strip<'a, R: Read + 'a>(
+    reader: R,
+    markup: Markup,
+    exclude_code: bool,
+    decode_entities: bool,
+) -> Box<dyn Read + 'a> {
+    let stripper = match markup {
+        Markup::Markdown => LineStripper::Markdown(MarkdownStripper {
+            exclude_code,
+            ..MarkdownStripper::default()
+        }),
+        Markup::Html => LineStripper::Tags(TagStripper::new(true, exclude_code)),
+        Markup::Xml => LineStripper::Tags(TagStripper::new(false, exclude_code)),
+    };
+
+    Box::new(Stripper {
+        reader: BufReader::new(reader),
+        stripper,
+        decode_entities,
+        line: Vec::new(),
+        pos: 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strip_markdown(text: &str, exclude_code: bool) -> String {
+        let mut stripped = String::new();
+        strip(text.as_bytes(), Markup::Markdown, exclude_code, false)
+            .read_to_string(&mut stripped)
+            .unwrap();
+        stripped
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let _ = env_logger::try_init();
+
+        let cases = [
+            ("## A *title* ##\n", "A title\n"),
+            ("Title\r\n=====\r\n", "Title\r\n\r\n"),
+            ("> quoted **bold** text\n", "quoted bold text\n"),
+            ("- [x] done\n1. first\n", "done\nfirst\n"),
+            ("see [the docs](https://x.y/z \"Docs\")\n", "see the docs\n"),
+            ("![a cat](cat.png) and [a ref][1]\n", "a cat and a ref\n"),
+            ("[1]: https://x.y/z\n", "\n"),
+            ("<https://x.y/z>\n", "https://x.y/z\n"),
+            (
+                "_a_ __b__ snake_case_name ~~c~~\n",
+                "a b snake_case_name c\n",
+            ),
+            (
+                "5 * 3 * 2 and \\*not emphasis\\*\n",
+                "5 * 3 * 2 and *not emphasis*\n",
+            ),
+            ("* * *\n---\n", "\n\n"),
+            ("`*code*` and ``a ` b``\n", "*code* and a ` b\n"),
+            ("no newline", "no newline"),
+        ];
+
+        for (markdown, expected) in cases.iter() {
+            assert_eq!(*expected, strip_markdown(markdown, false), "{:?}", markdown);
+        }
+    }
+
+    #[test]
+    fn test_strip_markdown_code() {
+        let _ = env_logger::try_init();
+
+        let markdown = "text `code`\n```rust\nlet *x* = 1;\n~~~\n````\nafter\n";
+
+        assert_eq!(
+            "text code\n\nlet *x* = 1;\n~~~\n\nafter\n",
+            strip_markdown(markdown, false)
+        );
+        assert_eq!("text \n\n\n\n\nafter\n", strip_markdown(markdown, true));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let _ = env_logger::try_init();
+
+        let mut stripped = Vec::new();
+        strip(&b"# a\n\xff *b*\n"[..], Markup::Markdown, false, false)
+            .read_to_end(&mut stripped)
+            .unwrap();
+        assert_eq!(b"a\n\xff *b*\n".to_vec(), stripped);
+    }
+
+    fn strip_tags(text: &str, markup: Markup, exclude_code: bool) -> String {
+        let mut stripped = String::new();
+        strip(text.as_bytes(), markup, exclude_code, true)
+            .read_to_string(&mut stripped)
+            .unwrap();
+        stripped
+    }
+
+    #[test]
+    fn test_strip_html() {
+        let _ = env_logger::try_init();
+
+        let cases = [
+            ("<p>one</p><p>two</p>\n", " one  two \n"),
+            (
+                "<b>un</b>believable <a href=\"x>y\">link</a>\n",
+                "unbelievable link\n",
+            ),
+            ("a <!-- hidden\nstill hidden --> b\n", "a \n b\n"),
+            (
+                "<script type='text/javascript'>\nif (a < b) {}\n</SCRIPT>c\n",
+                "\n\nc\n",
+            ),
+            ("<img\n  alt=\"x\"\n/>text\n", "\n\ntext\n"),
+            (
+                "1 < 2 &amp;&amp; 3 &gt; 2 &#8212; &#x41;\n",
+                "1 < 2 && 3 > 2 — A\n",
+            ),
+            ("&unknown; &#xFFFFFF;\n", "&unknown; &#xFFFFFF;\n"),
+        ];
+
+        for (html, expected) in cases.iter() {
+            assert_eq!(
+                *expected,
+                strip_tags(html, Markup::Html, false),
+                "{:?}",
+                html
+            );
+        }
+
+        let html = "<p>run <code>ls</code></p>\n<pre>\n$ ls\n</pre>\n";
+        assert_eq!(
+            " run ls \n \n$ ls\n \n",
+            strip_tags(html, Markup::Html, false)
+        );
+        assert_eq!(" run  \n \n\n \n", strip_tags(html, Markup::Html, true));
+    }
+
+    #[test]
+    fn test_strip_xml() {
+        let _ = env_logger::try_init();
+
+        let xml = "<?xml version=\"1.0\"?>\n<doc><p>a</p><![CDATA[<b>]]></doc>\n";
+        assert_eq!("\na<b>\n", strip_tags(xml, Markup::Xml, false));
+    }
+}
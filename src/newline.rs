@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read};
+use std::iter;
+use std::str::FromStr;
+
+use memchr::{Memchr2, Memchr3};
+use regex::bytes::Regex;
+
+use crate::constants::{CR, CRLF, FF, LF, LS, NEL, NEWLINES, NEWLINE_PATTERN, PS};
+
+/// The newline sequences, in the order they need to be matched in so CRLF is
+/// preferred over CR and LF on their own.
+const ORDERED_NEWLINES: [&str; 7] = [CRLF, LF, CR, NEL, FF, LS, PS];
+
+/// The longest newline sequence, in bytes.
+const MAX_NEWLINE_LEN: usize = 3;
+
+/// How much is read at a time when counting newlines in raw bytes.
+const RAW_READ_LEN: usize = 64 * 1024;
+
+/// The names of the newline sequences, as given on the command line.
+const NEWLINE_NAMES: [(&str, &str); 7] = [
+    ("crlf", CRLF),
+    ("lf", LF),
+    ("cr", CR),
+    ("nel", NEL),
+    ("ff", FF),
+    ("ls", LS),
+    ("ps", PS),
+];
+
+/// A set of newline sequences that count as line breaks.
+///
+/// CRLF is always treated as a single line break, so selecting either CR or LF
+/// selects CRLF as well.
+#[derive(Debug, Clone)]
+pub struct Newlines {
+    /// The selected newline sequences.
+    sequences: HashSet<&'static str>,
+
+    /// A pattern that matches any of the selected newline sequences.
+    pattern: Regex,
+
+    /// The selected newline sequences again, since looking through them is
+    /// faster than hashing.
+    selected: Vec<&'static str>,
+
+    /// The first bytes of the selected newline sequences that are ASCII.
+    ascii_leads: [u8; 3],
+
+    /// The first bytes of the selected newline sequences that aren't ASCII,
+    /// if any. These are searched for separately, since they show up in lots
+    /// of other characters too.
+    other_leads: Option<[u8; 2]>,
+}
+
+impl Newlines {
+    /// Determines if the given grapheme cluster is a selected newline sequence.
+    pub fn contains(&self, grapheme: &str) -> bool {
+        self.sequences.contains(grapheme)
+    }
+
+    /// A pattern that matches any of the selected newline sequences.
+    pub fn pattern(&self) -> &Regex {
+        &self.pattern
+    }
+
+    /// Finds the first selected newline sequence in `haystack`, returning
+    /// where it starts and ends. This finds the same ones as `pattern`, but
+    /// it's faster, since it only has to look for the first bytes of the
+    /// sequences.
+    pub fn find(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+        let [a, b, c] = self.ascii_leads;
+        let mut other_start = 0;
+
+        for ascii in Memchr3::new(a, b, c, haystack).chain(iter::once(haystack.len())) {
+            // the other leads only need to be looked for up to the next ASCII
+            // one
+            if let Some([d, e]) = self.other_leads {
+                for other in Memchr2::new(d, e, &haystack[other_start..ascii]) {
+                    if let Some(found) = self.match_at(haystack, other_start + other) {
+                        return Some(found);
+                    }
+                }
+
+                other_start = ascii;
+            }
+
+            if let Some(found) = self.match_at(haystack, ascii) {
+                return Some(found);
+            }
+        }
+
+        None
+    }
+
+    /// Determines if a selected newline sequence that was found could go on
+    /// into a longer one, like a CR that could be the start of a CRLF.
+    pub fn may_continue(&self, sequence: &[u8]) -> bool {
+        self.selected
+            .iter()
+            .any(|s| s.len() > sequence.len() && s.as_bytes().starts_with(sequence))
+    }
+
+    /// Gets the length of the longest selected newline sequence, in bytes.
+    pub fn max_len(&self) -> usize {
+        self.selected.iter().map(|s| s.len()).max().unwrap_or(0)
+    }
+
+    /// Counts the bytes read from `reader`, and the selected newline sequences
+    /// among them, without decoding them. Newline sequences are always
+    /// grapheme clusters of their own, so this gives the same line count as
+    /// the newline grapheme clusters of the decoded text, but the bytes don't
+    /// have to be valid UTF-8.
+    pub fn count_raw<R: Read>(&self, reader: R) -> io::Result<(usize, usize)> {
+        let mut num_newlines = 0;
+        let num_bytes = self.scan_raw(reader, |_| num_newlines += 1)?;
+        Ok((num_bytes, num_newlines))
+    }
+
+    /// Counts each of the selected newline sequences read from `reader` like
+    /// `count_raw`, by the names they're selected with, like "crlf". The ones
+    /// that aren't found are left out.
+    pub fn count_raw_by_name<R: Read>(
+        &self,
+        reader: R,
+    ) -> io::Result<BTreeMap<&'static str, usize>> {
+        let mut counts = BTreeMap::new();
+
+        self.scan_raw(reader, |newline| {
+            let (name, _) = NEWLINE_NAMES
+                .iter()
+                .find(|(_, sequence)| sequence.as_bytes() == newline)
+                .unwrap();
+            *counts.entry(*name).or_insert(0) += 1;
+        })?;
+
+        Ok(counts)
+    }
+
+    /// Finds the selected newline sequences in the bytes read from `reader`,
+    /// calling `on_newline` with each of them, and returns how many bytes were
+    /// read.
+    fn scan_raw<R, F>(&self, mut reader: R, mut on_newline: F) -> io::Result<usize>
+    where
+        R: Read,
+        F: FnMut(&[u8]),
+    {
+        let mut buf = vec![0; RAW_READ_LEN];
+        let mut carried = 0;
+        let mut num_bytes = 0;
+
+        loop {
+            let read = match reader.read(&mut buf[carried..]) {
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            num_bytes += read;
+
+            let len = carried + read;
+            let done = read == 0;
+
+            // a newline that starts this close to the end might not have been
+            // read whole yet, like the CR of a CRLF, so it's left for the next
+            // read
+            let complete_end = match done {
+                true => len,
+                false => len.saturating_sub(MAX_NEWLINE_LEN - 1),
+            };
+
+            let mut pos = 0;
+
+            while let Some((start, end)) = self.find(&buf[pos..len]) {
+                if pos + start >= complete_end {
+                    break;
+                }
+
+                on_newline(&buf[pos + start..pos + end]);
+                pos += end;
+            }
+
+            if done {
+                return Ok(num_bytes);
+            }
+
+            let resume = pos.max(complete_end);
+            buf.copy_within(resume..len, 0);
+            carried = len - resume;
+        }
+    }
+
+    /// Gets where the selected newline sequence at `start` ends, if there is
+    /// one.
+    fn match_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+        let newline = match haystack[start..] {
+            [b'\r', b'\n', ..] => CRLF,
+            [b'\r', ..] => CR,
+            [b'\n', ..] => LF,
+            [0x0C, ..] => FF,
+            [0xC2, 0x85, ..] => NEL,
+            [0xE2, 0x80, 0xA8, ..] => LS,
+            [0xE2, 0x80, 0xA9, ..] => PS,
+            _ => return None,
+        };
+
+        Some((start, start + newline.len())).filter(|_| self.selected.contains(&newline))
+    }
+
+    fn new(sequences: HashSet<&'static str>, pattern: Regex) -> Newlines {
+        let selected: Vec<&'static str> = sequences.iter().copied().collect();
+
+        let mut leads: Vec<u8> = selected
+            .iter()
+            .map(|newline| newline.as_bytes()[0])
+            .collect();
+        leads.sort_unstable();
+        leads.dedup();
+
+        let (ascii_leads, other_leads): (Vec<u8>, Vec<u8>) =
+            leads.into_iter().partition(u8::is_ascii);
+
+        // the searches take a fixed number of bytes, so repeat them to fill
+        // it up. If there aren't any ASCII ones, search for a byte that can't
+        // be in UTF-8.
+        let ascii_leads = match *ascii_leads {
+            [] => [0xFF; 3],
+            [a] => [a; 3],
+            [a, b] => [a, b, b],
+            [a, b, c] => [a, b, c],
+            _ => unreachable!("too many newline sequences"),
+        };
+
+        let other_leads = match *other_leads {
+            [] => None,
+            [d] => Some([d; 2]),
+            [d, e] => Some([d, e]),
+            _ => unreachable!("too many newline sequences"),
+        };
+
+        Newlines {
+            sequences,
+            pattern,
+            selected,
+            ascii_leads,
+            other_leads,
+        }
+    }
+}
+
+impl Default for Newlines {
+    /// All the Unicode newline sequences.
+    fn default() -> Newlines {
+        Newlines::new(NEWLINES.clone(), NEWLINE_PATTERN.clone())
+    }
+}
+
+impl FromStr for Newlines {
+    type Err = String;
+
+    /// Parses a comma-separated list of newline names: "lf", "cr", "crlf",
+    /// "nel", "ff", "ls", and "ps". "all" selects all of them, and "posix"
+    /// is an alias for "lf". A name prefixed with "-" removes that newline
+    /// from the ones selected so far, e.g., "all,-ff".
+    fn from_str(s: &str) -> Result<Newlines, String> {
+        let mut sequences = HashSet::new();
+
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let name = name.to_ascii_lowercase();
+            let (remove, name) = match name.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, name.as_str()),
+            };
+
+            let named: Vec<&str> = match name {
+                "all" => NEWLINES.iter().cloned().collect(),
+                "posix" => vec![LF],
+                _ => match NEWLINE_NAMES.iter().find(|(n, _)| *n == name) {
+                    Some((_, newline)) => vec![newline],
+                    None => return Err(format!("Unknown newline: {}", name)),
+                },
+            };
+
+            for newline in named {
+                if remove {
+                    sequences.remove(newline);
+                } else {
+                    sequences.insert(newline);
+                }
+            }
+        }
+
+        // CRLF is a single line break made of CR and LF, so it counts whenever
+        // either of them does
+        if sequences.contains(CR) || sequences.contains(LF) {
+            sequences.insert(CRLF);
+        }
+
+        if sequences.is_empty() {
+            return Err(String::from("No newlines given"));
+        }
+
+        let pattern = ORDERED_NEWLINES
+            .iter()
+            .filter(|newline| sequences.contains(*newline))
+            .map(|newline| regex::escape(newline))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        Ok(Newlines::new(sequences, Regex::new(&pattern).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::{Count, CountOptions, Counter};
+
+    #[test]
+    fn test_parse() {
+        let newlines: Newlines = "lf".parse().unwrap();
+        assert!(newlines.contains(LF));
+        assert!(newlines.contains(CRLF));
+        assert!(!newlines.contains(CR));
+        assert!(!newlines.contains(FF));
+        assert_eq!(
+            Some(1),
+            newlines.pattern().find(b"a\r\nb").map(|m| m.start())
+        );
+
+        assert!(newlines.may_continue(b"\r"));
+        assert!(!newlines.may_continue(b"\n"));
+
+        let newlines: Newlines = "crlf, ls".parse().unwrap();
+        assert!(newlines.contains(CRLF));
+        assert!(newlines.contains(LS));
+        assert!(!newlines.contains(LF));
+        assert!(newlines.pattern().find(b"a\nb").is_none());
+
+        let newlines: Newlines = "all,-ff".parse().unwrap();
+        assert!(newlines.contains(LF));
+        assert!(newlines.contains(NEL));
+        assert!(!newlines.contains(FF));
+
+        let newlines: Newlines = "all,-lf".parse().unwrap();
+        assert!(!newlines.contains(LF));
+        assert!(newlines.contains(CRLF));
+
+        assert!("".parse::<Newlines>().is_err());
+        assert!("lf,-lf".parse::<Newlines>().is_err());
+        assert!("lf,vt".parse::<Newlines>().is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let inputs = [
+            "",
+            "no newlines",
+            "a\r\nb",
+            "a\rb\nc",
+            "\r",
+            "\r\r\n",
+            "—“quotes” and dashes—\u{2028}next",
+            "nel\u{0085}ff\u{000C}ps\u{2029}",
+            "ends in a lone \r",
+        ];
+
+        for selection in &["all", "lf", "cr", "crlf", "nel,ls", "ps", "all,-lf"] {
+            let newlines: Newlines = selection.parse().unwrap();
+
+            for input in &inputs {
+                let expected = newlines
+                    .pattern()
+                    .find(input.as_bytes())
+                    .map(|m| (m.start(), m.end()));
+                assert_eq!(
+                    expected,
+                    newlines.find(input.as_bytes()),
+                    "{:?} in {:?}",
+                    selection,
+                    input
+                );
+            }
+        }
+    }
+
+    /// Reads one byte at a time, so newlines are split across reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            (&mut self.0).take(1).read(buf)
+        }
+    }
+
+    #[test]
+    fn test_count_raw() {
+        let _ = env_logger::try_init();
+
+        let input = "a\r\nb\r\r\n\u{2028}c\u{0085}\u{000C}d\u{2029}\n\r";
+        let mut invalid = input.as_bytes().to_vec();
+        invalid.extend_from_slice(b"\xE2\x80\n\xC2\r\n");
+
+        for selection in &["all", "lf", "cr", "nel,ls", "ps"] {
+            let newlines: Newlines = selection.parse().unwrap();
+            let options = CountOptions {
+                newlines: newlines.clone(),
+                ..CountOptions::default()
+            };
+            let expected = Counter::Line.count(input, &options);
+
+            let counted = newlines.count_raw(input.as_bytes()).unwrap();
+            assert_eq!((input.len(), expected), counted, "{:?}", selection);
+
+            let counted = newlines.count_raw(Trickle(input.as_bytes())).unwrap();
+            assert_eq!((input.len(), expected), counted, "{:?}", selection);
+
+            // invalid UTF-8 doesn't get in the way
+            let expected = Counter::Line.count(&String::from_utf8_lossy(&invalid), &options);
+            let counted = newlines.count_raw(Trickle(&invalid)).unwrap();
+            assert_eq!((invalid.len(), expected), counted, "{:?}", selection);
+        }
+    }
+
+    #[test]
+    fn test_count_raw_by_name() {
+        let _ = env_logger::try_init();
+
+        let input = "a\r\nb\r\r\n\u{2028}c\u{0085}\u{000C}d\u{2029}\n\r";
+        let expected: BTreeMap<&str, usize> = vec![
+            ("cr", 2),
+            ("crlf", 2),
+            ("ff", 1),
+            ("lf", 1),
+            ("ls", 1),
+            ("nel", 1),
+            ("ps", 1),
+        ]
+        .into_iter()
+        .collect();
+
+        let newlines = Newlines::default();
+        let counted = newlines
+            .count_raw_by_name(Trickle(input.as_bytes()))
+            .unwrap();
+        assert_eq!(expected, counted);
+
+        // only the selected ones are counted
+        let newlines: Newlines = "lf".parse().unwrap();
+        let counted = newlines.count_raw_by_name(input.as_bytes()).unwrap();
+        assert_eq!(
+            vec![("crlf", 2), ("lf", 1)],
+            counted.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
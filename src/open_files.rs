@@ -0,0 +1,170 @@
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+use log::*;
+use rayon::prelude::*;
+
+use crate::opt::Opt;
+
+/// The most files that are open at once when the limit on open files can't be
+/// found, or when there isn't one.
+const DEFAULT_LIMIT: usize = 512;
+
+/// Gets the most inputs that are counted at once: the number given with
+/// `--max-open-files`, or else half of the process's limit on open files, so
+/// that the rest are left for stdio, the cache, byte ranges, and the like.
+pub fn limit(opts: &Opt) -> usize {
+    match opts.max_open_files {
+        Some(max) if max > 0 => max,
+        _ => (os_limit().unwrap_or(DEFAULT_LIMIT) / 2).max(1),
+    }
+}
+
+/// Gets the soft limit on how many files the process can have open, if there
+/// is one.
+#[cfg(unix)]
+fn os_limit() -> Option<usize> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    // SAFETY: getrlimit only writes to the given struct
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        debug!("couldn't get the limit on open files");
+        return None;
+    }
+
+    match limit.rlim_cur {
+        libc::RLIM_INFINITY => None,
+        cur => Some(cur as usize),
+    }
+}
+
+/// Gets the soft limit on how many files the process can have open, if there
+/// is one. Elsewhere, it's left to the default.
+#[cfg(not(unix))]
+fn os_limit() -> Option<usize> {
+    None
+}
+
+/// Counts how many inputs are being counted, waiting for one of them to be
+/// done before another one is started once there are as many as allowed.
+struct OpenFiles {
+    max: usize,
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl OpenFiles {
+    fn new(max: usize) -> OpenFiles {
+        OpenFiles {
+            max: max.max(1),
+            open: Mutex::new(0),
+            closed: Condvar::new(),
+        }
+    }
+
+    /// Waits until another input can be counted, and counts it as open.
+    fn open(&self) {
+        let mut open = self.open.lock().unwrap();
+
+        while *open >= self.max {
+            open = self.closed.wait(open).unwrap();
+        }
+
+        *open += 1;
+    }
+
+    fn close(&self) {
+        *self.open.lock().unwrap() -= 1;
+        self.closed.notify_one();
+    }
+}
+
+/// Counts the given files with `count` on the rayon pool, along with their
+/// index, with no more than `max_open` of them counted at once. Since a thread
+/// that waits on the pieces of one input can go on to count another one, the
+/// pool alone would open as many of them as it gets to. Instead, the files are
+/// handed out to the pool from a thread of its own, which waits for one of
+/// them to be done once there are `max_open`, so that the counting threads
+/// never wait on it. Returns the results in the order the files were given.
+pub fn count_limited<I, S, T, F>(files: I, max_open: usize, count: F) -> Vec<T>
+where
+    I: IntoIterator<Item = S>,
+    I::IntoIter: Send,
+    S: AsRef<str> + Send,
+    T: Send,
+    F: Fn(usize, &str) -> T + Sync + Send,
+{
+    let open_files = &OpenFiles::new(max_open);
+    let files = files.into_iter();
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::sync_channel(0);
+
+        scope.spawn(move || {
+            for file in files.enumerate() {
+                open_files.open();
+
+                if tx.send(file).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut results: Vec<_> = rx
+            .into_iter()
+            .par_bridge()
+            .map(|(i, file): (usize, S)| {
+                let result = count(i, file.as_ref());
+                open_files.close();
+                (i, result)
+            })
+            .collect();
+
+        results.sort_unstable_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_count_limited() {
+        let _ = env_logger::try_init();
+
+        let files: Vec<String> = (0..200).map(|i| format!("input{}", i)).collect();
+        let open = AtomicUsize::new(0);
+        let most_open = AtomicUsize::new(0);
+
+        let results = count_limited(&files, 3, |i, file| {
+            let now_open = open.fetch_add(1, Ordering::SeqCst) + 1;
+            most_open.fetch_max(now_open, Ordering::SeqCst);
+
+            // the pieces of the input are counted in parallel too, so the
+            // thread can go on to other inputs while it waits for them
+            let sum: usize = (0..64).into_par_iter().map(|n| n * i).sum();
+
+            open.fetch_sub(1, Ordering::SeqCst);
+            (sum, file.to_string())
+        });
+
+        assert!(most_open.load(Ordering::SeqCst) <= 3);
+
+        let expected: Vec<_> = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (2016 * i, file.clone()))
+            .collect();
+        assert_eq!(expected, results);
+
+        let opts = Opt::from_iter(&["uwc", "--max-open-files", "8"]);
+        assert_eq!(8, limit(&opts));
+        assert!(limit(&Opt::from_iter(&["uwc"])) >= 1);
+    }
+}
@@ -1,16 +1,47 @@
 use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use structopt::clap::AppSettings;
+use encoding_rs::Encoding;
+use regex::bytes::Regex;
+use structopt::clap::{self, AppSettings};
 
-use crate::counter::{self, Counter};
+use crate::baseline::DeltaLimit;
+use crate::binary::{BinaryPolicy, Decoding};
+use crate::byte_range::ByteRange;
+use crate::code_points::CodePoint;
+use crate::comments::{self, BlockComment, LineClassifier};
+use crate::compression::Decompress;
+use crate::config::Config;
+use crate::constants::NUL_PATTERN;
+use crate::counter::{
+    self, CountOptions, Counter, GraphemeMode, LineClass, RegexCounter, WordAlgorithm,
+};
+use crate::encoding::{parse_encoding, EncodingOverride};
+use crate::fields;
+use crate::group::{self, GroupBy};
+use crate::interval::IntervalCounts;
+use crate::json_field::JsonPath;
+use crate::letter_case::LetterCase;
+use crate::links::Link;
+use crate::locale::Locale;
+use crate::markup::Markup;
+use crate::newline::Newlines;
+use crate::report::ErrorFormat;
+use crate::threshold::Threshold;
+use crate::ubufreader::{Delimiter, InvalidUtf8};
+use crate::width::WIDTH_CLASSES;
 
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "uwc",
     about = "Counts things in strings.",
     author,
-    setting = AppSettings::ColoredHelp
+    setting = AppSettings::ColoredHelp,
+    // options in UWC_OPTS can be given again on the command line
+    setting = AppSettings::AllArgsOverrideSelf
 )]
 pub struct Opt {
     /// Counts the grapheme clusters
@@ -33,6 +64,90 @@ pub struct Opt {
     #[structopt(short = "p", long = "code-points")]
     pub codepoints: bool,
 
+    /// Counts the number of pages, i.e., form feed characters. Form feeds
+    /// also count as line breaks unless excluded with `--newlines all,-ff`.
+    #[structopt(short = "P", long = "pages")]
+    pub pages: bool,
+
+    /// Counts the matches of a regex, given as NAME=REGEX, in a column named
+    /// NAME. This can be given more than once.
+    #[structopt(long = "count-regex", number_of_values = 1)]
+    pub count_regex: Vec<RegexCounter>,
+
+    /// Counts the URLs, like "https://example.com/path" or "www.example.com",
+    /// in a column named "urls". Internationalized domain names are counted
+    /// too.
+    #[structopt(long = "urls")]
+    pub urls: bool,
+
+    /// Counts the email addresses, like "jane@example.com", in a column named
+    /// "emails". Internationalized domain names are counted too.
+    #[structopt(long = "emails")]
+    pub emails: bool,
+
+    /// Counts the hashtags, like "#rust" or "#東京", in a column named
+    /// "hashtags". They have to have a letter in them, so "#1" isn't one.
+    #[structopt(long = "hashtags")]
+    pub hashtags: bool,
+
+    /// Counts the mentions, like "@jane", in a column named "mentions". Email
+    /// addresses aren't mentions.
+    #[structopt(long = "mentions")]
+    pub mentions: bool,
+
+    /// Counts the uppercase letters, like "A" or "Ω", in a column named
+    /// "uppercase".
+    #[structopt(long = "uppercase")]
+    pub uppercase: bool,
+
+    /// Counts the lowercase letters, like "a" or "ß", in a column named
+    /// "lowercase".
+    #[structopt(long = "lowercase")]
+    pub lowercase: bool,
+
+    /// Counts the titlecase letters, like "ǅ", in a column named "titlecase".
+    /// These are digraphs with only their first letter uppercase.
+    #[structopt(long = "titlecase")]
+    pub titlecase: bool,
+
+    /// Counts the characters by their East Asian width, in columns named
+    /// "narrow", "wide", and "ambiguous": the ones that take up one column in
+    /// a terminal, two columns, or either, depending on the terminal, like
+    /// "°" or "①". Control characters and combining marks aren't counted.
+    #[structopt(long = "widths")]
+    pub widths: bool,
+
+    /// Counts the replacement characters, U+FFFD, in a column named
+    /// "replacements". These usually mean the text was decoded wrong before
+    /// it got to uwc. With --invalid-utf8 replace, the ones uwc puts in for
+    /// the invalid sequences it reads are counted too, one for each sequence.
+    #[structopt(long = "replacements")]
+    pub replacements: bool,
+
+    /// Counts the code points of the private use areas, U+E000 to U+F8FF, and
+    /// planes 15 and 16, in a column named "private-use". Icon fonts and
+    /// legacy encodings leave them in text.
+    #[structopt(long = "private-use")]
+    pub private_use: bool,
+
+    /// Counts the code points that aren't assigned to anything in the version
+    /// of Unicode uwc was built with, in a column named "unassigned". These
+    /// are either from a newer version of Unicode or corrupted. The version is
+    /// given by --report-unicode-version.
+    #[structopt(long = "unassigned")]
+    pub unassigned: bool,
+
+    /// Writes the versions of Unicode that uwc's tables are from, and what
+    /// they're used for, instead of counting anything.
+    #[structopt(long = "report-unicode-version")]
+    pub report_unicode_version: bool,
+
+    /// A comma-separated list of the counters to count, like
+    /// "lines,words,graphemes", along with the ones given with the flags
+    /// above. Counters given with --count-regex can be listed by name too.
+    #[structopt(long = "counters", use_delimiter = true, require_delimiter = true)]
+    pub counters: Vec<Counter>,
+
     /// Counts everything. (The default counters are: lines, words, bytes)
     #[structopt(short = "a", long = "all")]
     pub all: bool,
@@ -41,12 +156,22 @@ pub struct Opt {
     #[structopt(short = "n", long = "no-header")]
     pub no_header: bool,
 
+    /// Print the field names on the first line, even if the config file says
+    /// not to.
+    #[structopt(long = "header", overrides_with = "no-header")]
+    pub header: bool,
+
     /// Don't print the output with elastic tabstops. Instead, fields will just be
     /// separated with hard tab characters. Use this if you want streaming output,
     /// or if you want the output to be more scriptable.
     #[structopt(short = "e", long = "no-elastic")]
     pub no_elastic: bool,
 
+    /// Print the output with elastic tabstops, even if the config file says
+    /// not to.
+    #[structopt(long = "elastic", overrides_with = "no-elastic")]
+    pub elastic: bool,
+
     /// The counting mode.
     #[structopt(
         short = "m",
@@ -63,18 +188,592 @@ pub struct Opt {
     #[structopt(long = "count-newlines")]
     pub count_newlines: bool,
 
+    /// The algorithm used to find word boundaries. "unicode" uses the default
+    /// Unicode word boundary rules, which can't split words in scripts that
+    /// don't put spaces between them, like Thai, Lao, or Khmer. "icu" uses
+    /// ICU's dictionary-based segmentation for those scripts.
+    #[structopt(long = "word-algorithm", default_value = "unicode")]
+    #[structopt(possible_values = &["unicode", "icu"])]
+    pub word_algorithm: WordAlgorithm,
+
+    /// The language of the input, e.g. "fr" or "it_IT.UTF-8", used to tailor
+    /// word counts to the language's conventions. For example, in French,
+    /// Italian, and Catalan, elided words like the "l'" in "l'homme" are
+    /// counted as separate words.
+    #[structopt(long = "locale")]
+    pub locale: Option<Locale>,
+
+    /// The flavor of grapheme clusters to count. Some older systems segment
+    /// text with the legacy rules, which split off things like spacing marks
+    /// from the characters they belong to.
+    #[structopt(long = "grapheme-mode", default_value = "extended")]
+    #[structopt(possible_values = &["extended", "legacy"])]
+    pub grapheme_mode: GraphemeMode,
+
+    /// A comma-separated list of the newline sequences that count as line
+    /// breaks, out of "lf", "cr", "crlf", "nel", "ff", "ls", and "ps". By
+    /// default, all of them do, which counts lines differently than `wc -l`
+    /// does; use "lf" (or "posix") to match it. CRLF is always a single line
+    /// break, and it counts if either "cr" or "lf" is given.
+    #[structopt(long = "newlines", default_value = "all")]
+    pub newlines: Newlines,
+
+    /// Splits the input into records on this separator instead of on newlines,
+    /// like awk's RS. A single character is taken literally; anything longer
+    /// is a regular expression. In line mode, counts are given for every
+    /// record.
+    #[structopt(long = "record-sep", parse(try_from_str = parse_record_separator))]
+    pub record_separator: Option<Regex>,
+
+    /// Splits the input into records terminated by NUL instead of by
+    /// newlines, as `find -print0` and `grep -z` produce. In line mode, records
+    /// are numbered in place of lines.
+    #[structopt(
+        short = "z",
+        long = "zero-terminated",
+        conflicts_with = "record-separator"
+    )]
+    pub zero_terminated: bool,
+
     /// How many "chunks" of the file to operate on in parallel. (As of this
     /// version, "chunks" means lines.) You probably don't need to mess with this.
     /// uwc will wait until it reads this many chunks (or the end of the file)
     /// to start counting. For normal files, you won't notice this, but if
     /// you're piping a slow command into uwc, you may wonder why it doesn't
-    /// seem to be counting anything. You can set this value lower for this case.
+    /// seem to be counting anything. You can set this value lower for this case,
+    /// or use --flush-interval.
     #[structopt(long = "chunk-size", default_value = "10000")]
     pub chunk_size: usize,
 
-    /// Sets the input file(s) to use. "-" gets treated as stdin.
-    #[structopt(default_value = "-")]
+    /// Counts whatever has been read so far once no input has arrived for this
+    /// long, like `500ms` or `2s`, instead of waiting for a whole chunk. In
+    /// line mode, the lines are printed as they're counted. This is for
+    /// piping slow commands into uwc.
+    #[structopt(long = "flush-interval", parse(try_from_str = parse_duration))]
+    pub flush_interval: Option<Duration>,
+
+    /// Writes a snapshot of the counts of every input that's still being read
+    /// once every interval, like `10s`, named after the time since the input
+    /// started, like `-:10s`. This is for counting endless inputs, like
+    /// `journalctl -f | uwc --interval 10s -l -w`.
+    #[structopt(long = "interval", parse(try_from_str = parse_duration))]
+    #[structopt(conflicts_with_all = &["follow", "watch", "flush-interval", "stream"])]
+    pub interval: Option<Duration>,
+
+    /// Which counts the snapshots of --interval have. "cumulative" gives
+    /// everything counted so far, and "delta" only what's been counted since
+    /// the last snapshot.
+    #[structopt(long = "interval-counts", default_value = "cumulative")]
+    #[structopt(possible_values = &["cumulative", "delta"])]
+    pub interval_counts: IntervalCounts,
+
+    /// In line mode, counts every line as soon as it's read, and prints its
+    /// counts right away, in order. This is for following logs, like
+    /// `tail -f log | uwc -m line --stream`.
+    #[structopt(long = "stream")]
+    pub stream: bool,
+
+    /// In line mode, writes the byte offset where every line starts, counting
+    /// from 0, in a column before its name, to jump to it with something like
+    /// `dd bs=1 skip=N`. The offsets are of the text that's counted, i.e.,
+    /// after decompressing or transcoding it.
+    #[structopt(long = "byte-offset")]
+    pub byte_offset: bool,
+
+    /// In line mode, writes every line itself after its counts, in place of
+    /// its name, like `nl` does with line numbers. This shows which lines go
+    /// over a budget at a glance.
+    #[structopt(long = "annotate")]
+    pub annotate: bool,
+
+    /// Counts only the lines from START to END of every input, given as
+    /// START:END, like `100:200`. Lines are numbered from 1, and both ends
+    /// are included; either one can be left out to count from the first line
+    /// or up to the last. The lines before the range are skipped without
+    /// being decoded, and nothing after it is read.
+    #[structopt(long = "line-range", conflicts_with = "follow")]
+    pub line_range: Option<LineRange>,
+
+    /// Counts only the bytes from START up to END of every file, given as
+    /// START:END, like `0:1048576`, to sample huge files or to pick up a scan
+    /// where it left off. Either end can be left out to count from the start
+    /// of the file or up to its end. Both ends are moved forward to the start
+    /// of the next grapheme cluster, so the bytes, code points, and grapheme
+    /// clusters of ranges that follow each other add up to the whole file's,
+    /// but a word that's split between them is counted in both. This only
+    /// works on regular files that aren't compressed or transcoded. In line
+    /// mode, the lines are numbered from the start of the range.
+    #[structopt(long = "byte-range", conflicts_with_all = &["follow", "archive", "line-range"])]
+    pub byte_range: Option<ByteRange>,
+
+    /// Splits lines longer than this many bytes into pieces that are counted
+    /// separately, so huge lines, like in minified files, don't have to be
+    /// held in memory whole. Pieces end on word boundaries where possible,
+    /// and the counts are the same as for the whole lines. 0 never splits
+    /// lines.
+    #[structopt(long = "split-len", default_value = "1048576")]
+    pub split_len: usize,
+
+    /// Keeps the files open after counting them, and waits for data to be
+    /// appended to them, like `tail -f`. Whenever they grow, the counts are
+    /// given again. Files are read as they are, without decompressing or
+    /// transcoding them.
+    #[structopt(long = "follow")]
+    pub follow: bool,
+
+    /// With --follow, how many seconds to wait between checks for appended
+    /// data.
+    #[structopt(long = "follow-interval", default_value = "1")]
+    pub follow_interval: f64,
+
+    /// Counts the files in the directories given as input like --recursive,
+    /// then watches them for changes. When files are created or modified,
+    /// they're counted again, and the counts are given again.
+    #[structopt(long = "watch", conflicts_with = "follow")]
+    pub watch: bool,
+
+    /// Checks whether the inputs are valid UTF-8 instead of counting them.
+    /// For every input, either that it's valid, or the line and byte offset
+    /// of its first invalid sequence, is given. If any input isn't valid, the
+    /// exit code is 3.
+    #[structopt(long = "check-utf8", conflicts_with_all = &["follow", "watch"])]
+    pub check_utf8: bool,
+
+    /// With --check-utf8, gives every invalid sequence, rather than only the
+    /// first.
+    #[structopt(long = "all-invalid", requires = "check-utf8")]
+    pub all_invalid: bool,
+
+    /// Checks whether the inputs each use a single newline convention instead
+    /// of counting them. For every input, how many of each line ending it has
+    /// is given, like "10 crlf, 2 lf". If any input mixes them, the exit code
+    /// is 3. Only the newlines selected with --newlines are looked for, and
+    /// form feeds don't count as line endings.
+    #[structopt(long = "check-line-endings")]
+    #[structopt(conflicts_with_all = &["follow", "watch", "check-utf8"])]
+    pub check_line_endings: bool,
+
+    /// Gives the median, 90th and 99th percentiles, and the longest of the
+    /// lengths of the lines of every input, instead of counting it. The line
+    /// breaks aren't part of the lengths.
+    #[structopt(long = "line-stats")]
+    #[structopt(conflicts_with_all = &["follow", "watch", "check-utf8", "check-line-endings"])]
+    #[structopt(conflicts_with = "concat")]
+    pub line_stats: bool,
+
+    /// The unit of the line lengths that --line-stats gives: "bytes",
+    /// "codepoints", or "graphemes".
+    #[structopt(long = "line-stats-unit", default_value = "graphemes")]
+    #[structopt(possible_values = &["bytes", "codepoints", "graphemes"])]
+    pub line_stats_unit: Counter,
+
+    /// Writes the lines whose counts satisfy this condition instead of
+    /// counting the inputs, given like "words>30", with the operators of
+    /// --fail-if. Every line is given after its file name and line number,
+    /// like "notes.txt:12:...". The line breaks aren't counted.
+    #[structopt(long = "filter")]
+    #[structopt(conflicts_with_all = &["follow", "watch", "check-utf8", "check-line-endings"])]
+    #[structopt(conflicts_with_all = &["line-stats", "concat"])]
+    pub filter: Option<Threshold>,
+
+    /// Caches the counts of the files, so that the next runs only count the
+    /// files whose size or modification time changed. The cache is kept in
+    /// the user's cache directory, unless a path is given, like
+    /// "--cache=counts.json". Only used in file mode.
+    #[structopt(long = "cache", require_equals = true)]
+    pub cache: Option<Option<PathBuf>>,
+
+    /// Fails the run if this condition holds for any file, given like
+    /// "lines>1000" or "bytes>=1048576". The operators are >, >=, <, <=, ==,
+    /// and !=. The counter is counted even if it isn't selected. This can be
+    /// given more than once.
+    #[structopt(long = "fail-if", number_of_values = 1)]
+    pub fail_if: Vec<Threshold>,
+
+    /// Fails the run if this condition holds for the total of all the files,
+    /// given like --fail-if. This can be given more than once.
+    #[structopt(long = "fail-if-total", number_of_values = 1)]
+    pub fail_if_total: Vec<Threshold>,
+
+    /// Stops counting at the first input that can't be counted, like a
+    /// directory, or a file with invalid UTF-8. The inputs that were already
+    /// being counted are still finished, and the run fails with exit code 2.
+    #[structopt(long = "strict", conflicts_with = "lenient")]
+    pub strict: bool,
+
+    /// Reports the inputs that can't be counted, but doesn't fail the run
+    /// because of them, so the exit code is 0 unless something fatal happened
+    /// or a --fail-if or --max-delta condition held.
+    #[structopt(long = "lenient")]
+    pub lenient: bool,
+
+    /// Saves the counts of every file to this JSON file, to compare later runs
+    /// against with --compare.
+    #[structopt(long = "save-baseline")]
+    pub save_baseline: Option<PathBuf>,
+
+    /// Compares the counts against the ones saved with --save-baseline, and
+    /// gives how much they changed for every file instead of the counts.
+    #[structopt(long = "compare")]
+    pub compare: Option<PathBuf>,
+
+    /// With --compare, fails if a counter changed by more than this for any
+    /// file, given like "words=500". The counter is counted even if it isn't
+    /// selected. This can be given more than once.
+    #[structopt(long = "max-delta", number_of_values = 1, requires = "compare")]
+    pub max_delta: Vec<DeltaLimit>,
+
+    /// Sums the counts of the inputs per file extension ("ext") or per the first
+    /// directory in their path ("dir"), and gives a row for every group
+    /// instead of for every input.
+    #[structopt(long = "group-by", possible_values = &["ext", "dir"])]
+    pub group_by: Option<GroupBy>,
+
+    /// Like --group-by, but groups the inputs by what a regex matches in their
+    /// path, given like "re:src/(?P<crate>[^/]+)/". The group of every input
+    /// is what the regex's first capture group matches, or the whole match if
+    /// it has none. The inputs that it doesn't match are in the "(none)" group.
+    #[structopt(long = "group-by-path", conflicts_with = "group-by")]
+    #[structopt(parse(try_from_str = group::parse_path))]
+    pub group_by_path: Option<GroupBy>,
+
+    /// Gives a row named "mean" after the total, with the average of every
+    /// count over the inputs, like the average length of a set of articles.
+    /// Only used in file mode.
+    #[structopt(long = "mean")]
+    pub mean: bool,
+
+    /// Gives rows named "min" and "max" after the total, with the smallest
+    /// and the largest of every count over the inputs, each along with the
+    /// input it's from, like which file has the most lines. Only used in file
+    /// mode.
+    #[structopt(long = "min-max")]
+    pub min_max: bool,
+
+    /// Counts all the inputs as if they were one, like `cat` would give them,
+    /// and gives a single row named "concat". Words that span the end of one
+    /// input and the start of the next are counted once, and in line mode,
+    /// the lines are numbered on from one input to the next.
+    #[structopt(long = "concat", conflicts_with_all = &["mean", "min-max"])]
+    #[structopt(conflicts_with_all = &["follow", "watch", "group-by", "archive", "byte-range"])]
+    #[structopt(conflicts_with_all = &["cache", "async-io", "group-by-path"])]
+    pub concat: bool,
+
+    /// Counts every regular file in the directories given as input, and in
+    /// their subdirectories.
+    #[structopt(short = "r", long = "recursive")]
+    pub recursive: bool,
+
+    /// When counting recursively, only descends this many levels below the
+    /// directories given as input, like `find -maxdepth`. With 1, only the
+    /// files directly in them are counted.
+    #[structopt(long = "max-depth")]
+    pub max_depth: Option<usize>,
+
+    /// When counting recursively, counts hidden files and directories too,
+    /// i.e., the ones whose names start with a ".".
+    #[structopt(long = "hidden")]
+    pub hidden: bool,
+
+    /// When counting recursively, don't skip version control and dependency
+    /// directories: .git, .hg, .svn, _darcs, and node_modules.
+    #[structopt(long = "no-default-prune")]
+    pub no_default_prune: bool,
+
+    /// When counting recursively, don't skip files that are ignored by
+    /// `.gitignore` and `.ignore` files.
+    #[structopt(long = "no-ignore")]
+    pub no_ignore: bool,
+
+    /// When counting recursively, follows symbolic links to files and
+    /// directories. Symbolic link loops are reported as errors rather than
+    /// followed forever.
+    #[structopt(long = "follow-symlinks", conflicts_with = "no-follow")]
+    pub follow_symlinks: bool,
+
+    /// Skips symbolic links, even when they're given as input. By default,
+    /// symbolic links given as input are followed, but the ones found while
+    /// counting recursively are not.
+    #[structopt(long = "no-follow")]
+    pub no_follow: bool,
+
+    /// Writes the names of the files that would be counted, one per line,
+    /// without counting them. These are the files found when counting
+    /// recursively, after the ignore rules, --max-depth, and the like, less the
+    /// ones that look binary with --binary skip.
+    #[structopt(long = "list-files")]
+    #[structopt(conflicts_with_all = &["follow", "watch", "check-utf8", "check-line-endings"])]
+    #[structopt(conflicts_with_all = &["line-stats", "filter", "concat"])]
+    pub list_files: bool,
+
+    /// When to decompress inputs compressed with gzip, xz, zstd, or bzip2.
+    /// "auto" decompresses the inputs that are compressed, "off" never does,
+    /// and "force" fails on inputs that aren't compressed.
+    #[structopt(long = "decompress", default_value = "auto")]
+    #[structopt(possible_values = &["auto", "off", "force"])]
+    pub decompress: Decompress,
+
+    /// Maps regular files into memory and counts them from there, instead of
+    /// reading them. This is usually faster for large files, but if a file is
+    /// truncated while it's being counted, uwc crashes. Files that are
+    /// decompressed, transcoded, stripped of markup, narrowed to a field or a
+    /// JSON field, or read as archives are read as usual.
+    #[structopt(long = "mmap")]
+    pub mmap: bool,
+
+    /// Splits large files into this many byte ranges, which are counted in
+    /// parallel. 0 uses as many ranges as there are threads. Ranges start
+    /// after a line break, and are at least 1 MiB long. This is only done in
+    /// file mode, for regular files that aren't decompressed, transcoded,
+    /// stripped of markup, narrowed to a field or a JSON field, or read as
+    /// archives, and whose lines aren't classified with --comment-prefix or
+    /// --block-comment.
+    #[structopt(long = "ranges", default_value = "1")]
+    pub ranges: usize,
+
+    /// How many threads to count with. By default, or with 0, there's one for
+    /// every CPU. This overrides the RAYON_NUM_THREADS environment variable.
+    #[structopt(long = "threads", conflicts_with = "sequential")]
+    pub threads: Option<usize>,
+
+    /// The most inputs that are counted at once, each of which has a file
+    /// open. By default, or with 0, it's half of the limit on open files, as
+    /// given by `ulimit -n`, so that counting many inputs doesn't fail with
+    /// "too many open files".
+    #[structopt(long = "max-open-files")]
+    pub max_open_files: Option<usize>,
+
+    /// Counts everything on a single thread, in the order it's given, so the
+    /// rows are always written in the same order. This is the same as
+    /// `--threads 1`.
+    #[structopt(long = "sequential")]
+    pub sequential: bool,
+
+    /// Writes the rows of the inputs as soon as they're counted, instead of in
+    /// the order the inputs were given. In line mode, the lines of inputs that
+    /// are counted at the same time can be mixed up. Rows come out sooner this
+    /// way, and the rows of later inputs aren't kept in memory until the ones
+    /// before them are done. Without this, --async-io writes the rows of every
+    /// input together once it's done, in the order the inputs are read in.
+    #[structopt(long = "unordered")]
+    pub unordered: bool,
+
+    /// Writes the rows of the inputs in the order the inputs were given, which
+    /// is the default. This overrides an --unordered in UWC_OPTS or in a
+    /// profile.
+    #[structopt(long = "ordered", overrides_with = "unordered")]
+    pub ordered: bool,
+
+    /// Reads small files and inputs that aren't regular files, like FIFOs,
+    /// concurrently on an async runtime, and counts them as soon as they've
+    /// been read, so slow inputs don't hold up the counting threads. Stdin and
+    /// files larger than 16 MiB are read as usual. This needs uwc to be built
+    /// with the async-io feature.
+    #[structopt(long = "async-io")]
+    pub async_io: bool,
+
+    /// Writes how long it took to count every input to stderr, along with how
+    /// many MB and lines per second were counted, and the same for all of them
+    /// at the end. With --async-io, this doesn't include the time it took to
+    /// read the inputs that were read on the async runtime.
+    #[structopt(long = "timings")]
+    pub timings: bool,
+
+    /// Shows how many bytes of the inputs have been counted on stderr, along
+    /// with how many there are, if their sizes are known, and how fast they're
+    /// counted. This is only done when stderr is a terminal.
+    #[structopt(long = "progress")]
+    pub progress: bool,
+
+    /// Writes a summary of the run to stderr at the end: how many inputs were
+    /// counted, skipped, and failed, how many errors there were, even with
+    /// --quiet, and how many bytes were counted in how long.
+    #[structopt(long = "summary")]
+    pub summary: bool,
+
+    /// Writes the status of the run to this JSON file at the end, for batch
+    /// schedulers and CI: its exit code and why, how many inputs were
+    /// counted, skipped, and failed, and how many errors there were. It's
+    /// written even if the run fails outright once the options are parsed.
+    #[structopt(long = "status-file")]
+    pub status_file: Option<PathBuf>,
+
+    /// Also writes the rows of every input to a file of its own in this
+    /// directory, named after the input's path with ".counts" added, like
+    /// "DIR/src/main.rs.counts", in the same format as they're written to
+    /// stdout. The inputs that fail don't leave a file behind.
+    #[structopt(long = "output-dir")]
+    #[structopt(conflicts_with_all = &["concat", "group-by", "group-by-path", "compare"])]
+    pub output_dir: Option<PathBuf>,
+
+    /// Transcodes the inputs from this encoding, e.g., "utf-16le", "latin1", or
+    /// "shift_jis", before counting them. Any of the labels in the WHATWG
+    /// Encoding Standard can be given. Bytes are counted after transcoding to
+    /// UTF-8.
+    #[structopt(long = "encoding", parse(try_from_str = parse_encoding))]
+    pub encoding: Option<&'static Encoding>,
+
+    /// Transcodes the inputs whose names match a glob from an encoding, given
+    /// like "*.sjis.txt=shift_jis". This can be given more than once; if more
+    /// than one glob matches, the last one wins. Inputs that don't match any
+    /// glob use --encoding.
+    #[structopt(long = "encoding-for", number_of_values = 1)]
+    pub encoding_for: Vec<EncodingOverride>,
+
+    /// Doesn't count the byte order mark (U+FEFF) at the start of an input,
+    /// if there is one.
+    #[structopt(long = "strip-bom")]
+    pub strip_bom: bool,
+
+    /// Strips the syntax of a markup language from the inputs before counting
+    /// them, so that only the text that would be read in the rendered document
+    /// is counted. "markdown" strips headings, emphasis, list markers, block
+    /// quotes, code fences, and the URLs of links and images. "html" and "xml"
+    /// strip tags, comments, and in HTML, the head, scripts, and styles. Every
+    /// line is kept, so lines are numbered as in the source, but everything
+    /// else, including bytes, is counted after stripping.
+    #[structopt(long = "markup", conflicts_with_all = &["follow", "byte-range", "field"])]
+    #[structopt(possible_values = &["markdown", "md", "html", "xml"])]
+    pub markup: Option<Markup>,
+
+    /// With --markup, leaves out code blocks and inline code too, i.e., the
+    /// content of `code` and `pre` elements in HTML.
+    #[structopt(long = "exclude-code", requires = "markup")]
+    pub exclude_code: bool,
+
+    /// With --markup, decodes character references like `&amp;` or `&#8212;`
+    /// into the characters they stand for. Named references that aren't
+    /// common are kept as they are.
+    #[structopt(long = "decode-entities", requires = "markup")]
+    pub decode_entities: bool,
+
+    /// Counts only this field of every record of delimited data, like CSV or
+    /// TSV, counting from 1. Fields in double quotes can have the separator
+    /// and line breaks in them, and "" in them stands for a double quote.
+    /// Every line is kept, so lines are numbered as in the input, but
+    /// everything else, including bytes, is counted in the field only. Header
+    /// rows are counted too, unless they're left out with --line-range 2:.
+    #[structopt(long = "field", conflicts_with_all = &["follow", "byte-range"])]
+    #[structopt(parse(try_from_str = fields::parse_field))]
+    pub field: Option<usize>,
+
+    /// The single ASCII character that separates the fields with --field,
+    /// which is a comma by default. "\t" or "tab" stands for a tab.
+    #[structopt(long = "field-sep", requires = "field")]
+    #[structopt(parse(try_from_str = fields::parse_separator))]
+    pub field_sep: Option<u8>,
+
+    /// Counts only the field at this path of every line of newline-delimited
+    /// JSON, like structured logs, given like ".message" or
+    /// ".error.details[0]". Strings are counted as their text, and other
+    /// values as their JSON. Lines that aren't JSON or don't have the field
+    /// count as empty lines, and line breaks in the field count as spaces, so
+    /// that every line of the input is still one line.
+    #[structopt(long = "json-field", conflicts_with_all = &["follow", "byte-range", "field"])]
+    pub json_field: Option<JsonPath>,
+
+    /// Classifies every line as code, a comment, or blank, and counts them in
+    /// the code-lines, comment-lines, and blank-lines columns. Lines that
+    /// start with this prefix, after any whitespace, are comments. Lines with
+    /// both code and a comment count as code. This can be given more than
+    /// once.
+    #[structopt(
+        long = "comment-prefix",
+        number_of_values = 1,
+        conflicts_with = "follow"
+    )]
+    #[structopt(parse(try_from_str = comments::parse_prefix))]
+    pub comment_prefix: Vec<String>,
+
+    /// Like --comment-prefix, but for block comments, given by their start and
+    /// end, separated by a space, like '/* */'. Lines in them count as
+    /// comments, unless they're blank. This can be given more than once.
+    #[structopt(
+        long = "block-comment",
+        number_of_values = 1,
+        conflicts_with = "follow"
+    )]
+    pub block_comment: Vec<BlockComment>,
+
+    /// What to do with invalid UTF-8. "abort" stops counting the input at the
+    /// first line with invalid UTF-8, "replace" replaces invalid sequences with
+    /// U+FFFD, "skip-line" skips the lines with invalid UTF-8, and "skip-file"
+    /// skips the whole input.
+    #[structopt(long = "invalid-utf8", default_value = "abort")]
+    #[structopt(possible_values = &["abort", "replace", "skip-line", "skip-file"])]
+    pub invalid_utf8: InvalidUtf8,
+
+    /// What to do with inputs that look binary, i.e., that have NUL bytes or a
+    /// lot of invalid UTF-8 near their start. "skip" doesn't count them at all,
+    /// "bytes" only counts their bytes, and "count" counts them like any other
+    /// input.
+    #[structopt(long = "binary", default_value = "count")]
+    #[structopt(possible_values = &["skip", "bytes", "count"])]
+    pub binary: BinaryPolicy,
+
+    /// Whether to decode the inputs as UTF-8. With "off", only the lines and
+    /// bytes are counted, over the raw bytes, like `wc -lc`, so that binary
+    /// inputs, or ones with invalid UTF-8, are counted in full. The other
+    /// counts are given as "-". Only used in file mode. The default is "on".
+    #[structopt(long = "bytes-only-decode")]
+    #[structopt(possible_values = &["on", "off"])]
+    #[structopt(conflicts_with_all = &["line-range", "interval", "filter", "line-stats"])]
+    #[structopt(conflicts_with_all = &["mean", "min-max"])]
+    pub bytes_only_decode: Option<Decoding>,
+
+    /// Counts the files inside tar and zip archives, giving a row of counts
+    /// for each of them, named like "archive.tar:path/inside". Compressed
+    /// archives, like .tar.gz, are decompressed first.
+    #[structopt(long = "archive")]
+    pub archive: bool,
+
+    /// Reads the input file names from this file, where they are separated by
+    /// NUL characters, as `find -print0` produces. If this is "-", the names
+    /// are read from stdin.
+    #[structopt(long = "files0-from", conflicts_with_all = &["files", "files-from"])]
+    pub files0_from: Option<String>,
+
+    /// Reads the input file names from this file, one per line. If this is
+    /// "-", the names are read from stdin.
+    #[structopt(long = "files-from", conflicts_with = "files")]
+    pub files_from: Option<String>,
+
+    /// Logs what uwc is doing to stderr: once for the inputs it's counting,
+    /// twice for more detail, and three times for everything. If the RUST_LOG
+    /// environment variable is set, it's used instead.
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    pub verbose: u8,
+
+    /// Doesn't report the inputs that can't be counted, like directories or
+    /// files with invalid UTF-8. They still fail the run, unless --lenient is
+    /// given.
+    #[structopt(short = "q", long = "quiet")]
+    pub quiet: bool,
+
+    /// How to write the errors with the inputs that can't be counted to
+    /// stderr. "json" writes every error as a JSON object on a line of its
+    /// own, with its "file", "line", "byte_offset", "kind", and "message", and
+    /// whether the rest of the input was "skipped".
+    #[structopt(long = "error-format", default_value = "text")]
+    #[structopt(possible_values = &["text", "json"])]
+    pub error_format: ErrorFormat,
+
+    /// Uses the options of this profile from the config file, like
+    /// `[profile.prose]`. The options given on the command line, and in
+    /// UWC_OPTS, still win.
+    // this is read before the options are parsed, with `find_profile`
+    #[allow(dead_code)]
+    #[structopt(long = "profile")]
+    pub profile: Option<String>,
+
+    /// Sets the input file(s) to use. "-" gets treated as stdin, which is also
+    /// used if no files are given.
     pub files: Vec<String>,
+
+    /// The counters that are counted when none are given, from the config
+    /// file. If there are none, `DEFAULT_COUNTERS` are counted.
+    #[structopt(skip)]
+    pub default_counters: Vec<Counter>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, StructOpt)]
@@ -98,11 +797,208 @@ impl FromStr for CountMode {
     }
 }
 
+/// A range of lines to count, given with `--line-range`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineRange {
+    /// The first line to count, numbered from 1.
+    pub first: usize,
+
+    /// The last line to count, if it isn't the last line of the input.
+    pub last: Option<usize>,
+}
+
+impl LineRange {
+    /// How many lines there are before the range.
+    pub fn lines_before(&self) -> usize {
+        self.first - 1
+    }
+
+    /// How many lines there are in the range, if it doesn't go on to the end.
+    pub fn num_lines(&self) -> Option<usize> {
+        self.last.map(|last| last + 1 - self.first)
+    }
+}
+
+impl FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LineRange, String> {
+        let err = || format!("Expected a line range like 100:200: {}", s);
+
+        let (first, last) = s.split_once(':').ok_or_else(err)?;
+
+        let first = match first {
+            "" => 1,
+            first => first.parse().map_err(|_| err())?,
+        };
+
+        let last = match last {
+            "" => None,
+            last => Some(last.parse().map_err(|_| err())?),
+        };
+
+        if first == 0 || last.is_some_and(|last| last < first) {
+            return Err(err());
+        }
+
+        Ok(LineRange { first, last })
+    }
+}
+
 impl Opt {
-    /// Gets the [`Counter`]s from the CLI options.
+    /// Parses the options from the given arguments, like `from_iter`, with the
+    /// defaults from `config` for the options that aren't given. The
+    /// counters given with `--count-regex` are registered first, since other
+    /// options, like `--fail-if`, can refer to them by name, and clap doesn't
+    /// parse the options in the order they're given.
+    pub fn parse_from<I>(args: I, config: &Config) -> Opt
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+
+        for regex in Opt::find_regex_counters(&args) {
+            if let Err(e) = regex.register() {
+                clap::Error::with_description(&e, clap::ErrorKind::ValueValidation).exit();
+            }
+        }
+
+        let matches = <Opt as structopt::StructOpt>::clap().get_matches_from(args);
+        let mut opts: Opt = structopt::StructOpt::from_clap(&matches);
+
+        if let Some(ref counters) = config.counters {
+            opts.default_counters = counters.clone();
+        }
+
+        if let Some(header) = config.header.filter(|_| !opts.header && !opts.no_header) {
+            opts.no_header = !header;
+        }
+
+        if let Some(elastic) = config.elastic.filter(|_| !opts.elastic && !opts.no_elastic) {
+            opts.no_elastic = !elastic;
+        }
+
+        if let Some(chunk_size) = config.chunk_size {
+            if matches.occurrences_of("chunk-size") == 0 {
+                opts.chunk_size = chunk_size;
+            }
+        }
+
+        opts
+    }
+
+    /// Gets how the counts of the inputs are grouped together, with
+    /// `--group-by` or `--group-by-path`.
+    pub fn grouping(&self) -> Option<&GroupBy> {
+        self.group_by.as_ref().or(self.group_by_path.as_ref())
+    }
+
+    /// Finds the profile given with `--profile` in the given arguments, which
+    /// has to be known before they're parsed, since it gives more of them. If
+    /// it's given more than once, the last one wins.
+    pub fn find_profile(args: &[OsString]) -> Option<String> {
+        let mut values = args.iter().skip(1).filter_map(|arg| arg.to_str());
+        let mut profile = None;
+
+        while let Some(arg) = values.next() {
+            match arg.strip_prefix("--profile") {
+                Some("") => profile = values.next().or(profile),
+                Some(value) => profile = value.strip_prefix('=').or(profile),
+                None if arg == "--" => break,
+                None => {}
+            }
+        }
+
+        profile.map(String::from)
+    }
+
+    /// Finds the counters given with `--count-regex` in the given arguments,
+    /// which have to be registered before they're parsed. Invalid ones are
+    /// left for clap to report.
+    fn find_regex_counters(args: &[OsString]) -> Vec<RegexCounter> {
+        let mut values = args.iter().skip(1).filter_map(|arg| arg.to_str());
+        let mut counters = Vec::new();
+
+        while let Some(arg) = values.next() {
+            let counter = match arg.strip_prefix("--count-regex") {
+                Some("") => values.next(),
+                Some(value) => value.strip_prefix('='),
+                None if arg == "--" => break,
+                None => None,
+            };
+
+            counters.extend(counter.and_then(|counter| counter.parse().ok()));
+        }
+
+        counters
+    }
+
+    /// Gets the [`Counter`]s from the CLI options. The line counters of
+    /// `--comment-prefix` and `--block-comment` are always added, along with
+    /// the counters of the `--fail-if` and `--fail-if-total` conditions and of
+    /// the `--max-delta` limits, so that they're checked, and the default
+    /// counters if no others are given.
     pub fn get_counters(&self) -> BTreeSet<Counter> {
+        let mut counters = self.get_selected_counters();
+
+        let thresholds = self.fail_if.iter().chain(&self.fail_if_total);
+        counters.extend(thresholds.map(Threshold::counter));
+        counters.extend(self.max_delta.iter().map(|limit| limit.counter));
+
+        if self.line_classifier().is_some() {
+            counters.extend(&[
+                Counter::LineClass(LineClass::Code),
+                Counter::LineClass(LineClass::Comment),
+                Counter::LineClass(LineClass::Blank),
+            ]);
+        }
+
+        counters
+    }
+
+    /// Gets the counters that are selected with the counter options.
+    fn get_selected_counters(&self) -> BTreeSet<Counter> {
         let mut counters = BTreeSet::new();
 
+        counters.extend(self.count_regex.iter().filter_map(RegexCounter::counter));
+        counters.extend(&self.counters);
+
+        let links = [
+            (self.urls, Link::Url),
+            (self.emails, Link::Email),
+            (self.hashtags, Link::Hashtag),
+            (self.mentions, Link::Mention),
+        ];
+
+        for (_, link) in links.iter().filter(|(selected, _)| *selected) {
+            counters.insert(Counter::Link(*link));
+        }
+
+        let cases = [
+            (self.uppercase, LetterCase::Upper),
+            (self.lowercase, LetterCase::Lower),
+            (self.titlecase, LetterCase::Title),
+        ];
+
+        for (_, case) in cases.iter().filter(|(selected, _)| *selected) {
+            counters.insert(Counter::LetterCase(*case));
+        }
+
+        for class in WIDTH_CLASSES.iter().filter(|_| self.widths) {
+            counters.insert(Counter::Width(*class));
+        }
+
+        let code_points = [
+            (self.replacements, CodePoint::Replacement),
+            (self.private_use, CodePoint::PrivateUse),
+            (self.unassigned, CodePoint::Unassigned),
+        ];
+
+        for (_, code_point) in code_points.iter().filter(|(selected, _)| *selected) {
+            counters.insert(Counter::CodePoint(*code_point));
+        }
+
         if self.all {
             counters.extend(&counter::ALL_COUNTERS[..]);
             return counters;
@@ -128,14 +1024,74 @@ impl Opt {
             counters.insert(Counter::CodePoints);
         }
 
+        if self.pages {
+            counters.insert(Counter::Pages);
+        }
+
         // pick some defaults if the user doesn't specify any counters
-        if counters.is_empty() {
+        if counters.is_empty() && !self.default_counters.is_empty() {
+            counters.extend(&self.default_counters);
+        } else if counters.is_empty() {
             counters.extend(&counter::DEFAULT_COUNTERS[..]);
         }
 
         counters
     }
 
+    /// Gets a classifier for the lines of an input, if any comment syntax is
+    /// given with `--comment-prefix` or `--block-comment`.
+    pub fn line_classifier(&self) -> Option<LineClassifier> {
+        if self.comment_prefix.is_empty() && self.block_comment.is_empty() {
+            return None;
+        }
+
+        Some(LineClassifier::new(
+            self.comment_prefix.clone(),
+            self.block_comment.clone(),
+        ))
+    }
+
+    /// Gets how many threads to count with, if it's been set.
+    pub fn num_threads(&self) -> Option<usize> {
+        match self.sequential {
+            true => Some(1),
+            false => self.threads,
+        }
+    }
+
+    /// Determines if the rows of the inputs are written in the order the
+    /// inputs were given.
+    pub fn writes_in_order(&self) -> bool {
+        self.ordered || !self.unordered
+    }
+
+    /// Gets the [`CountOptions`] from the CLI options.
+    pub fn get_count_options(&self) -> CountOptions {
+        CountOptions {
+            word_algorithm: self.word_algorithm,
+            locale: self.locale.clone(),
+            grapheme_mode: self.grapheme_mode,
+            newlines: self.newlines.clone(),
+        }
+    }
+
+    /// Gets the pattern that separates the records the input is split into.
+    pub fn record_pattern(&self) -> &Regex {
+        match self.record_separator {
+            Some(ref separator) => separator,
+            None if self.zero_terminated => &NUL_PATTERN,
+            None => self.newlines.pattern(),
+        }
+    }
+
+    /// Gets the delimiter that separates the records the input is split into.
+    pub fn record_delimiter(&self) -> Delimiter<'_> {
+        match self.record_separator {
+            None if !self.zero_terminated => Delimiter::Newlines(&self.newlines),
+            _ => Delimiter::pattern(self.record_pattern()),
+        }
+    }
+
     /// Determines if the input buffer should count newlines.
     pub fn should_keep_newlines(&self) -> bool {
         match self.mode {
@@ -143,4 +1099,161 @@ impl Opt {
             CountMode::Line => self.count_newlines,
         }
     }
+
+    /// Gets the encoding to transcode the input with the given name from, if
+    /// any.
+    pub fn input_encoding(&self, name: &str) -> Option<&'static Encoding> {
+        EncodingOverride::find(&self.encoding_for, name).or(self.encoding)
+    }
+}
+
+/// Parses a record separator the way awk does: a single character is taken
+/// literally, and anything longer is a regular expression.
+fn parse_record_separator(s: &str) -> Result<Regex, regex::Error> {
+    if s.chars().count() == 1 {
+        Regex::new(&regex::escape(s))
+    } else {
+        Regex::new(s)
+    }
+}
+
+/// Parses a duration given in milliseconds or seconds, like `500ms` or `1.5s`.
+/// A plain number is taken as seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (value, scale) = match s.strip_suffix("ms") {
+        Some(value) => (value, 0.001),
+        None => (s.strip_suffix('s').unwrap_or(s), 1.0),
+    };
+
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * scale).ok())
+        .ok_or_else(|| format!("Expected a duration like 500ms or 2s: {}", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let _ = env_logger::try_init();
+
+        assert_eq!(Ok(Duration::from_millis(500)), parse_duration("500ms"));
+        assert_eq!(Ok(Duration::from_secs(2)), parse_duration("2s"));
+        assert_eq!(Ok(Duration::from_millis(1500)), parse_duration("1.5"));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        let _ = env_logger::try_init();
+
+        let range: LineRange = "100:200".parse().unwrap();
+        assert_eq!((99, Some(101)), (range.lines_before(), range.num_lines()));
+
+        let range: LineRange = "5:".parse().unwrap();
+        assert_eq!((4, None), (range.lines_before(), range.num_lines()));
+
+        let range: LineRange = ":3".parse().unwrap();
+        assert_eq!((0, Some(3)), (range.lines_before(), range.num_lines()));
+
+        for s in &["", "5", "0:3", "3:2", "a:b", "-1:"] {
+            assert!(s.parse::<LineRange>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_from() {
+        let _ = env_logger::try_init();
+
+        // thresholds can be on counters that are given after them
+        let args = [
+            "uwc",
+            "--fail-if",
+            "todo>1",
+            "--count-regex=todo=TODO",
+            "--count-regex",
+            "fixme=FIXME",
+        ];
+        let opts = Opt::parse_from(&args, &Config::default());
+
+        let counters: Vec<String> = opts.get_counters().iter().map(|c| c.to_string()).collect();
+        assert_eq!(vec!["todo", "fixme"], counters);
+        assert_eq!("todo>1", opts.fail_if[0].to_string());
+
+        // and they're the same counters when they're given again
+        let again = Opt::parse_from(&args, &Config::default());
+        assert_eq!(opts.get_counters(), again.get_counters());
+
+        // so can the ones in --counters
+        let opts = Opt::parse_from(
+            &[
+                "uwc",
+                "--counters",
+                "words,done",
+                "--count-regex=done=DONE",
+                "-l",
+            ],
+            &Config::default(),
+        );
+
+        let counters: Vec<String> = opts.get_counters().iter().map(|c| c.to_string()).collect();
+        assert_eq!(vec!["lines", "words", "done"], counters);
+    }
+
+    #[test]
+    fn test_parse_from_config() {
+        let _ = env_logger::try_init();
+
+        let config = Config {
+            counters: Some(vec![Counter::Line, Counter::GraphemeCluster]),
+            header: Some(false),
+            elastic: Some(false),
+            chunk_size: Some(100),
+            ..Config::default()
+        };
+
+        let opts = Opt::parse_from(&["uwc"], &config);
+        let counters: Vec<Counter> = opts.get_counters().into_iter().collect();
+        assert_eq!(vec![Counter::Line, Counter::GraphemeCluster], counters);
+        assert!(opts.no_header);
+        assert!(opts.no_elastic);
+        assert_eq!(100, opts.chunk_size);
+
+        // the command line wins
+        let opts = Opt::parse_from(
+            &["uwc", "-w", "--header", "--elastic", "--chunk-size=5"],
+            &config,
+        );
+        let counters: Vec<Counter> = opts.get_counters().into_iter().collect();
+        assert_eq!(vec![Counter::Words], counters);
+        assert!(!opts.no_header);
+        assert!(!opts.no_elastic);
+        assert_eq!(5, opts.chunk_size);
+    }
+
+    #[test]
+    fn test_find_profile() {
+        let _ = env_logger::try_init();
+
+        let args = |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+
+        assert_eq!(None, Opt::find_profile(&args(&["uwc", "-l"])));
+        assert_eq!(
+            Some("prose".to_string()),
+            Opt::find_profile(&args(&["uwc", "--profile", "prose", "-l"]))
+        );
+        assert_eq!(
+            Some("logs".to_string()),
+            Opt::find_profile(&args(&["uwc", "--profile=prose", "--profile=logs"]))
+        );
+        assert_eq!(
+            None,
+            Opt::find_profile(&args(&["uwc", "--", "--profile=prose"]))
+        );
+    }
 }
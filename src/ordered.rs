@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async-io")]
+use failure::Error;
+
+/// Writes the rows of inputs that are counted in parallel in the order the
+/// inputs were given. The rows of the first input that isn't done yet are
+/// written as they're counted, and the ones of the inputs after it are kept
+/// until it's their turn.
+pub struct OrderedOutput {
+    state: Mutex<State>,
+    writer: Arc<Mutex<dyn Write + Send + Sync>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The index of the input whose rows are written as they're counted.
+    current: usize,
+
+    /// What the inputs after the current one have written so far.
+    buffers: HashMap<usize, Vec<u8>>,
+
+    /// The inputs after the current one that are done.
+    finished: HashSet<usize>,
+}
+
+impl OrderedOutput {
+    pub fn new(writer: Arc<Mutex<dyn Write + Send + Sync>>) -> Arc<OrderedOutput> {
+        Arc::new(OrderedOutput {
+            state: Mutex::new(State::default()),
+            writer,
+        })
+    }
+
+    /// Gets the writer for the rows of the input with the given index.
+    pub fn input_writer(self: &Arc<Self>, index: usize) -> Arc<Mutex<dyn Write + Send + Sync>> {
+        Arc::new(Mutex::new(InputWriter {
+            output: self.clone(),
+            index,
+        }))
+    }
+
+    /// Marks the input with the given index as done. If it was the current
+    /// one, the inputs after it take its place, in order, and what they've
+    /// written so far is written.
+    pub fn finish(&self, index: usize) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if index != state.current {
+            state.finished.insert(index);
+            return Ok(());
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+
+        loop {
+            state.current += 1;
+            let current = state.current;
+
+            if let Some(buffer) = state.buffers.remove(&current) {
+                writer.write_all(&buffer)?;
+            }
+
+            if !state.finished.remove(&current) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Writes the rows of one input through an [`OrderedOutput`].
+struct InputWriter {
+    output: Arc<OrderedOutput>,
+    index: usize,
+}
+
+impl Write for InputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.output.state.lock().unwrap();
+
+        if self.index == state.current {
+            return self.output.writer.lock().unwrap().write(buf);
+        }
+
+        state
+            .buffers
+            .entry(self.index)
+            .or_default()
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let state = self.output.state.lock().unwrap();
+
+        match self.index == state.current {
+            true => self.output.writer.lock().unwrap().flush(),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Counts an input with a writer that keeps its rows, and writes them all at
+/// once when it's done, so that they aren't mixed up with the rows of the
+/// inputs that are counted at the same time. This is for inputs that are
+/// counted in no particular order, so that [`OrderedOutput`] can't be used.
+#[cfg(feature = "async-io")]
+pub fn write_grouped<T, F>(
+    writer: &Arc<Mutex<dyn Write + Send + Sync>>,
+    count: F,
+) -> Result<T, Error>
+where
+    F: FnOnce(Arc<Mutex<dyn Write + Send + Sync>>) -> Result<T, Error>,
+{
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let result = count(buffer.clone());
+
+    writer.lock().unwrap().write_all(&buffer.lock().unwrap())?;
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A writer whose output can be looked at after it's been given away.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ordered_output() {
+        let _ = env_logger::try_init();
+
+        let buffer = SharedBuffer::default();
+        let output = OrderedOutput::new(Arc::new(Mutex::new(buffer.clone())));
+        let writers: Vec<_> = (0..3).map(|i| output.input_writer(i)).collect();
+
+        let written = || String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+
+        // the current input is written right away, and the others are kept
+        writeln!(writers[2].lock().unwrap(), "2a").unwrap();
+        writeln!(writers[0].lock().unwrap(), "0a").unwrap();
+        writeln!(writers[1].lock().unwrap(), "1a").unwrap();
+        assert_eq!("0a\n", written());
+
+        output.finish(2).unwrap();
+        assert_eq!("0a\n", written());
+
+        output.finish(0).unwrap();
+        assert_eq!("0a\n1a\n", written());
+
+        writeln!(writers[1].lock().unwrap(), "1b").unwrap();
+        assert_eq!("0a\n1a\n1b\n", written());
+
+        output.finish(1).unwrap();
+        assert_eq!("0a\n1a\n1b\n2a\n", written());
+    }
+
+    #[cfg(feature = "async-io")]
+    #[test]
+    fn test_write_grouped() {
+        let _ = env_logger::try_init();
+
+        let buffer = SharedBuffer::default();
+        let writer: Arc<Mutex<dyn Write + Send + Sync>> = Arc::new(Mutex::new(buffer.clone()));
+
+        let result = write_grouped(&writer, |input_writer| {
+            writeln!(input_writer.lock().unwrap(), "a")?;
+            writeln!(writer.lock().unwrap(), "other")?;
+            writeln!(input_writer.lock().unwrap(), "b")?;
+            Ok(1)
+        });
+
+        assert_eq!(1, result.unwrap());
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("other\na\nb\n", written);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use failure::{format_err, Error};
+use tabwriter::TabWriter;
+
+use crate::opt::Opt;
+use crate::{write_header, InputCounts};
+
+/// The extension added to the names of the inputs' files in `--output-dir`.
+const EXTENSION: &str = ".counts";
+
+/// Gets the path of the file in `dir` that the rows of the input with the given
+/// name are written to, which is the input's path, with `.counts` added. The
+/// root and any `..` are left out, so that it's always in `dir`.
+fn path(dir: &Path, name: &str) -> PathBuf {
+    let relative: PathBuf = Path::new(name)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+
+    let mut file_name = relative.into_os_string();
+    file_name.push(EXTENSION);
+
+    dir.join(file_name)
+}
+
+/// Creates the file at `path`, along with the directories it's in, and writes
+/// the header to it, the same way as it's written to stdout.
+fn create(path: &Path, opts: &Opt) -> Result<Box<dyn Write + Send + Sync>, Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut writer: Box<dyn Write + Send + Sync> = if opts.no_elastic {
+        Box::new(file)
+    } else {
+        Box::new(TabWriter::new(file))
+    };
+
+    if !opts.no_header {
+        write_header(
+            &mut writer,
+            &opts.get_counters(),
+            opts.byte_offset,
+            opts.annotate,
+        )?;
+    }
+
+    Ok(writer)
+}
+
+/// Writes an input's rows to stdout and to its file in `--output-dir`.
+struct Tee {
+    output: Arc<Mutex<dyn Write + Send + Sync>>,
+    file: Box<dyn Write + Send + Sync>,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.lock().unwrap().write_all(buf)?;
+        self.file.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.lock().unwrap().flush()?;
+        self.file.flush()
+    }
+}
+
+/// Counts an input with `count`, giving it a writer for its rows. With
+/// `--output-dir`, its rows are written to its own file in the directory too,
+/// in the same format as they are to stdout, with a header of their own. The
+/// inputs that fail don't leave a file behind, so that it's never taken for
+/// their counts.
+pub fn count_to_file<F>(
+    name: &str,
+    opts: &Opt,
+    output_writer: Arc<Mutex<dyn Write + Send + Sync>>,
+    count: F,
+) -> Result<InputCounts, Error>
+where
+    F: FnOnce(Arc<Mutex<dyn Write + Send + Sync>>) -> Result<InputCounts, Error>,
+{
+    let dir = match opts.output_dir {
+        Some(ref dir) => dir,
+        None => return count(output_writer),
+    };
+
+    let path = path(dir, name);
+    let file = create(&path, opts).map_err(|e| format_err!("{}: {}", path.display(), e))?;
+
+    let tee = Arc::new(Mutex::new(Tee {
+        output: output_writer,
+        file,
+    }));
+
+    let (success, rows) = count(tee.clone())?;
+
+    // only the file is flushed, since stdout is flushed once everything's
+    // been counted, so that its columns line up
+    let flushed = match success {
+        true => tee.lock().unwrap().file.flush(),
+        false => fs::remove_file(&path),
+    };
+
+    flushed.map_err(|e| format_err!("{}: {}", path.display(), e))?;
+
+    Ok((success, rows))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        let _ = env_logger::try_init();
+
+        let dir = Path::new("out");
+        assert_eq!(
+            Path::new("out/src/main.rs.counts"),
+            path(dir, "src/main.rs")
+        );
+        assert_eq!(Path::new("out/etc/passwd.counts"), path(dir, "/etc/passwd"));
+        assert_eq!(Path::new("out/a/b.counts"), path(dir, "../a/./b"));
+        assert_eq!(Path::new("out/-.counts"), path(dir, "-"));
+    }
+}
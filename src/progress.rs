@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::input;
+use crate::opt::Opt;
+
+/// How often the progress bar is drawn again.
+const DRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How many characters wide the bar itself is.
+const BAR_WIDTH: usize = 30;
+
+/// The progress of the run, if `--progress` or `--summary` is given. It's
+/// global, since the bytes are counted deep down where the inputs are
+/// read, in every way they can be read.
+static PROGRESS: OnceLock<Progress> = OnceLock::new();
+
+/// How much of the inputs has been counted.
+struct Progress {
+    /// The sizes of the inputs that are regular files counted as they are.
+    sizes: HashMap<String, u64>,
+
+    /// The sum of the sizes of the inputs, if they're all known.
+    total: Option<u64>,
+
+    start: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The bytes counted in all the inputs so far.
+    done: u64,
+
+    /// The bytes counted so far in the inputs that are being counted, by
+    /// name, which can be the name of an archive member.
+    counting: HashMap<String, u64>,
+
+    /// The input that was counted last, which is the one that's shown.
+    current: Option<String>,
+}
+
+/// Formats a number of bytes with a binary unit, like "1.5 GiB".
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut scaled = bytes;
+    let mut unit = 0;
+
+    while scaled >= 1024.0 && unit < UNITS.len() - 1 {
+        scaled /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", scaled, UNITS[unit]),
+    }
+}
+
+/// Gets how much of `total` is done, as a whole percentage.
+fn percent(done: u64, total: u64) -> u64 {
+    match total {
+        0 => 100,
+        total => (done.min(total) * 100) / total,
+    }
+}
+
+impl Progress {
+    /// Draws the progress as a single line, like
+    /// `[=====>    ] 42% 1.2 GiB / 2.9 GiB, 310.5 MiB/s, big.txt 80%`.
+    fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let secs = self.start.elapsed().as_secs_f64();
+        let rate = match secs {
+            secs if secs > 0.0 => format_bytes((state.done as f64 / secs).round()),
+            _ => format_bytes(0.0),
+        };
+
+        let mut line = match self.total {
+            Some(total) => {
+                let filled = BAR_WIDTH * percent(state.done, total) as usize / 100;
+                let bar: String = (0..BAR_WIDTH)
+                    .map(|i| match i {
+                        i if i < filled => '=',
+                        i if i == filled => '>',
+                        _ => ' ',
+                    })
+                    .collect();
+
+                format!(
+                    "[{}] {:>3}% {} / {}, {}/s",
+                    bar,
+                    percent(state.done, total),
+                    format_bytes(state.done as f64),
+                    format_bytes(total as f64),
+                    rate
+                )
+            }
+            None => format!("{}, {}/s", format_bytes(state.done as f64), rate),
+        };
+
+        let current = state
+            .current
+            .as_ref()
+            .and_then(|name| Some((name, *state.counting.get(name)?)));
+
+        if let Some((name, done)) = current {
+            line.push_str(", ");
+            line.push_str(name);
+
+            if let Some(size) = self.sizes.get(name) {
+                line.push_str(&format!(" {}%", percent(done, *size)));
+            }
+        }
+
+        line
+    }
+}
+
+/// Adds bytes that were counted in the input with the given name.
+pub fn advance(name: &str, bytes: usize) {
+    let progress = match PROGRESS.get() {
+        Some(progress) => progress,
+        None => return,
+    };
+
+    let mut state = progress.state.lock().unwrap();
+    state.done += bytes as u64;
+    *state.counting.entry(name.to_string()).or_insert(0) += bytes as u64;
+
+    if state.current.as_deref() != Some(name) {
+        state.current = Some(name.to_string());
+    }
+}
+
+/// Gets how many bytes of the inputs have been counted so far.
+pub fn bytes_done() -> u64 {
+    PROGRESS
+        .get()
+        .map_or(0, |progress| progress.state.lock().unwrap().done)
+}
+
+/// Reads from another reader, adding the bytes it reads to the progress of
+/// the input with the given name.
+pub struct Tracked<'a, R> {
+    pub reader: R,
+    pub name: &'a str,
+}
+
+impl<'a, R: Read> Read for Tracked<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        advance(self.name, read);
+        Ok(read)
+    }
+}
+
+/// Marks the given input as done, along with its archive members, if it has
+/// any. If it was counted without reading all of it, like when its size is
+/// its byte count, the rest of it is added then.
+pub fn finish(file_name: &str) {
+    let progress = match PROGRESS.get() {
+        Some(progress) => progress,
+        None => return,
+    };
+
+    let mut state = progress.state.lock().unwrap();
+    let member_prefix = format!("{}:", file_name);
+    let mut counted = 0;
+
+    state.counting.retain(|name, done| {
+        let is_input = name == file_name || name.starts_with(&member_prefix);
+        counted += if is_input { *done } else { 0 };
+        !is_input
+    });
+
+    let size = progress.sizes.get(file_name).copied().unwrap_or(0);
+    state.done += size.saturating_sub(counted);
+}
+
+/// Draws the progress bar on stderr until it's dropped, when the bar is
+/// cleared.
+pub struct ProgressBar {
+    stop: Option<mpsc::Sender<()>>,
+    drawing: Option<JoinHandle<()>>,
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(drawing) = self.drawing.take() {
+            let _ = drawing.join();
+        }
+    }
+}
+
+/// Starts keeping track of how much of the given inputs has been counted, for
+/// `--progress` and `--summary`, and with `--progress`, shows it if stderr is
+/// a terminal. Only the first call does.
+pub fn start(file_names: &[String], opts: &Opt) -> Option<ProgressBar> {
+    if !opts.progress && !opts.summary {
+        return None;
+    }
+
+    let sizes: Vec<_> = file_names
+        .iter()
+        .map(|name| (name, input::plain_file_size(name, opts.decompress)))
+        .collect();
+
+    let total = sizes.iter().map(|(_, size)| *size).sum();
+    let sizes = sizes
+        .into_iter()
+        .filter_map(|(name, size)| Some((name.clone(), size?)))
+        .collect();
+
+    let progress = Progress {
+        sizes,
+        total,
+        start: Instant::now(),
+        state: Mutex::new(State::default()),
+    };
+
+    PROGRESS.set(progress).ok()?;
+
+    if !opts.progress || !io::stderr().is_terminal() {
+        return None;
+    }
+
+    let (stop, stopped) = mpsc::channel::<()>();
+
+    let drawing = thread::spawn(move || {
+        let progress = PROGRESS.get().unwrap();
+        let stderr = io::stderr();
+
+        loop {
+            let done = !matches!(
+                stopped.recv_timeout(DRAW_INTERVAL),
+                Err(RecvTimeoutError::Timeout)
+            );
+
+            // the line is cleared first, in case the new one is shorter
+            let mut stderr = stderr.lock();
+
+            let _ = match done {
+                true => write!(stderr, "\r\x1b[K"),
+                false => write!(stderr, "\r\x1b[K{}", progress.render()),
+            };
+            let _ = stderr.flush();
+
+            if done {
+                return;
+            }
+        }
+    });
+
+    Some(ProgressBar {
+        stop: Some(stop),
+        drawing: Some(drawing),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let _ = env_logger::try_init();
+
+        let progress = Progress {
+            sizes: vec![("big.txt".to_string(), 4096)].into_iter().collect(),
+            total: Some(8192),
+            start: Instant::now(),
+            state: Mutex::new(State::default()),
+        };
+
+        {
+            let mut state = progress.state.lock().unwrap();
+            state.done = 6144;
+            state.counting.insert("big.txt".to_string(), 1024);
+            state.current = Some("big.txt".to_string());
+        }
+
+        let line = progress.render();
+        let bar = format!("[{}>{}]", "=".repeat(22), " ".repeat(7));
+        let expected = format!("{}  75% 6.0 KiB / 8.0 KiB, ", bar);
+        assert!(line.starts_with(&expected), "{}", line);
+        assert!(line.ends_with("/s, big.txt 25%"), "{}", line);
+
+        assert_eq!("512 B", format_bytes(512.0));
+        assert_eq!("1.5 GiB", format_bytes(1.5 * 1024.0 * 1024.0 * 1024.0));
+    }
+}
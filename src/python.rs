@@ -0,0 +1,92 @@
+// the code that #[pyfunction] generates converts PyErr into itself
+#![allow(clippy::useless_conversion)]
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::counter::{self, CountOptions, Counted, Counter, DEFAULT_COUNTERS};
+use crate::error::UwcError;
+
+/// Gets the counters with the given names, or the default ones, if none are
+/// given.
+fn counters_named(names: Option<Vec<String>>) -> PyResult<Vec<Counter>> {
+    match names {
+        None => Ok(DEFAULT_COUNTERS.to_vec()),
+        Some(names) => names
+            .iter()
+            .map(|name| name.parse().map_err(PyValueError::new_err))
+            .collect(),
+    }
+}
+
+impl From<UwcError> for PyErr {
+    /// I/O errors become the matching `OSError`, like `FileNotFoundError`, and
+    /// invalid UTF-8 is a `ValueError`, with where it is in its message.
+    fn from(e: UwcError) -> PyErr {
+        match e {
+            UwcError::Io { source, .. } => source.into(),
+            e => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// Gets the counts by the names of their counters, which Python gets as a
+/// dict.
+fn by_name(counts: Counted) -> BTreeMap<String, usize> {
+    counts
+        .into_iter()
+        .map(|(counter, count)| (counter.to_string(), count))
+        .collect()
+}
+
+/// Counts the counters with the given names, like "words" or "graphemes", in
+/// `text`, or the default ones, if none are given.
+#[pyfunction]
+#[pyo3(signature = (text, counters = None))]
+fn count(text: &str, counters: Option<Vec<String>>) -> PyResult<BTreeMap<String, usize>> {
+    let counters = counters_named(counters)?;
+    let counts = counter::count(&counters, text, &CountOptions::default());
+    Ok(by_name(counts))
+}
+
+/// Counts the counters with the given names in the UTF-8 file at `path`, like
+/// `count`, without reading it into memory whole.
+#[pyfunction]
+#[pyo3(signature = (path, counters = None))]
+fn count_file(path: &str, counters: Option<Vec<String>>) -> PyResult<BTreeMap<String, usize>> {
+    let counters = counters_named(counters)?;
+    let reader = BufReader::new(File::open(path)?);
+    let counts = counter::count_reader(reader, &counters, &CountOptions::default())
+        .map_err(|e| e.with_file(path))?;
+    Ok(by_name(counts))
+}
+
+#[pymodule]
+fn uwc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(count, m)?)?;
+    m.add_function(wrap_pyfunction!(count_file, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let counts = count("Μπορῶ νὰ\n", Some(vec!["words".to_string()])).unwrap();
+        assert_eq!(Some(&2), counts.get("words"));
+
+        let counts = count("a b\n", None).unwrap();
+        let names: Vec<&str> = counts.keys().map(String::as_str).collect();
+        assert_eq!(vec!["bytes", "lines", "words"], names);
+
+        assert!(count("a", Some(vec!["sentences".to_string()])).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use failure::Error;
+use log::*;
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::binary::{self, BinaryPolicy};
+use crate::constants::BOM;
+use crate::counter::{self, Counted};
+use crate::error::UwcError;
+use crate::input;
+use crate::opt::{CountMode, Opt};
+use crate::report::{self, InputError};
+use crate::ubufreader::{Delimiter, InvalidUtf8, UStrChunksIter};
+use crate::{count_binary, count_records, write_input_counts};
+
+/// The smallest range that files are split into. Files that can't be split
+/// into at least two ranges of this size are counted whole.
+const MIN_RANGE_LEN: u64 = 1 << 20;
+
+/// Where the ranges of a file are read from.
+enum Source<'a> {
+    /// A file that's mapped into memory with `--mmap`.
+    Map(Mmap),
+
+    /// A file that's opened again for every range.
+    File(&'a str),
+}
+
+impl<'a> Source<'a> {
+    /// Reads the bytes from `start` up to `end`.
+    fn reader(&self, start: u64, end: u64) -> io::Result<Box<dyn BufRead + '_>> {
+        match *self {
+            Source::Map(ref map) => Ok(Box::new(Cursor::new(&map[start as usize..end as usize]))),
+            Source::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(start))?;
+                Ok(Box::new(BufReader::new(file.take(end - start))))
+            }
+        }
+    }
+}
+
+/// Finds the end of the first delimiter from `start` on, or `None` if there
+/// isn't one before `end`.
+fn find_delimiter_end(
+    source: &Source,
+    start: u64,
+    end: u64,
+    delimiter: Delimiter,
+) -> io::Result<Option<u64>> {
+    let mut reader = source.reader(start, end)?;
+    let mut pos = start;
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            return Ok(None);
+        }
+
+        let consumed = match delimiter.find(buf) {
+            // a delimiter at the end of the buffer could be the start of a
+            // longer one, like the CR of a CRLF, so look for the next one
+            Some((_, delimiter_end)) if delimiter_end == buf.len() => delimiter_end,
+            Some((_, delimiter_end)) => return Ok(Some(pos + delimiter_end as u64)),
+            None => buf.len(),
+        };
+
+        reader.consume(consumed);
+        pos += consumed as u64;
+    }
+}
+
+/// Splits `len` bytes into at most `num_ranges` ranges of about the same size,
+/// which all start right after a delimiter, except the first.
+fn split(
+    source: &Source,
+    len: u64,
+    num_ranges: u64,
+    delimiter: Delimiter,
+) -> io::Result<Vec<(u64, u64)>> {
+    let mut bounds = vec![0];
+
+    for i in 1..num_ranges {
+        let nominal = len * i / num_ranges;
+
+        if nominal <= *bounds.last().unwrap() {
+            continue;
+        }
+
+        match find_delimiter_end(source, nominal, len, delimiter)? {
+            Some(bound) if bound < len => bounds.push(bound),
+            _ => break,
+        }
+    }
+
+    bounds.push(len);
+
+    Ok(bounds.windows(2).map(|w| (w[0], w[1])).collect())
+}
+
+/// Counts the records from `start` up to `end`, the same way they're found
+/// when they're counted.
+fn count_lines(source: &Source, start: u64, end: u64, delimiter: Delimiter) -> io::Result<usize> {
+    let mut reader = source.reader(start, end)?;
+
+    // the text doesn't matter, so don't stop at invalid UTF-8
+    UStrChunksIter::new(&mut reader, delimiter, false)
+        .invalid_utf8(InvalidUtf8::Replace)
+        .try_fold(0, |lines, piece| {
+            piece.map(|_| lines + 1).map_err(UwcError::into_io)
+        })
+}
+
+/// Counts the given file by splitting it into byte ranges with `--ranges`, and
+/// counting them in parallel. Every range but the first starts right after a
+/// line break, which is always a grapheme cluster and word boundary, so the
+/// counts are the same as if the file were counted whole.
+///
+/// Returns `None` if the file shouldn't be split, i.e., if it isn't a regular
+/// file that's counted as it is, if it's too small, or if only a range of its
+/// lines is counted. Files whose markup is stripped, that are narrowed to a
+/// field or a JSON field, or whose lines are classified with
+/// `--comment-prefix` or `--block-comment`, aren't split either, since code
+/// blocks, quoted fields, and block comments can span the ranges.
+pub fn count(
+    file_name: &str,
+    opts: &Opt,
+    output_writer: &mut Arc<Mutex<dyn Write + Send + Sync>>,
+) -> Result<Option<(bool, Option<Counted>)>, Error> {
+    let num_ranges = match opts.ranges {
+        0 => rayon::current_num_threads(),
+        num_ranges => num_ranges,
+    } as u64;
+
+    if num_ranges < 2
+        || opts.mode != CountMode::File
+        || opts.archive
+        || opts.input_encoding(file_name).is_some()
+        || opts.line_range.is_some()
+        || opts.markup.is_some()
+        || opts.field.is_some()
+        || opts.json_field.is_some()
+        || opts.line_classifier().is_some()
+        || opts.interval.is_some()
+    {
+        return Ok(None);
+    }
+
+    let len = match input::plain_file_size(file_name, opts.decompress) {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+
+    let num_ranges = num_ranges.min(len / MIN_RANGE_LEN);
+
+    if num_ranges < 2 {
+        return Ok(None);
+    }
+
+    let source = match opts.mmap {
+        true => match input::map_plain_file(file_name, opts.decompress) {
+            Some(map) => Source::Map(map),
+            None => return Ok(None),
+        },
+        false => Source::File(file_name),
+    };
+
+    let read_header = || -> io::Result<Vec<u8>> {
+        let mut header = Vec::with_capacity(binary::SNIFF_LENGTH);
+        source
+            .reader(0, len.min(binary::SNIFF_LENGTH as u64))?
+            .read_to_end(&mut header)?;
+        Ok(header)
+    };
+
+    if opts.binary != BinaryPolicy::Count {
+        let is_binary = match read_header() {
+            Ok(header) => binary::is_binary(&header),
+            Err(e) => {
+                report::input_error(opts, InputError::io(file_name, &e));
+                return Ok(Some((false, None)));
+            }
+        };
+
+        if is_binary && opts.binary == BinaryPolicy::Skip {
+            info!("skipping binary input: {}", file_name);
+            return Ok(Some((true, None)));
+        }
+
+        if is_binary {
+            return count_binary(file_name, len as usize, opts, output_writer).map(Some);
+        }
+    }
+
+    let delimiter = opts.record_delimiter();
+
+    let split_ranges = || -> io::Result<Vec<((u64, u64), usize)>> {
+        let ranges = split(&source, len, num_ranges, delimiter)?;
+
+        // the first line of every range, for error messages
+        let lines = ranges
+            .par_iter()
+            .map(|(start, end)| count_lines(&source, *start, *end, delimiter))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let first_lines = lines.iter().scan(1, |line, lines| {
+            let first_line = *line;
+            *line += lines;
+            Some(first_line)
+        });
+
+        Ok(ranges.into_iter().zip(first_lines).collect())
+    };
+
+    let ranges = match split_ranges() {
+        Ok(ranges) => ranges,
+        Err(e) => {
+            report::input_error(opts, InputError::io(file_name, &e));
+            return Ok(Some((false, None)));
+        }
+    };
+
+    debug!("counting {} in ranges: {:?}", file_name, ranges);
+
+    let results = ranges
+        .into_par_iter()
+        .map(|((start, end), first_line)| -> Result<_, Error> {
+            let mut output_writer = output_writer.clone();
+
+            let mut reader = match source.reader(start, end) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    report::input_error(opts, InputError::io(file_name, &e));
+                    return Ok((false, None));
+                }
+            };
+
+            let mut first_offset = start;
+
+            if opts.strip_bom && start == 0 {
+                match input::skip_bom(&mut reader) {
+                    Ok(true) => first_offset = BOM.len() as u64,
+                    Ok(false) => {}
+                    Err(e) => {
+                        report::input_error(opts, InputError::io(file_name, &e));
+                        return Ok((false, None));
+                    }
+                }
+            }
+
+            let start = (first_line, first_offset);
+            count_records(file_name, reader, start, opts, &mut output_writer)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let success = results.iter().all(|(success, _)| *success);
+
+    // reading stops at the first error unless lines with errors are skipped,
+    // so the ranges after it aren't counted
+    let results = results.into_iter().scan(true, |keep_going, result| {
+        let keep = *keep_going;
+        *keep_going &= result.0 || opts.invalid_utf8 == InvalidUtf8::SkipLine;
+        Some(result).filter(|_| keep)
+    });
+
+    let counts: Option<Vec<Counted>> = results.into_iter().map(|(_, counts)| counts).collect();
+
+    // a range can only have no counts with `--invalid-utf8 skip-file`, which
+    // skips the whole file
+    let counts = match counts {
+        Some(counts) => counter::sum_all_counts(&counts),
+        None => return Ok(Some((false, None))),
+    };
+
+    write_input_counts(file_name, &counts, opts, output_writer)?;
+
+    Ok(Some((success, Some(counts))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::newline::Newlines;
+    use std::fs;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_split() {
+        let _ = env_logger::try_init();
+
+        let mut map = memmap2::MmapMut::map_anon(15).unwrap();
+        map.copy_from_slice(b"one\r\ntwo\nthree\n");
+        let map = Source::Map(map.make_read_only().unwrap());
+        let newlines = Newlines::default();
+        let delimiter = Delimiter::Newlines(&newlines);
+
+        // ranges never split a CRLF
+        assert_eq!(
+            vec![(0, 5), (5, 9), (9, 15)],
+            split(&map, 15, 4, delimiter).unwrap()
+        );
+        assert_eq!(vec![(0, 15)], split(&map, 15, 1, delimiter).unwrap());
+        assert_eq!(3, count_lines(&map, 0, 15, delimiter).unwrap());
+    }
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let dir = std::env::temp_dir().join(format!("uwc-ranges-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let input = dir.join("input.txt");
+        let line = "Μπορῶ νὰ φάω σπασμένα γυαλιὰ\r\n🇫🇷 x́ can't\u{2028}";
+        fs::write(&input, line.repeat(3 * MIN_RANGE_LEN as usize / line.len())).unwrap();
+        let input = input.to_str().unwrap();
+
+        let sink: Arc<Mutex<dyn Write + Send + Sync>> = Arc::new(Mutex::new(io::sink()));
+
+        let whole_opts = Opt::from_iter(&["uwc", "-l", "-w", "-b", "-c"]);
+        let (_, whole) = crate::count_file(input, &whole_opts, sink.clone()).unwrap();
+
+        for args in &[&["--ranges", "3"][..], &["--ranges", "3", "--mmap"][..]] {
+            let opts = Opt::from_iter(["uwc", "-l", "-w", "-b", "-c"].iter().chain(*args));
+            let (success, counts) = count(input, &opts, &mut sink.clone()).unwrap().unwrap();
+
+            assert!(success);
+            assert_eq!(Some(&whole[0].1), counts.as_ref());
+        }
+
+        // small files aren't split
+        let opts = Opt::from_iter(&["uwc", "--ranges", "3"]);
+        fs::write(input, line).unwrap();
+        assert!(count(input, &opts, &mut sink.clone()).unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
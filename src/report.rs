@@ -0,0 +1,205 @@
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+use crate::error::{Location, UwcError};
+use crate::opt::Opt;
+
+/// How the errors with inputs are written to stderr.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A line of text for every error, like `file:line: message`.
+    Text,
+
+    /// A JSON object on a line of its own for every error, with the parts of
+    /// the error in fields.
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ErrorFormat, String> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Unknown error format: {}", s)),
+        }
+    }
+}
+
+/// An error with an input that isn't fatal to the run, like an input that
+/// couldn't be opened, or a line with invalid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InputError {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub byte_offset: Option<u64>,
+
+    /// What went wrong, for telling errors apart without parsing their
+    /// messages, like "not-found", "is-directory", or "invalid-utf8".
+    pub kind: &'static str,
+
+    pub message: String,
+
+    /// Whether the rest of the input wasn't counted because of the error.
+    pub skipped: bool,
+}
+
+impl InputError {
+    pub fn new<D: Display>(file: Option<&str>, kind: &'static str, message: D) -> InputError {
+        InputError {
+            file: file.map(String::from),
+            line: None,
+            byte_offset: None,
+            kind,
+            message: message.to_string(),
+            skipped: false,
+        }
+    }
+
+    /// An error reading or opening the given input.
+    pub fn io(file: &str, e: &io::Error) -> InputError {
+        InputError::new(Some(file), io_kind(e), e)
+    }
+
+    /// An error reading an input, which knows where in the input it occurred.
+    pub fn read(e: &UwcError) -> InputError {
+        let kind = match e {
+            UwcError::Io { source, .. } => io_kind(source),
+            UwcError::Utf8 { .. } => "invalid-utf8",
+        };
+
+        let location = e.location();
+
+        InputError {
+            line: location.line,
+            byte_offset: location.byte_offset,
+            ..InputError::new(location.file.as_deref(), kind, e.message())
+        }
+    }
+
+    /// An error walking a directory, like a symbolic link loop.
+    pub fn walk(e: &ignore::Error) -> InputError {
+        let kind = match e.io_error() {
+            Some(source) => io_kind(source),
+            None if is_loop(e) => "symlink-loop",
+            None => "walk",
+        };
+
+        InputError::new(None, kind, e)
+    }
+
+    /// Marks the rest of the input as not counted because of the error.
+    pub fn skipping(mut self) -> InputError {
+        self.skipped = true;
+        self
+    }
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let location = Location {
+            file: self.file.clone(),
+            line: self.line,
+            byte_offset: None,
+        };
+
+        write!(f, "{}{}", location, self.message)?;
+
+        if self.skipped {
+            write!(f, ", skipping")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets the kind of an I/O error, named like the `InputError` kinds.
+fn io_kind(e: &io::Error) -> &'static str {
+    match e.kind() {
+        io::ErrorKind::NotFound => "not-found",
+        io::ErrorKind::PermissionDenied => "permission-denied",
+        io::ErrorKind::IsADirectory => "is-directory",
+        io::ErrorKind::InvalidData => "invalid-data",
+        io::ErrorKind::UnexpectedEof => "unexpected-eof",
+        _ => "io",
+    }
+}
+
+/// Determines if a walking error is a symbolic link loop, which can be wrapped
+/// in the path or depth it was found at.
+fn is_loop(e: &ignore::Error) -> bool {
+    match e {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+        _ => false,
+    }
+}
+
+/// How many errors with inputs there have been, including the ones that
+/// weren't written with `--quiet`.
+static NUM_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Gets how many errors with inputs there have been so far.
+pub fn num_errors() -> usize {
+    NUM_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Writes an error with an input to stderr in the format given with
+/// `--error-format`, unless `--quiet` is given.
+pub fn input_error(opts: &Opt, error: InputError) {
+    NUM_ERRORS.fetch_add(1, Ordering::Relaxed);
+
+    if opts.quiet {
+        return;
+    }
+
+    match opts.error_format {
+        ErrorFormat::Text => eprintln!("{}", error),
+        ErrorFormat::Json => match serde_json::to_string(&error) {
+            Ok(json) => eprintln!("{}", json),
+            Err(_) => eprintln!("{}", error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_input_error() {
+        let _ = env_logger::try_init();
+
+        let e = io::Error::from(io::ErrorKind::NotFound);
+        let error = InputError::io("nofile", &e);
+        assert_eq!("not-found", error.kind);
+        assert_eq!(format!("nofile: {}", e), error.to_string());
+
+        let bytes = vec![b'a', 0xff];
+        let source = std::str::from_utf8(&bytes).unwrap_err();
+        let e = UwcError::utf8(source)
+            .at_byte(3)
+            .with_file("input")
+            .with_line(2);
+        let error = InputError::read(&e).skipping();
+        assert_eq!(format!("{}, skipping", e), error.to_string());
+
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!("input", json["file"]);
+        assert_eq!(2, json["line"]);
+        assert_eq!(3, json["byte_offset"]);
+        assert_eq!("invalid-utf8", json["kind"]);
+        assert_eq!(true, json["skipped"]);
+        assert!(json["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("read non-utf8 bytes at byte 3: "));
+    }
+}
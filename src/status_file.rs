@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use failure::{format_err, Error};
+use serde::Serialize;
+
+use crate::report;
+use crate::summary::Summary;
+use crate::{exit_code, Status};
+
+/// The version of the format of the status file, which goes up when fields
+/// are changed or removed, but not when they're added.
+const VERSION: u32 = 1;
+
+/// The status of a run, as written with `--status-file`.
+#[derive(Serialize)]
+struct RunStatus<'a> {
+    version: u32,
+    exit_code: i32,
+
+    /// Why the run exited with its code: "success", "inputs-failed",
+    /// "assertion-failed", or "fatal".
+    reason: &'static str,
+
+    /// The error the run failed with, if it failed outright.
+    error: Option<String>,
+
+    inputs: &'a Summary,
+    errors: usize,
+}
+
+/// Writes the status of the run with the given result to the file at the
+/// given path, with what happened to its inputs.
+pub fn write(
+    path: &Path,
+    run_result: &Result<Status, Error>,
+    summary: &Summary,
+) -> Result<(), Error> {
+    let reason = match run_result {
+        Ok(Status::Success) => "success",
+        Ok(Status::InputsFailed) => "inputs-failed",
+        Ok(Status::AssertionFailed) => "assertion-failed",
+        Err(_) => "fatal",
+    };
+
+    let status = RunStatus {
+        version: VERSION,
+        exit_code: exit_code(run_result),
+        reason,
+        error: run_result.as_ref().err().map(|e| e.to_string()),
+        inputs: summary,
+        errors: report::num_errors(),
+    };
+
+    let write = || -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &status)?;
+        writeln!(writer)?;
+        Ok(writer.flush()?)
+    };
+
+    write().map_err(|e| format_err!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::counter::Counted;
+use crate::progress;
+use crate::report;
+
+/// What happened to the inputs of a run, for `--summary` and `--status-file`.
+#[derive(Serialize)]
+pub struct Summary {
+    #[serde(skip)]
+    start: Instant,
+
+    /// The inputs that were counted without errors.
+    counted: usize,
+
+    /// The inputs that were skipped without errors, like binary inputs with
+    /// `--binary skip`, or the ones left with `--strict` after one failed.
+    skipped: usize,
+
+    /// The inputs that had errors, even if they were still counted partly.
+    failed: usize,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            start: Instant::now(),
+            counted: 0,
+            skipped: 0,
+            failed: 0,
+        }
+    }
+
+    /// Adds an input, from whether it was counted without errors and its rows.
+    pub fn add(&mut self, success: bool, rows: &[(String, Counted)]) {
+        match (success, rows.is_empty()) {
+            (false, _) => self.failed += 1,
+            (true, true) => self.skipped += 1,
+            (true, false) => self.counted += 1,
+        }
+    }
+
+    fn render(&self, errors: usize, bytes: u64, elapsed: Duration) -> String {
+        format!(
+            "{} counted, {} skipped, {} failed, {} errors, {} in {:.2}s",
+            self.counted,
+            self.skipped,
+            self.failed,
+            errors,
+            progress::format_bytes(bytes as f64),
+            elapsed.as_secs_f64()
+        )
+    }
+
+    /// Writes the summary to stderr.
+    pub fn write(&self) -> io::Result<()> {
+        let summary = self.render(
+            report::num_errors(),
+            progress::bytes_done(),
+            self.start.elapsed(),
+        );
+        writeln!(io::stderr(), "{}", summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let _ = env_logger::try_init();
+
+        let mut summary = Summary::new();
+        let rows = vec![("a.txt".to_string(), Counted::new())];
+
+        summary.add(true, &rows);
+        summary.add(true, &rows);
+        summary.add(true, &[]);
+        summary.add(false, &rows);
+
+        assert_eq!(
+            "2 counted, 1 skipped, 1 failed, 3 errors, 1.5 KiB in 1.25s",
+            summary.render(3, 1536, Duration::from_millis(1250))
+        );
+    }
+}
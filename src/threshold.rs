@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::counter::{Counted, Counter};
+
+/// A comparison operator in a threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+/// The operators, with the two-character ones first so that they're matched
+/// before their prefixes.
+const OPS: [(&str, Op); 6] = [
+    (">=", Op::Ge),
+    ("<=", Op::Le),
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    (">", Op::Gt),
+    ("<", Op::Lt),
+];
+
+impl Op {
+    fn holds(self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Op::Gt => lhs > rhs,
+            Op::Ge => lhs >= rhs,
+            Op::Lt => lhs < rhs,
+            Op::Le => lhs <= rhs,
+            Op::Eq => lhs == rhs,
+            Op::Ne => lhs != rhs,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        OPS.iter().find(|(_, op)| *op == self).unwrap().0
+    }
+}
+
+/// A condition on a count that fails the run when it holds, given like
+/// `lines>1000` or `bytes>=1048576`, or that picks the lines `--filter` gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold {
+    counter: Counter,
+    op: Op,
+    value: usize,
+}
+
+impl Threshold {
+    /// Checks whether the condition holds for the given counts. Counters that
+    /// weren't counted never violate a threshold.
+    pub fn is_violated(&self, counts: &Counted) -> bool {
+        counts
+            .get(&self.counter)
+            .is_some_and(|count| self.holds(*count))
+    }
+
+    /// Checks whether the condition holds for the given count of its counter.
+    pub fn holds(&self, count: usize) -> bool {
+        self.op.holds(count, self.value)
+    }
+
+    /// The counter that the condition is on.
+    pub fn counter(&self) -> Counter {
+        self.counter
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.counter, self.op.as_str(), self.value)
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Threshold, String> {
+        let op_start = s
+            .find(['<', '>', '=', '!'])
+            .ok_or_else(|| format!("Expected COUNTER<OP>N, like lines>1000: {}", s))?;
+
+        let counter = s[..op_start].trim().parse()?;
+        let rest = &s[op_start..];
+
+        let (op_str, op) = OPS
+            .iter()
+            .find(|(op_str, _)| rest.starts_with(op_str))
+            .ok_or_else(|| format!("Unknown operator: {}", rest))?;
+
+        let value = rest[op_str.len()..].trim();
+        let value = value.parse().map_err(|e| format!("{}: {}", value, e))?;
+
+        Ok(Threshold {
+            counter,
+            op: *op,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_threshold() {
+        let _ = env_logger::try_init();
+
+        let counts: Counted = vec![(Counter::Line, 1000), (Counter::NumByte, 2048)]
+            .into_iter()
+            .collect();
+
+        let violated = |s: &str| s.parse::<Threshold>().unwrap().is_violated(&counts);
+        assert!(!violated("lines>1000"));
+        assert!(violated("lines>=1000"));
+        assert!(violated("lines == 1000"));
+        assert!(!violated("lines!=1000"));
+        assert!(violated("bytes<4096"));
+        assert!(!violated("bytes<=1024"));
+
+        // counters that weren't counted are never violated
+        assert!(!violated("words<1"));
+
+        let threshold: Threshold = "words>30".parse().unwrap();
+        assert!(threshold.holds(31));
+        assert!(!threshold.holds(30));
+
+        assert_eq!(
+            "lines>=1000",
+            "lines >= 1000".parse::<Threshold>().unwrap().to_string()
+        );
+
+        assert!("lines".parse::<Threshold>().is_err());
+        assert!("lines=>1".parse::<Threshold>().is_err());
+        assert!("sentences>1".parse::<Threshold>().is_err());
+        assert!("lines>many".parse::<Threshold>().is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use failure::Error;
+use tabwriter::TabWriter;
+
+use crate::counter::{Counted, Counter};
+use crate::input::STDIN_IDENTIFIER;
+use crate::InputCounts;
+
+/// How long it took to count an input, and how much was counted.
+#[derive(Debug)]
+struct Timing {
+    name: String,
+    elapsed: Duration,
+
+    /// The bytes counted, or the size of the file if they weren't.
+    bytes: Option<usize>,
+
+    /// The lines counted, if they were.
+    lines: Option<usize>,
+}
+
+impl Timing {
+    fn new(name: &str, elapsed: Duration, rows: &[(String, Counted)]) -> Timing {
+        let total = |counter| -> Option<usize> {
+            if rows.is_empty() {
+                return None;
+            }
+
+            rows.iter()
+                .map(|(_, counts)| counts.get(&counter).copied())
+                .sum()
+        };
+
+        let file_size = || {
+            Some(name)
+                .filter(|name| *name != STDIN_IDENTIFIER)
+                .and_then(|name| fs::metadata(name).ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len() as usize)
+        };
+
+        Timing {
+            name: name.to_string(),
+            elapsed,
+            bytes: total(Counter::NumByte).or_else(file_size),
+            lines: total(Counter::Line),
+        }
+    }
+
+    /// Writes a row of the report for this timing.
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let secs = self.elapsed.as_secs_f64();
+        let per_sec = |count: Option<usize>, scale: f64, precision: usize| match count {
+            Some(count) if secs > 0.0 => format!("{:.*}", precision, count as f64 / scale / secs),
+            _ => "-".to_string(),
+        };
+
+        writeln!(
+            writer,
+            "{:.6}\t{}\t{}\t{}",
+            secs,
+            per_sec(self.bytes, 1e6, 1),
+            per_sec(self.lines, 1.0, 0),
+            self.name
+        )
+    }
+}
+
+/// The timings of the inputs counted with `--timings`.
+#[derive(Debug)]
+pub struct Timings {
+    start: Instant,
+    inputs: Mutex<Vec<Timing>>,
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings {
+            start: Instant::now(),
+            inputs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Writes the report of the timings to stderr, with the slowest inputs
+    /// first, and the time it took to count all of them at the end.
+    pub fn write_report(self) -> Result<(), Error> {
+        let elapsed = self.start.elapsed();
+        let mut inputs = self.inputs.into_inner().unwrap();
+        inputs.sort_by_key(|timing| Reverse(timing.elapsed));
+
+        let sum = |count: fn(&Timing) -> Option<usize>| inputs.iter().map(count).sum();
+
+        let total = Timing {
+            name: "total".to_string(),
+            elapsed,
+            bytes: sum(|timing| timing.bytes),
+            lines: sum(|timing| timing.lines),
+        };
+
+        let mut writer = TabWriter::new(io::stderr());
+        writeln!(writer, "seconds\tMB/s\tlines/s\tfilename")?;
+
+        for timing in inputs.iter().chain(Some(&total)) {
+            timing.write(&mut writer)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Counts an input with `count`, and records how long it took if there are
+/// timings.
+pub fn time<F>(timings: Option<&Timings>, name: &str, count: F) -> Result<InputCounts, Error>
+where
+    F: FnOnce() -> Result<InputCounts, Error>,
+{
+    let timings = match timings {
+        Some(timings) => timings,
+        None => return count(),
+    };
+
+    let start = Instant::now();
+    let (success, rows) = count()?;
+    let timing = Timing::new(name, start.elapsed(), &rows);
+    timings.inputs.lock().unwrap().push(timing);
+
+    Ok((success, rows))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timing() {
+        let counts: Counted = vec![(Counter::Line, 300), (Counter::NumByte, 2_000_000)]
+            .into_iter()
+            .collect();
+        let rows = vec![("a".to_string(), counts.clone()), ("b".to_string(), counts)];
+
+        let timing = Timing::new("in", Duration::from_millis(500), &rows);
+        assert_eq!(Some(4_000_000), timing.bytes);
+        assert_eq!(Some(600), timing.lines);
+
+        let mut out = Vec::new();
+        timing.write(&mut out).unwrap();
+        assert_eq!("0.500000\t8.0\t1200\tin\n", String::from_utf8(out).unwrap());
+
+        // nothing was counted or read
+        let timing = Timing::new("-", Duration::from_millis(500), &[]);
+        assert_eq!(None, timing.bytes);
+        assert_eq!(None, timing.lines);
+    }
+}
@@ -1,15 +1,296 @@
-use std::io::BufRead;
+use std::io::{self, BufRead};
 use std::mem;
+use std::str::{self, FromStr};
+use std::sync::{Arc, Mutex};
 
+use regex::bytes::Regex;
+use regex_syntax::ParserBuilder;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::counter::CONTEXT_GRAPHEMES;
 use crate::error::{Result, UwcError};
+use crate::newline::Newlines;
+
+/// What to do with chunks that aren't valid UTF-8.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InvalidUtf8 {
+    /// Give an error for the chunk and stop reading.
+    #[default]
+    Abort,
+
+    /// Replace invalid sequences with U+FFFD REPLACEMENT CHARACTER.
+    Replace,
+
+    /// Give an error for the chunk, but keep reading the chunks after it.
+    SkipLine,
+
+    /// Give an error for the chunk and stop reading. The caller is expected to
+    /// discard the chunks that were already read.
+    SkipFile,
+}
+
+impl FromStr for InvalidUtf8 {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<InvalidUtf8, String> {
+        match s {
+            "abort" => Ok(InvalidUtf8::Abort),
+            "replace" => Ok(InvalidUtf8::Replace),
+            "skip-line" => Ok(InvalidUtf8::SkipLine),
+            "skip-file" => Ok(InvalidUtf8::SkipFile),
+            _ => Err(format!("Unknown invalid UTF-8 mode: {}", s)),
+        }
+    }
+}
+
+/// What separates the records that `UStrChunksIter` reads.
+#[derive(Debug, Clone, Copy)]
+pub enum Delimiter<'a> {
+    /// Newline sequences. These are found without a regex, since they're
+    /// usually everywhere.
+    Newlines(&'a Newlines),
+
+    /// Anything matched by a pattern, along with the length of its longest
+    /// match, if there's a limit to it.
+    Pattern(&'a Regex, Option<usize>),
+}
+
+impl<'a> Delimiter<'a> {
+    /// Makes a delimiter out of anything matched by `pattern`.
+    pub fn pattern(pattern: &'a Regex) -> Delimiter<'a> {
+        Delimiter::Pattern(pattern, max_match_len(pattern))
+    }
+
+    /// Finds the first delimiter in `haystack`, returning where it starts and
+    /// ends.
+    pub fn find(self, haystack: &[u8]) -> Option<(usize, usize)> {
+        match self {
+            Delimiter::Newlines(newlines) => newlines.find(haystack),
+            Delimiter::Pattern(pattern, _) => pattern.find(haystack).map(|m| (m.start(), m.end())),
+        }
+    }
+
+    /// Determines if a delimiter that was found could go on past where it was
+    /// found. A match of a pattern could unless it's already as long as the
+    /// pattern's longest match, like the NUL of `-z`.
+    fn may_continue(self, delimiter: &[u8]) -> bool {
+        match self {
+            Delimiter::Newlines(newlines) => newlines.may_continue(delimiter),
+            Delimiter::Pattern(_, Some(max_len)) => delimiter.len() < max_len,
+            Delimiter::Pattern(_, None) => true,
+        }
+    }
+
+    /// How many bytes at the end of a buffer without a delimiter in it could
+    /// be the start of one that ends in the next buffer, like the first two
+    /// bytes of a U+2028 LINE SEPARATOR.
+    fn carry_len(self) -> usize {
+        match self {
+            Delimiter::Newlines(newlines) => newlines.max_len().saturating_sub(1),
+            Delimiter::Pattern(_, Some(max_len)) => max_len.saturating_sub(1),
+            Delimiter::Pattern(_, None) => MAX_DELIMITER_PEEK,
+        }
+    }
+
+    /// Finds the first delimiter that starts in `tail`, the end of the bytes
+    /// before `buffer`, and ends in `buffer`, returning how many of its bytes
+    /// are in `tail`, and where it ends in `buffer`.
+    fn find_across(self, tail: &[u8], buffer: &[u8]) -> Option<(usize, usize)> {
+        if tail.is_empty() {
+            return None;
+        }
+
+        let mut peek = buffer.len().min(MAX_DELIMITER_PEEK);
+
+        loop {
+            let mut joined = tail.to_vec();
+            joined.extend_from_slice(&buffer[..peek]);
+
+            let (start, end) = self
+                .find(&joined)
+                .filter(|(start, end)| *start < tail.len() && *end > tail.len())?;
+
+            // a delimiter that goes on to the end of what was looked at could
+            // go on in the rest of the buffer
+            if end == joined.len() && peek < buffer.len() && self.may_continue(&joined[start..]) {
+                peek = buffer.len();
+                continue;
+            }
+
+            return Some((tail.len() - start, end - tail.len()));
+        }
+    }
+
+    /// Finds the first delimiter in `buffer`, or one that starts at the end of
+    /// `before`, which holds the bytes read before it. The bytes of `buffer`
+    /// that come before the delimiter are added to `before`, and the bytes of
+    /// the delimiter are taken out of it. Returns the delimiter, along with
+    /// where it ends in `buffer`.
+    fn find_after(self, before: &mut Vec<u8>, buffer: &[u8]) -> Option<(Vec<u8>, usize)> {
+        let carried = before.len().min(self.carry_len());
+        let tail = &before[before.len() - carried..];
+
+        if let Some((in_before, end)) = self.find_across(tail, buffer) {
+            let mut delimiter = before.split_off(before.len() - in_before);
+            delimiter.extend_from_slice(&buffer[..end]);
+            return Some((delimiter, end));
+        }
+
+        let (start, end) = self.find(buffer)?;
+        before.extend_from_slice(&buffer[..start]);
+
+        Some((buffer[start..end].to_vec(), end))
+    }
+}
+
+/// Gets the length of the longest string that `pattern` can match, if there is
+/// a limit to it.
+fn max_match_len(pattern: &Regex) -> Option<usize> {
+    ParserBuilder::new()
+        .utf8(false)
+        .build()
+        .parse(pattern.as_str())
+        .ok()?
+        .properties()
+        .maximum_len()
+}
+
+/// The most bytes that are taken from the reader at once.
+const MAX_READ_LEN: usize = 64 * 1024;
+
+/// How many bytes of the next buffer are looked at to see if a delimiter at the
+/// end of a buffer continues in it.
+const MAX_DELIMITER_PEEK: usize = 16;
+
+/// How many bytes at the end of a long record are searched for a place to split
+/// it.
+const SPLIT_WINDOW: usize = 4096;
+
+/// How many word boundaries to leave on either side of a split. Whether a
+/// boundary is one can depend on the characters around it, so the ones at the
+/// edges of the searched bytes can't be trusted.
+const SPLIT_CONTEXT: usize = 3;
+
+/// The most buffers that a `BufferPool` keeps.
+const MAX_POOLED_BUFFERS: usize = 1 << 14;
+
+/// Buffers bigger than this aren't kept by a `BufferPool`, so that a few huge
+/// lines don't keep their memory around.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+/// Buffers that `UStrChunksIter` reads pieces into, which are given back once
+/// the pieces have been counted, so that reading lines doesn't allocate for
+/// every one of them once it's going.
+#[derive(Debug, Clone, Default)]
+pub struct BufferPool(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl BufferPool {
+    /// Takes an empty buffer from the pool, or a new one if it's empty.
+    fn take(&self) -> Vec<u8> {
+        self.0.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Gives the buffers of the given texts back to the pool.
+    pub fn give_back<I: IntoIterator<Item = String>>(&self, texts: I) {
+        let mut buffers = self.0.lock().unwrap();
+
+        for text in texts {
+            if buffers.len() >= MAX_POOLED_BUFFERS {
+                break;
+            }
+
+            let mut buffer = text.into_bytes();
+
+            if buffer.capacity() <= MAX_POOLED_CAPACITY {
+                buffer.clear();
+                buffers.push(buffer);
+            }
+        }
+    }
+}
+
+/// A piece of the input read by `UStrChunksIter`. This is usually a whole
+/// record, but records longer than the maximum length are split into several
+/// pieces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    pub text: String,
+
+    /// The offset in the input of the piece's first byte.
+    pub offset: u64,
+
+    /// Whether this is the last piece of its record.
+    pub ends_record: bool,
+
+    /// The end of the piece before this one, if the record was split between
+    /// them where there's no word boundary, like in the middle of a very long
+    /// word. This is needed to count what spans the split only once.
+    pub split_context: Option<String>,
+}
+
+/// Checks whether the given result of `UStrChunksIter` is the last piece of a
+/// record. Errors always are, since the rest of the record is never read.
+pub fn ends_record(piece: &Result<Piece>) -> bool {
+    piece.as_ref().map_or(true, |piece| piece.ends_record)
+}
+
+/// Counts the records that end in the given results of `UStrChunksIter`.
+pub fn count_records(pieces: &[Result<Piece>]) -> usize {
+    pieces.iter().filter(|piece| ends_record(piece)).count()
+}
+
+/// Finds where to split a long record whose bytes so far are in `buf`. It's
+/// split on a word boundary near the end, which is also a grapheme cluster
+/// boundary, so that counting the pieces separately gives the same counts as
+/// counting the whole record. If there aren't enough word boundaries, it's
+/// split on a grapheme cluster boundary instead, which is returned along with
+/// `true`. Returns `None` if there's no such boundary yet.
+fn split_point(buf: &[u8]) -> Option<(usize, bool)> {
+    let mut start = buf.len().saturating_sub(SPLIT_WINDOW);
+
+    // start on a character boundary
+    while start < buf.len() && buf[start] & 0xC0 == 0x80 {
+        start += 1;
+    }
+
+    let tail = &buf[start..];
+    let tail = match str::from_utf8(tail) {
+        Ok(tail) => tail,
+        Err(e) => str::from_utf8(&tail[..e.valid_up_to()]).unwrap(),
+    };
+
+    let words: Vec<usize> = tail.split_word_bound_indices().map(|(i, _)| i).collect();
+
+    if words.len() > 2 * SPLIT_CONTEXT {
+        return Some((start + words[words.len() - SPLIT_CONTEXT], false));
+    }
+
+    let graphemes: Vec<usize> = tail.grapheme_indices(true).map(|(i, _)| i).collect();
+
+    if graphemes.len() > 2 * SPLIT_CONTEXT {
+        return Some((start + graphemes[graphemes.len() - SPLIT_CONTEXT], true));
+    }
+
+    None
+}
+
+/// Gets the last few grapheme clusters of a piece, for the piece after it.
+fn split_context(piece: &[u8]) -> String {
+    let tail = String::from_utf8_lossy(&piece[piece.len().saturating_sub(SPLIT_WINDOW)..]);
+    let start = tail
+        .grapheme_indices(true)
+        .rev()
+        .nth(CONTEXT_GRAPHEMES - 1)
+        .map_or(0, |(i, _)| i);
+
+    tail[start..].to_string()
+}
 
 /// An iterator over `&str`s read from a `BufRead`. For now, it reads lines,
 /// similar to `BufRead::lines`, but it includes the newline character for
-/// accurate counts.
-//
-// In the future, this should attempt to be more memory-stable by chunking by a
-// fixed size, or close to a fixed size, that splits on grapheme cluster
-// boundaries.
+/// accurate counts. Lines can be delimited by any pattern, not just newlines.
+/// With a maximum length, lines longer than it are split into pieces, so that
+/// huge lines, like in minified files, don't have to be held in memory whole.
 pub struct UStrChunksIter<'a, R: BufRead + 'a> {
     /// The `BufRead` to read from.
     pub reader: &'a mut R,
@@ -19,109 +300,365 @@ pub struct UStrChunksIter<'a, R: BufRead + 'a> {
     /// error has occurred.
     keep_reading: bool,
 
-    /// For line mode. Indicates whether the newline should be kept or not.
+    /// The delimiters to split on, usually newline sequences.
+    delimiter: Delimiter<'a>,
+
+    /// For line mode. Indicates whether the delimiter should be kept or not.
     keep_newline: bool,
 
+    /// What to do with chunks that aren't valid UTF-8.
+    invalid_utf8: InvalidUtf8,
+
+    /// The length after which records are split into pieces, if any.
+    max_len: Option<usize>,
+
+    /// How many records are left to skip before the first one that's read.
+    skip_records: usize,
+
+    /// How many records are left to read before stopping, if there's a limit.
+    records_left: Option<usize>,
+
+    /// Set when a piece of a record wasn't valid UTF-8 and the rest of the
+    /// record should be skipped.
+    skip_record: bool,
+
+    /// The end of the last piece, if the record was split after it where
+    /// there's no word boundary.
+    split_context: Option<String>,
+
+    /// Where to take the buffers for new pieces from, if anywhere.
+    buffer_pool: Option<BufferPool>,
+
     /// Internal buffer for reading until a break point is found
     buf: Vec<u8>,
+
+    /// The offset in the input of the first byte in `buf`.
+    buf_start: u64,
+
+    /// How many bytes have been consumed from the reader so far.
+    consumed: u64,
 }
 
 impl<'a, R: BufRead> UStrChunksIter<'a, R> {
-    pub fn new(reader: &'a mut R, keep_newline: bool) -> UStrChunksIter<'a, R> {
+    pub fn new(
+        reader: &'a mut R,
+        delimiter: Delimiter<'a>,
+        keep_newline: bool,
+    ) -> UStrChunksIter<'a, R> {
         UStrChunksIter {
             reader,
             keep_reading: true,
-            keep_newline: keep_newline,
+            delimiter,
+            keep_newline,
+            invalid_utf8: InvalidUtf8::default(),
+            max_len: None,
+            skip_records: 0,
+            records_left: None,
+            skip_record: false,
+            split_context: None,
+            buffer_pool: None,
             buf: Vec::new(),
+            buf_start: 0,
+            consumed: 0,
         }
     }
-}
 
-impl<'a, R: BufRead> Iterator for UStrChunksIter<'a, R> {
-    type Item = Result<String>;
+    /// Sets what to do with chunks that aren't valid UTF-8.
+    pub fn invalid_utf8(mut self, invalid_utf8: InvalidUtf8) -> UStrChunksIter<'a, R> {
+        self.invalid_utf8 = invalid_utf8;
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if !self.keep_reading {
+    /// Sets the length after which records are split into pieces. The pieces
+    /// are usually a little longer, since records are only split on word
+    /// boundaries.
+    pub fn max_len(mut self, max_len: Option<usize>) -> UStrChunksIter<'a, R> {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Sets how many records to skip before the first one that's read. They're
+    /// only scanned for delimiters, not decoded, so they can't have errors.
+    pub fn skip_records(mut self, skip_records: usize) -> UStrChunksIter<'a, R> {
+        self.skip_records = skip_records;
+        self
+    }
+
+    /// Sets how many records to read before stopping, if there's a limit. The
+    /// rest of the reader isn't read at all.
+    pub fn max_records(mut self, max_records: Option<usize>) -> UStrChunksIter<'a, R> {
+        self.records_left = max_records;
+        self
+    }
+
+    /// Sets the offset in the input of the first byte that's read, for readers
+    /// that don't start at the start of the input.
+    pub fn start_offset(mut self, offset: u64) -> UStrChunksIter<'a, R> {
+        self.buf_start = offset;
+        self.consumed = offset;
+        self
+    }
+
+    /// Sets the pool to take the buffers for new pieces from. The buffers are
+    /// expected to be given back once the pieces have been counted.
+    pub fn buffer_pool(mut self, buffer_pool: BufferPool) -> UStrChunksIter<'a, R> {
+        self.buffer_pool = Some(buffer_pool);
+        self
+    }
+
+    /// Takes the record read so far, along with its offset in the input,
+    /// leaving a new buffer for the next one.
+    fn take_buf(&mut self) -> (u64, Vec<u8>) {
+        let buf = match self.buffer_pool {
+            Some(ref pool) => pool.take(),
+            None => Vec::new(),
+        };
+
+        let start = mem::replace(&mut self.buf_start, self.consumed);
+        (start, mem::replace(&mut self.buf, buf))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt);
+        self.consumed += amt as u64;
+    }
+
+    /// Extends a delimiter that was found at the end of the reader's buffer
+    /// with the start of the next one, if it continues there, like the LF of a
+    /// CRLF. The next buffer is only read if it could, so that a record that
+    /// ends where the input stops for now, like in a pipe, isn't held back
+    /// until more of the input arrives.
+    fn extend_delimiter(&mut self, delimiter: &mut Vec<u8>) {
+        if !self.delimiter.may_continue(delimiter) {
+            return;
+        }
+
+        // errors are given when the next record is read
+        let next = match self.reader.fill_buf() {
+            Ok(next) => next,
+            Err(_) => return,
+        };
+
+        let mut joined = delimiter.clone();
+        joined.extend_from_slice(&next[..next.len().min(MAX_DELIMITER_PEEK)]);
+
+        if let Some((0, end)) = self.delimiter.find(&joined) {
+            let extra = end.saturating_sub(delimiter.len());
+            delimiter.extend_from_slice(&next[..extra]);
+            self.consume(extra);
+        }
+    }
+
+    /// Splits the record read so far if it's longer than the maximum length,
+    /// and returns the first piece, along with its end if it's needed for the
+    /// piece after it. The piece's offset in the input is returned first.
+    fn split_long_record(&mut self) -> Option<(u64, Vec<u8>, Option<String>)> {
+        if self.buf.len() < self.max_len? {
             return None;
         }
 
+        let (split, mid_word) = split_point(&self.buf)?;
+        let rest = self.buf.split_off(split);
+        let piece = mem::replace(&mut self.buf, rest);
+        let context = Some(&piece)
+            .filter(|_| mid_word)
+            .map(|piece| split_context(piece));
+
+        let start = self.buf_start;
+        self.buf_start += piece.len() as u64;
+
+        Some((start, piece, context))
+    }
+
+    /// Makes a piece out of the bytes that start at `start` in the input.
+    fn make_piece(&mut self, start: u64, bytes: Vec<u8>, ends_record: bool) -> Result<Piece> {
+        let text = match String::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(err) if self.invalid_utf8 == InvalidUtf8::Replace => {
+                String::from_utf8_lossy(err.as_bytes()).into_owned()
+            }
+            Err(err) => {
+                if self.invalid_utf8 != InvalidUtf8::SkipLine {
+                    self.keep_reading = false;
+                } else if !ends_record {
+                    self.skip_record = true;
+                }
+
+                let err = err.utf8_error();
+                return Err(UwcError::utf8(err).at_byte(start + err.valid_up_to() as u64));
+            }
+        };
+
+        Ok(Piece {
+            text,
+            offset: start,
+            ends_record,
+            split_context: self.split_context.take(),
+        })
+    }
+
+    /// Skips the records that are left to skip, without decoding them.
+    fn skip_to_first_record(&mut self) -> io::Result<()> {
+        if self.skip_records == 0 {
+            return Ok(());
+        }
+
+        // the end of the bytes skipped so far, which could be the start of a
+        // delimiter that ends in the next buffer
+        let mut carried = Vec::new();
+
+        while self.skip_records > 0 {
+            let buffer = self.reader.fill_buf()?;
+
+            if buffer.is_empty() {
+                self.skip_records = 0;
+                break;
+            }
+
+            let len = buffer.len();
+
+            match self.delimiter.find_after(&mut carried, buffer) {
+                Some((mut delimiter, end)) => {
+                    self.consume(end);
+
+                    if end == len {
+                        self.extend_delimiter(&mut delimiter);
+                    }
+
+                    carried.clear();
+                    self.skip_records -= 1;
+                }
+                None => {
+                    carried.extend_from_slice(buffer);
+                    let keep = carried.len().min(self.delimiter.carry_len());
+                    carried.drain(..carried.len() - keep);
+                    self.consume(len);
+                }
+            }
+        }
+
+        self.buf_start = self.consumed;
+        Ok(())
+    }
+
+    /// Reads the next piece, which is the whole next record unless it's split.
+    fn read_piece(&mut self) -> Option<Result<Piece>> {
         loop {
+            if !self.keep_reading {
+                return None;
+            }
+
             let buffer = match self.reader.fill_buf() {
-                Ok(buf) => buf,
+                // readers over bytes in memory give all of them at once
+                Ok(buf) => &buf[..buf.len().min(MAX_READ_LEN)],
                 Err(err) => {
                     self.keep_reading = false;
-                    return Some(Err(UwcError::IoError(err)));
+                    return Some(Err(UwcError::io(err).at_byte(self.consumed)));
                 }
             };
 
-            if buffer.len() == 0 {
+            if buffer.is_empty() {
                 self.keep_reading = false;
-                break;
-            }
 
-            let mat = crate::constants::NEWLINE_PATTERN.find(buffer);
+                if self.buf.is_empty() {
+                    return None;
+                }
+            } else if let Some((mut delimiter, end)) =
+                self.delimiter.find_after(&mut self.buf, buffer)
+            {
+                // the bytes up to the delimiter we found are in our buffer now,
+                // without the start of it if it started in the last one
+                let at_end = end == buffer.len();
+
+                // consume the bytes including the delimiter regardless of
+                // whether we want to keep the newlines for counting
+                self.consume(end);
+
+                if at_end {
+                    self.extend_delimiter(&mut delimiter);
+                }
 
-            // if we didn't find a newline sequence, stuff the bytes into our
-            // buffer and keep reading
-            if mat.is_none() {
+                if self.keep_newline {
+                    self.buf.extend_from_slice(&delimiter);
+                }
+            } else {
+                // if we didn't find a newline sequence, stuff the bytes into
+                // our buffer and keep reading
                 self.buf.extend_from_slice(buffer);
                 let length = buffer.len();
-                self.reader.consume(length);
-                continue;
+                self.consume(length);
+
+                match self.split_long_record() {
+                    Some((start, piece, context)) if !self.skip_record => {
+                        let piece = self.make_piece(start, piece, false);
+                        self.split_context = context.filter(|_| piece.is_ok());
+                        return Some(piece);
+                    }
+                    _ => continue,
+                }
             }
 
-            let mat = mat.unwrap();
+            // consume the buffer we've built so far and replace it with a new
+            // one
+            let (start, new_str_bytes) = self.take_buf();
 
-            let end = match self.keep_newline {
-                true => mat.end(),
-                false => mat.start(),
-            };
+            if mem::take(&mut self.skip_record) {
+                continue;
+            }
 
-            // copy up to the delimiter we found
-            self.buf.extend_from_slice(&buffer[..end]);
+            return Some(self.make_piece(start, new_str_bytes, true));
+        }
+    }
+}
 
-            // consume the bytes including the delimiter regardless of whether we
-            // want to keep the newlines for counting
-            let consume_length = mat.end();
-            self.reader.consume(consume_length);
+impl<'a, R: BufRead> Iterator for UStrChunksIter<'a, R> {
+    type Item = Result<Piece>;
 
-            break;
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.keep_reading || self.records_left == Some(0) {
+            return None;
         }
 
-        if !self.keep_reading && self.buf.len() == 0 {
-            return None;
+        if let Err(err) = self.skip_to_first_record() {
+            self.keep_reading = false;
+            return Some(Err(UwcError::io(err).at_byte(self.consumed)));
         }
 
-        // consume the buffer we've built so far and replace it with a new one
-        let new_str_bytes = mem::replace(&mut self.buf, Vec::new());
+        let piece = self.read_piece()?;
 
-        let new_str = match String::from_utf8(new_str_bytes) {
-            Ok(s) => s,
-            Err(err) => {
-                self.keep_reading = false;
-                return Some(Err(UwcError::Utf8Error(err)));
-            }
-        };
+        if let Some(ref mut records_left) = self.records_left {
+            *records_left -= ends_record(&piece) as usize;
+        }
 
-        Some(Ok(new_str))
+        Some(piece)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use env_logger;
+    use crate::constants::NUL_PATTERN;
+    use crate::counter::{self, CountOptions, ALL_COUNTERS};
+    use lazy_static::lazy_static;
     use log::*;
     use std::io;
     use std::io::BufReader;
 
+    lazy_static! {
+        static ref NEWLINES: Newlines = Newlines::default();
+    }
+
+    fn all_newlines() -> Delimiter<'static> {
+        Delimiter::Newlines(&NEWLINES)
+    }
+
     #[test]
     fn test_basic() {
         let _ = env_logger::try_init();
         let mut cursor = io::Cursor::new(b"hello");
-        let mut chunks = UStrChunksIter::new(&mut cursor, true);
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true);
         let mut s = chunks.next();
-        assert_eq!("hello", s.unwrap().unwrap());
+        assert_eq!("hello", s.unwrap().unwrap().text);
 
         s = chunks.next();
         debug!("{:?}", s);
@@ -136,14 +673,14 @@ mod test {
             "hello\ngoodbye\r\nwindows?\u{0085}\u{000C}unicode\u{2028}newline\u{2029}sequences"
             .as_bytes());
 
-        let mut chunks = UStrChunksIter::new(&mut cursor, true);
-        assert_eq!("hello\n", chunks.next().unwrap().unwrap());
-        assert_eq!("goodbye\r\n", chunks.next().unwrap().unwrap());
-        assert_eq!("windows?\u{0085}", chunks.next().unwrap().unwrap());
-        assert_eq!("\u{000C}", chunks.next().unwrap().unwrap());
-        assert_eq!("unicode\u{2028}", chunks.next().unwrap().unwrap());
-        assert_eq!("newline\u{2029}", chunks.next().unwrap().unwrap());
-        assert_eq!("sequences", chunks.next().unwrap().unwrap());
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true);
+        assert_eq!("hello\n", chunks.next().unwrap().unwrap().text);
+        assert_eq!("goodbye\r\n", chunks.next().unwrap().unwrap().text);
+        assert_eq!("windows?\u{0085}", chunks.next().unwrap().unwrap().text);
+        assert_eq!("\u{000C}", chunks.next().unwrap().unwrap().text);
+        assert_eq!("unicode\u{2028}", chunks.next().unwrap().unwrap().text);
+        assert_eq!("newline\u{2029}", chunks.next().unwrap().unwrap().text);
+        assert_eq!("sequences", chunks.next().unwrap().unwrap().text);
 
         assert!(chunks.next().is_none());
         assert!(chunks.next().is_none());
@@ -156,14 +693,14 @@ mod test {
             "hello\ngoodbye\r\nwindows?\u{0085}\u{000C}unicode\u{2028}newline\u{2029}sequences"
             .as_bytes());
 
-        let mut chunks = UStrChunksIter::new(&mut cursor, false);
-        assert_eq!("hello", chunks.next().unwrap().unwrap());
-        assert_eq!("goodbye", chunks.next().unwrap().unwrap());
-        assert_eq!("windows?", chunks.next().unwrap().unwrap());
-        assert_eq!("", chunks.next().unwrap().unwrap());
-        assert_eq!("unicode", chunks.next().unwrap().unwrap());
-        assert_eq!("newline", chunks.next().unwrap().unwrap());
-        assert_eq!("sequences", chunks.next().unwrap().unwrap());
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), false);
+        assert_eq!("hello", chunks.next().unwrap().unwrap().text);
+        assert_eq!("goodbye", chunks.next().unwrap().unwrap().text);
+        assert_eq!("windows?", chunks.next().unwrap().unwrap().text);
+        assert_eq!("", chunks.next().unwrap().unwrap().text);
+        assert_eq!("unicode", chunks.next().unwrap().unwrap().text);
+        assert_eq!("newline", chunks.next().unwrap().unwrap().text);
+        assert_eq!("sequences", chunks.next().unwrap().unwrap().text);
 
         assert!(chunks.next().is_none());
         assert!(chunks.next().is_none());
@@ -173,8 +710,8 @@ mod test {
     fn test_basic_buffered() {
         let cursor = io::Cursor::new(b"hello");
         let mut reader = BufReader::with_capacity(3, cursor);
-        let mut chunks = UStrChunksIter::new(&mut reader, true);
-        assert_eq!("hello", chunks.next().unwrap().unwrap());
+        let mut chunks = UStrChunksIter::new(&mut reader, all_newlines(), true);
+        assert_eq!("hello", chunks.next().unwrap().unwrap().text);
         assert!(chunks.next().is_none());
         assert!(chunks.next().is_none());
     }
@@ -186,9 +723,9 @@ mod test {
 
         // this should stop reading 2 bytes into the emoji
         let mut reader = BufReader::with_capacity(8, cursor);
-        let mut chunks = UStrChunksIter::new(&mut reader, true);
+        let mut chunks = UStrChunksIter::new(&mut reader, all_newlines(), true);
 
-        assert_eq!("hello 😬 whoops", chunks.next().unwrap().unwrap());
+        assert_eq!("hello 😬 whoops", chunks.next().unwrap().unwrap().text);
         assert!(chunks.next().is_none());
         assert!(chunks.next().is_none());
     }
@@ -202,15 +739,350 @@ mod test {
 
         // with a capacity of 10, it should stop in the middle of some graphemes
         let mut reader = BufReader::with_capacity(10, cursor);
-        let mut chunks = UStrChunksIter::new(&mut reader, true);
+        let mut chunks = UStrChunksIter::new(&mut reader, all_newlines(), true);
 
         assert_eq!(
             "私はガラスを食べられます。\n",
-            chunks.next().unwrap().unwrap()
+            chunks.next().unwrap().unwrap().text
+        );
+        assert_eq!(
+            "それは私を傷つけません。",
+            chunks.next().unwrap().unwrap().text
+        );
+
+        assert!(chunks.next().is_none());
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_chunks_by_restricted_newlines() {
+        let _ = env_logger::try_init();
+        let mut cursor = io::Cursor::new("hello\ngoodbye\r\nform\u{000C}feed\rend".as_bytes());
+
+        let newlines: crate::newline::Newlines = "lf".parse().unwrap();
+        let mut chunks = UStrChunksIter::new(&mut cursor, Delimiter::Newlines(&newlines), false);
+        assert_eq!("hello", chunks.next().unwrap().unwrap().text);
+        assert_eq!("goodbye", chunks.next().unwrap().unwrap().text);
+        assert_eq!(
+            "form\u{000C}feed\rend",
+            chunks.next().unwrap().unwrap().text
+        );
+
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_chunks_by_custom_delimiter() {
+        let _ = env_logger::try_init();
+        let mut cursor = io::Cursor::new("select 1;\nselect\n  2;;end".as_bytes());
+
+        let delimiter = Regex::new(";+").unwrap();
+        let mut chunks = UStrChunksIter::new(&mut cursor, Delimiter::pattern(&delimiter), false);
+        assert_eq!("select 1", chunks.next().unwrap().unwrap().text);
+        assert_eq!("\nselect\n  2", chunks.next().unwrap().unwrap().text);
+        assert_eq!("end", chunks.next().unwrap().unwrap().text);
+
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let _ = env_logger::try_init();
+        let input = b"one\ntw\xffo\nthree\n";
+
+        let mut cursor = io::Cursor::new(input);
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true);
+        assert_eq!("one\n", chunks.next().unwrap().unwrap().text);
+        let e = chunks.next().unwrap().unwrap_err();
+        assert!(e.is_utf8());
+        assert_eq!(Some(6), e.location().byte_offset);
+        assert!(chunks.next().is_none());
+
+        let mut cursor = io::Cursor::new(input);
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true)
+            .invalid_utf8(InvalidUtf8::Replace);
+        assert_eq!("one\n", chunks.next().unwrap().unwrap().text);
+        assert_eq!("tw\u{FFFD}o\n", chunks.next().unwrap().unwrap().text);
+        assert_eq!("three\n", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+
+        let mut cursor = io::Cursor::new(input);
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true)
+            .invalid_utf8(InvalidUtf8::SkipLine);
+        assert_eq!("one\n", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().unwrap().is_err());
+        assert_eq!("three\n", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+
+        // the offset counts the delimiters that aren't kept, and the pieces of
+        // long lines before the invalid one
+        let input = b"one\r\ntwo two two two two tw\xffo\n";
+        let mut cursor = io::Cursor::new(&input[..]);
+        let results: Vec<_> = UStrChunksIter::new(&mut cursor, all_newlines(), false)
+            .max_len(Some(8))
+            .collect();
+        let e = results.into_iter().find_map(|piece| piece.err()).unwrap();
+        assert_eq!(Some(27), e.location().byte_offset);
+    }
+
+    #[test]
+    fn test_chunks_by_nul() {
+        let _ = env_logger::try_init();
+        let mut cursor = io::Cursor::new("file one\0file\ntwo\0".as_bytes());
+
+        let mut chunks = UStrChunksIter::new(&mut cursor, Delimiter::pattern(&NUL_PATTERN), true);
+        assert_eq!("file one\0", chunks.next().unwrap().unwrap().text);
+        assert_eq!("file\ntwo\0", chunks.next().unwrap().unwrap().text);
+
+        assert!(chunks.next().is_none());
+    }
+
+    /// A reader that gives its input in the given reads, and counts how many
+    /// of them were made, like a pipe that's written to a bit at a time.
+    struct Pipe<'a> {
+        reads: Vec<&'a [u8]>,
+        made: usize,
+    }
+
+    impl io::Read for Pipe<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = match self.reads.get(self.made) {
+                Some(read) => read,
+                None => return Ok(0),
+            };
+
+            self.made += 1;
+            buf[..read.len()].copy_from_slice(read);
+            Ok(read.len())
+        }
+    }
+
+    #[test]
+    fn test_pattern_at_end_of_buffer() {
+        let _ = env_logger::try_init();
+
+        // a NUL can't go on, so the next read isn't waited for
+        let mut reader = BufReader::new(Pipe {
+            reads: vec![b"one\0", b"two\0"],
+            made: 0,
+        });
+
+        let mut chunks = UStrChunksIter::new(&mut reader, Delimiter::pattern(&NUL_PATTERN), true);
+        assert_eq!("one\0", chunks.next().unwrap().unwrap().text);
+        assert_eq!(1, chunks.reader.get_ref().made);
+        assert_eq!("two\0", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+
+        // but a run of semicolons can
+        let mut reader = BufReader::new(Pipe {
+            reads: vec![b"one;", b";two"],
+            made: 0,
+        });
+
+        let delimiter = Regex::new(";+").unwrap();
+        let mut chunks = UStrChunksIter::new(&mut reader, Delimiter::pattern(&delimiter), true);
+        assert_eq!("one;;", chunks.next().unwrap().unwrap().text);
+        assert_eq!("two", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_split_long_lines() {
+        let _ = env_logger::try_init();
+
+        let line = r#"{"name":"uwc","keywords":["unicode","wc"],"words":"can't stop, won't stop"}"#;
+        let input = format!("{}\nshort\n{}", line, line.repeat(20));
+
+        let mut cursor = io::Cursor::new(input.as_bytes());
+        let pieces: Vec<Piece> = UStrChunksIter::new(&mut cursor, all_newlines(), true)
+            .max_len(Some(16))
+            .map(|piece| piece.unwrap())
+            .collect();
+
+        assert!(pieces.len() > 3);
+        assert_eq!(3, pieces.iter().filter(|piece| piece.ends_record).count());
+        assert!(pieces.last().unwrap().ends_record);
+
+        let joined: String = pieces.iter().map(|piece| piece.text.as_str()).collect();
+        assert_eq!(input, joined);
+
+        // the pieces count the same as the whole lines
+        let options = CountOptions::default();
+        let count = |s: &str| counter::count(&ALL_COUNTERS, s, &options);
+        let piece_counts: Vec<_> = pieces.iter().map(|piece| count(&piece.text)).collect();
+        assert_eq!(count(&input), counter::sum_all_counts(&piece_counts));
+
+        // lines aren't split without a maximum length
+        let mut cursor = io::Cursor::new(input.as_bytes());
+        let chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true);
+        assert_eq!(3, chunks.count());
+    }
+
+    #[test]
+    fn test_split_long_words() {
+        let _ = env_logger::try_init();
+
+        // words too long to split between
+        let input = format!(
+            "{} {}x\u{0301}\n{}",
+            "Ab1".repeat(5000),
+            "Ω".repeat(9000),
+            "e\u{0301}".repeat(3000)
+        );
+
+        let mut reader = BufReader::with_capacity(512, input.as_bytes());
+        let pieces: Vec<Piece> = UStrChunksIter::new(&mut reader, all_newlines(), true)
+            .max_len(Some(1024))
+            .map(|piece| piece.unwrap())
+            .collect();
+
+        assert!(pieces.len() > 6);
+        assert!(pieces.iter().any(|piece| piece.split_context.is_some()));
+
+        let joined: String = pieces.iter().map(|piece| piece.text.as_str()).collect();
+        assert_eq!(input, joined);
+
+        let options = CountOptions::default();
+        let piece_counts: Vec<_> = pieces
+            .iter()
+            .map(|piece| {
+                let counts = match piece.split_context {
+                    Some(ref before) => {
+                        counter::count_continued(&ALL_COUNTERS, before, &piece.text, &options)
+                    }
+                    None => counter::count_array(&ALL_COUNTERS, &piece.text, &options),
+                };
+
+                counter::counted_from_array(&ALL_COUNTERS, &counts)
+            })
+            .collect();
+
+        assert_eq!(
+            counter::count(&ALL_COUNTERS, &input, &options),
+            counter::sum_all_counts(&piece_counts)
         );
-        assert_eq!("それは私を傷つけません。", chunks.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_buffer_pool() {
+        let _ = env_logger::try_init();
+
+        let pool = BufferPool::default();
+        let mut cursor = io::Cursor::new("first line\nsecond line\nthird");
+        let mut chunks =
+            UStrChunksIter::new(&mut cursor, all_newlines(), true).buffer_pool(pool.clone());
 
+        let first = chunks.next().unwrap().unwrap();
+        assert_eq!("first line\n", first.text);
+
+        // the buffer of a counted piece is used for the next one after it
+        let capacity = first.text.capacity();
+        pool.give_back(vec![first.text]);
+        chunks.next().unwrap().unwrap();
+        let third = chunks.next().unwrap().unwrap();
+        assert_eq!("third", third.text);
+        assert_eq!(capacity, third.text.capacity());
+
+        // huge buffers aren't kept
+        pool.give_back(vec![String::with_capacity(MAX_POOLED_CAPACITY + 1)]);
+        assert!(pool.take().capacity() <= MAX_POOLED_CAPACITY);
+    }
+
+    #[test]
+    fn test_crlf_across_buffers() {
+        let _ = env_logger::try_init();
+
+        // the buffer ends between the CR and the LF
+        let cursor = io::Cursor::new("one\r\ntwo\rthree".as_bytes());
+        let mut reader = BufReader::with_capacity(4, cursor);
+
+        let mut chunks = UStrChunksIter::new(&mut reader, all_newlines(), true);
+        assert_eq!("one\r\n", chunks.next().unwrap().unwrap().text);
+        assert_eq!("two\r", chunks.next().unwrap().unwrap().text);
+        assert_eq!("three", chunks.next().unwrap().unwrap().text);
         assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_newlines_across_buffers() {
+        let _ = env_logger::try_init();
+
+        // with only LF selected, a CR at the end of a buffer isn't a newline
+        // on its own, so the CRLF has to be found across the buffers
+        let lf: Newlines = "lf".parse().unwrap();
+        let cases = [
+            ("\u{0085}", all_newlines()),
+            ("\u{2028}", all_newlines()),
+            ("\u{2029}", all_newlines()),
+            ("\r\n", all_newlines()),
+            ("\r\n", Delimiter::Newlines(&lf)),
+        ];
+
+        for (newline, delimiter) in &cases {
+            // the buffer ends after the first byte of the newline, and the
+            // second one for the three-byte ones
+            for before in 6..8 {
+                let input = format!("{}{}b", "a".repeat(before), newline);
+                let cursor = io::Cursor::new(input.as_bytes());
+                let mut reader = BufReader::with_capacity(8, cursor);
+
+                let mut chunks = UStrChunksIter::new(&mut reader, *delimiter, true);
+                let expected = format!("{}{}", "a".repeat(before), newline);
+                assert_eq!(expected, chunks.next().unwrap().unwrap().text);
+                assert_eq!("b", chunks.next().unwrap().unwrap().text);
+                assert!(chunks.next().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_pattern_across_buffers() {
+        let _ = env_logger::try_init();
+
+        // the buffer ends in the middle of the first and last separators
+        let input = format!("{}<>{}<>c", "a".repeat(7), "b".repeat(10));
+        let delimiter = Regex::new("<>").unwrap();
+
+        let cursor = io::Cursor::new(input.as_bytes());
+        let mut reader = BufReader::with_capacity(8, cursor);
+
+        let mut chunks = UStrChunksIter::new(&mut reader, Delimiter::pattern(&delimiter), false);
+        assert_eq!("aaaaaaa", chunks.next().unwrap().unwrap().text);
+        assert_eq!("bbbbbbbbbb", chunks.next().unwrap().unwrap().text);
+        assert_eq!("c", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+
+        // the same goes for the records that are skipped
+        let cursor = io::Cursor::new(input.as_bytes());
+        let mut reader = BufReader::with_capacity(8, cursor);
+
+        let mut chunks =
+            UStrChunksIter::new(&mut reader, Delimiter::pattern(&delimiter), true).skip_records(2);
+        assert_eq!("c", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_skip_and_max_records() {
+        let _ = env_logger::try_init();
+
+        // invalid UTF-8 in the skipped records isn't an error, and the CRLF
+        // is skipped whole even though the buffer ends between the CR and LF
+        let cursor = io::Cursor::new(b"o\xffe\r\ntwo\nthree\nfour\n".to_vec());
+        let mut reader = BufReader::with_capacity(4, cursor);
+
+        let mut chunks = UStrChunksIter::new(&mut reader, all_newlines(), true)
+            .skip_records(1)
+            .max_records(Some(2));
+
+        let two = chunks.next().unwrap().unwrap();
+        assert_eq!(("two\n", 5), (two.text.as_str(), two.offset));
+        assert_eq!("three\n", chunks.next().unwrap().unwrap().text);
+        assert!(chunks.next().is_none());
+
+        // skipping more records than there are reads nothing
+        let mut cursor = io::Cursor::new(b"one\ntwo".to_vec());
+        let mut chunks = UStrChunksIter::new(&mut cursor, all_newlines(), true).skip_records(5);
         assert!(chunks.next().is_none());
     }
 }
@@ -0,0 +1,49 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use failure::Error;
+use tabwriter::TabWriter;
+
+use crate::code_points;
+use crate::opt::Opt;
+
+/// Formats a Unicode version the way it's written, like `16.0.0`.
+fn format_version<T: Display>((major, minor, update): (T, T, T)) -> String {
+    format!("{}.{}.{}", major, minor, update)
+}
+
+/// Writes the versions of Unicode that uwc's tables are from with
+/// `--report-unicode-version`, along with what they're used for, since what's
+/// counted depends on them, like which code points `--unassigned` counts.
+pub fn report(opts: &Opt) -> Result<(), Error> {
+    let versions = [
+        (
+            code_points::UNICODE_VERSION.to_string(),
+            "general categories, like --unassigned, --uppercase, and --lowercase",
+        ),
+        (
+            format_version(unicode_segmentation::UNICODE_VERSION),
+            "graphemes, and words with --word-algorithm unicode",
+        ),
+        (format_version(unicode_width::UNICODE_VERSION), "--widths"),
+    ];
+
+    let stdout = io::stdout();
+    let mut writer: Box<dyn Write> = if opts.no_elastic {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(TabWriter::new(stdout.lock()))
+    };
+
+    if !opts.no_header {
+        writeln!(writer, "unicode\tused for")?;
+    }
+
+    for (version, used_for) in &versions {
+        writeln!(writer, "{}\t{}", version, used_for)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
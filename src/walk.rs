@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use failure::{format_err, Error};
+use ignore::WalkBuilder;
+use log::*;
+
+use crate::input::{self, STDIN_IDENTIFIER};
+use crate::open_files;
+use crate::opt::Opt;
+use crate::report::{self, InputError};
+
+/// The directories that are skipped when counting recursively, unless
+/// `--no-default-prune` is given: version control metadata and dependencies
+/// that are rarely what anyone wants to count.
+const DEFAULT_PRUNE: [&str; 5] = [".git", ".hg", ".svn", "_darcs", "node_modules"];
+
+/// The most files that can be found ahead of the ones being counted, when
+/// they're counted while the directories are still being walked.
+const QUEUE_LEN: usize = 1024;
+
+/// Gets the input files given by the user, either as arguments or in a file
+/// with a list of them. If none were given, stdin is used.
+///
+/// The list of files can itself be read from stdin, in which case stdin can't
+/// also be given as an input to count; such entries are reported as errors,
+/// and the return value's second element will be `false`.
+pub fn given_inputs(opts: &Opt) -> Result<(Vec<String>, bool), Error> {
+    let list = match (&opts.files0_from, &opts.files_from) {
+        (Some(path), _) => Some((path, b'\0')),
+        (None, Some(path)) => Some((path, b'\n')),
+        (None, None) => None,
+    };
+
+    let (path, delimiter) = match list {
+        Some(list) => list,
+        None if opts.files.is_empty() => return Ok((vec![STDIN_IDENTIFIER.to_string()], true)),
+        None => return Ok((opts.files.clone(), true)),
+    };
+
+    let mut files =
+        input::read_file_list(path, delimiter).map_err(|e| format_err!("{}: {}", path, e))?;
+
+    let mut success = true;
+
+    if path == STDIN_IDENTIFIER {
+        files.retain(|file| {
+            let is_stdin = file == STDIN_IDENTIFIER;
+
+            if is_stdin {
+                let message = "can't count stdin when reading file names from it";
+                report::input_error(opts, InputError::new(Some(file), "invalid-input", message));
+                success = false;
+            }
+
+            !is_stdin
+        });
+    }
+
+    Ok((files, success))
+}
+
+/// Whether the input is a symbolic link that shouldn't be followed.
+fn is_skipped_symlink(opts: &Opt, input: &str) -> bool {
+    let skipped = opts.no_follow
+        && input != STDIN_IDENTIFIER
+        && fs::symlink_metadata(input).is_ok_and(|meta| meta.file_type().is_symlink());
+
+    if skipped {
+        info!("skipping symbolic link: {}", input);
+    }
+
+    skipped
+}
+
+/// Expands the given inputs into the files to count. When counting
+/// recursively, directories are replaced with every regular file underneath
+/// them, sorted by name; otherwise, the inputs are given back as is. Unless
+/// disabled, files matched by `.gitignore` and `.ignore` files are skipped, as
+/// are hidden files and the directories in [`DEFAULT_PRUNE`]. Symbolic links
+/// are followed as described by `--follow-symlinks` and `--no-follow`.
+///
+/// Errors encountered while walking a directory are printed, and the return
+/// value's second element will be `false`.
+pub fn expand(inputs: &[String], opts: &Opt) -> (Vec<String>, bool) {
+    let mut files = Vec::new();
+    let success = walk(inputs, opts, |file| files.push(file));
+    (files, success)
+}
+
+/// Expands the given inputs like [`expand`], but walks the directories on a
+/// thread of their own, while the files that were already found are counted
+/// with `count` on the rayon pool, along with their index, like
+/// [`open_files::count_limited`]. This way, walking large trees of small files
+/// overlaps with counting them. Returns the results of counting the files, in
+/// the order they were found, and whether the walk had no errors.
+pub fn expand_while_counting<T, F>(inputs: &[String], opts: &Opt, count: F) -> (Vec<T>, bool)
+where
+    T: Send,
+    F: Fn(usize, &str) -> T + Sync + Send,
+{
+    thread::scope(|scope| {
+        // the walk waits while the queue is full, so that it doesn't get far
+        // ahead of the counting
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+
+        let walking = scope.spawn(move || {
+            walk(inputs, opts, |file| {
+                let _ = tx.send(file);
+            })
+        });
+
+        let results = open_files::count_limited(rx, open_files::limit(opts), count);
+
+        (results, walking.join().unwrap())
+    })
+}
+
+/// Walks the given inputs as described by [`expand`], giving every file to
+/// count to `found` in order. Returns whether the walk had no errors.
+fn walk<F: FnMut(String)>(inputs: &[String], opts: &Opt, mut found: F) -> bool {
+    let mut inputs = inputs.to_vec();
+    inputs.retain(|input| !is_skipped_symlink(opts, input));
+
+    if !opts.recursive {
+        inputs.into_iter().for_each(found);
+        return true;
+    }
+
+    let mut success = true;
+
+    for input in inputs {
+        if input == STDIN_IDENTIFIER || !Path::new(&input).is_dir() {
+            found(input);
+            continue;
+        }
+
+        let respect_ignore = !opts.no_ignore;
+
+        let prune = !opts.no_default_prune;
+
+        let walker = WalkBuilder::new(&input)
+            .standard_filters(false)
+            .hidden(!opts.hidden)
+            .ignore(respect_ignore)
+            .git_ignore(respect_ignore)
+            .git_global(respect_ignore)
+            .git_exclude(respect_ignore)
+            .parents(respect_ignore)
+            .follow_links(opts.follow_symlinks)
+            .max_depth(opts.max_depth)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                let is_pruned = prune
+                    && entry.depth() > 0
+                    && entry.file_type().is_some_and(|t| t.is_dir())
+                    && DEFAULT_PRUNE.iter().any(|name| entry.file_name() == *name);
+
+                if is_pruned {
+                    debug!("pruned: {}", entry.path().display());
+                }
+
+                !is_pruned
+            })
+            .build();
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report::input_error(opts, InputError::walk(&e));
+                    success = false;
+                    continue;
+                }
+            };
+
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                debug!("found file: {}", entry.path().display());
+                found(entry.path().to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    success
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_expand_while_counting() {
+        let _ = env_logger::try_init();
+
+        let dir = std::env::temp_dir().join(format!("uwc-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for sub in &["a", "b/c", "d"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+
+            for name in &["1", "2", "3"] {
+                fs::write(dir.join(sub).join(name), name).unwrap();
+            }
+        }
+
+        let opts = Opt::from_iter(&["uwc", "-r"]);
+        let inputs = vec![dir.to_string_lossy().into_owned()];
+
+        let (expanded, success) = expand(&inputs, &opts);
+        assert!(success);
+        assert_eq!(9, expanded.len());
+
+        // the files are given along with the index they'd have been expanded at
+        let (mut counted, success) =
+            expand_while_counting(&inputs, &opts, |i, file| (i, file.to_string()));
+        assert!(success);
+
+        counted.sort();
+        let expanded: Vec<_> = expanded.into_iter().enumerate().collect();
+        assert_eq!(expanded, counted);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
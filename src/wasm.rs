@@ -0,0 +1,55 @@
+use wasm_bindgen::prelude::*;
+
+use crate::counter::{self, CountOptions, Counted, Counter, DEFAULT_COUNTERS};
+
+/// Counts the counters with the given names in `text`, or the default ones, if
+/// none are given.
+fn count_named(text: &str, counters: &[String]) -> Result<Counted, String> {
+    let counters = match counters {
+        [] => DEFAULT_COUNTERS.to_vec(),
+        names => names
+            .iter()
+            .map(|name| name.parse())
+            .collect::<Result<Vec<Counter>, String>>()?,
+    };
+
+    Ok(counter::count(&counters, text, &CountOptions::default()))
+}
+
+/// Counts the counters with the given names, like "words" or "graphemes", in
+/// `text`, and gives their counts as an object by name, like
+/// `{ "lines": 1, "words": 2 }`. With no names, the default counters are
+/// counted, like with `uwc`.
+#[wasm_bindgen(js_name = countText)]
+pub fn count_text(text: &str, counters: Vec<String>) -> Result<JsValue, JsValue> {
+    let counts = count_named(text, &counters).map_err(|e| JsValue::from_str(&e))?;
+
+    // this is deprecated in favor of serde-wasm-bindgen, but that would be one
+    // more dependency for a map of numbers
+    #[allow(deprecated)]
+    JsValue::from_serde(&counts).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count_named() {
+        let _ = env_logger::try_init();
+
+        let counts = count_named(
+            "Μπορῶ νὰ\n",
+            &["words".to_string(), "graphemes".to_string()],
+        );
+        let expected: Counted = vec![(Counter::Words, 2), (Counter::GraphemeCluster, 9)]
+            .into_iter()
+            .collect();
+        assert_eq!(Ok(expected), counts);
+
+        let counts = count_named("a b\n", &[]).unwrap();
+        assert_eq!(vec![1, 2, 4], counts.values().copied().collect::<Vec<_>>());
+
+        assert!(count_named("a", &["sentences".to_string()]).is_err());
+    }
+}
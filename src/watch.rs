@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use failure::{format_err, Error};
+use log::*;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::counter::Counted;
+use crate::input::STDIN_IDENTIFIER;
+use crate::opt::{CountMode, Opt};
+use crate::report::{self, InputError};
+use crate::{count_file, walk, write_table};
+
+/// How long to wait for more events after one is received, so that a burst
+/// of changes, like an editor saving a file, is counted only once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The counts of the files under the watched inputs.
+#[derive(Default)]
+pub struct Dashboard {
+    /// The rows of counts for every file, by the file's name.
+    rows: BTreeMap<String, Vec<(String, Counted)>>,
+}
+
+impl Dashboard {
+    /// Finds the files under the inputs again, and counts the ones that are
+    /// new or that changed, i.e., whose canonical paths are in `changed`.
+    /// Returns whether the counts changed.
+    pub fn refresh(
+        &mut self,
+        inputs: &[String],
+        changed: &HashSet<PathBuf>,
+        opts: &Opt,
+    ) -> Result<bool, Error> {
+        let (files, _) = walk::expand(inputs, opts);
+        let sink: Arc<Mutex<dyn io::Write + Send + Sync>> = Arc::new(Mutex::new(io::sink()));
+
+        let mut updated = files.len() != self.rows.len();
+        let mut rows = BTreeMap::new();
+
+        for file in files {
+            let is_changed = fs::canonicalize(&file).is_ok_and(|path| changed.contains(&path));
+
+            match self.rows.remove(&file) {
+                Some(file_rows) if !is_changed => {
+                    rows.insert(file, file_rows);
+                }
+                _ => {
+                    debug!("recounting: {}", file);
+                    let (_, file_rows) = count_file(&file, opts, sink.clone())?;
+                    rows.insert(file, file_rows);
+                    updated = true;
+                }
+            }
+        }
+
+        self.rows = rows;
+
+        Ok(updated)
+    }
+
+    /// Writes the table of counts.
+    pub fn write(&self, opts: &Opt) -> Result<(), Error> {
+        let rows = self
+            .rows
+            .values()
+            .flatten()
+            .map(|(name, counts)| (name.as_str(), counts));
+
+        write_table(rows, opts)
+    }
+}
+
+/// Counts the files under the given inputs, then watches them for changes. When
+/// files are created or modified, only those are counted again, and the table
+/// of counts is written again. This only returns if there's an error.
+pub fn watch(inputs: &[String], opts: &Opt) -> Result<bool, Error> {
+    if opts.mode != CountMode::File {
+        return Err(format_err!("--watch can only be used in file mode"));
+    }
+
+    if inputs.iter().any(|name| name == STDIN_IDENTIFIER) {
+        return Err(format_err!("--watch can't be used with stdin"));
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    for input in inputs {
+        let path = fs::canonicalize(input).map_err(|e| format_err!("{}: {}", input, e))?;
+        watcher.watch(&path, RecursiveMode::Recursive)?;
+    }
+
+    let mut dashboard = Dashboard::default();
+    dashboard.refresh(inputs, &HashSet::new(), opts)?;
+    dashboard.write(opts)?;
+
+    loop {
+        let mut changed = HashSet::new();
+        let mut event = rx.recv()?;
+
+        loop {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => report::input_error(opts, InputError::new(None, "watch", e)),
+            }
+
+            event = match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(e) => return Err(e.into()),
+            };
+        }
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        debug!("changed: {:?}", changed);
+
+        if dashboard.refresh(inputs, &changed, opts)? {
+            dashboard.write(opts)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counter::Counter;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_refresh() {
+        let _ = env_logger::try_init();
+
+        let dir = std::env::temp_dir().join(format!("uwc-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, "one two\n").unwrap();
+
+        let opts = Opt::from_iter(&["uwc", "-w", "-r"]);
+        let inputs = vec![dir.to_str().unwrap().to_string()];
+        let words = |dashboard: &Dashboard| -> Vec<usize> {
+            dashboard
+                .rows
+                .values()
+                .flatten()
+                .map(|(_, counts)| counts[&Counter::Words])
+                .collect()
+        };
+
+        let mut dashboard = Dashboard::default();
+        assert!(dashboard.refresh(&inputs, &HashSet::new(), &opts).unwrap());
+        assert_eq!(vec![2], words(&dashboard));
+
+        // changes aren't counted until the file is reported as changed
+        fs::write(&a, "one two three\n").unwrap();
+        assert!(!dashboard.refresh(&inputs, &HashSet::new(), &opts).unwrap());
+        assert_eq!(vec![2], words(&dashboard));
+
+        let changed = vec![fs::canonicalize(&a).unwrap()].into_iter().collect();
+        assert!(dashboard.refresh(&inputs, &changed, &opts).unwrap());
+        assert_eq!(vec![3], words(&dashboard));
+
+        // new files are always counted
+        fs::write(&b, "four\n").unwrap();
+        assert!(dashboard.refresh(&inputs, &HashSet::new(), &opts).unwrap());
+        assert_eq!(vec![3, 1], words(&dashboard));
+
+        fs::remove_file(&a).unwrap();
+        assert!(dashboard.refresh(&inputs, &HashSet::new(), &opts).unwrap());
+        assert_eq!(vec![1], words(&dashboard));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use unicode_width::UnicodeWidthChar;
+
+use crate::counter::{Count, CountOptions};
+
+/// The East Asian width classes of characters that are counted with
+/// `--widths`, by how many columns they take up in a terminal.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum WidthClass {
+    /// Characters that take up one column everywhere, like `a` or `ｱ`.
+    Narrow,
+
+    /// Characters that take up two columns everywhere, like `東` or `Ａ`.
+    Wide,
+
+    /// Characters that take up one column, or two in East Asian contexts,
+    /// depending on the terminal, like `°` or `①`.
+    Ambiguous,
+}
+
+/// All the width classes, in the order their columns are shown in.
+pub const WIDTH_CLASSES: [WidthClass; 3] =
+    [WidthClass::Narrow, WidthClass::Wide, WidthClass::Ambiguous];
+
+impl WidthClass {
+    /// The name of the class's counter, as shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            WidthClass::Narrow => "narrow",
+            WidthClass::Wide => "wide",
+            WidthClass::Ambiguous => "ambiguous",
+        }
+    }
+
+    /// Gets the width class of the given character, or `None` for control
+    /// characters and the ones that don't take up a column of their own, like
+    /// combining marks.
+    fn of(c: char) -> Option<WidthClass> {
+        match (c.width(), c.width_cjk()) {
+            (Some(2), _) => Some(WidthClass::Wide),
+            (Some(1), Some(2)) => Some(WidthClass::Ambiguous),
+            (Some(1), _) => Some(WidthClass::Narrow),
+            _ => None,
+        }
+    }
+}
+
+impl Count for WidthClass {
+    fn count(&self, s: &str, _options: &CountOptions) -> usize {
+        // printable ASCII characters are all narrow
+        if s.is_ascii() {
+            return match self {
+                WidthClass::Narrow => s.bytes().filter(|b| (b' '..=b'~').contains(b)).count(),
+                _ => 0,
+            };
+        }
+
+        s.chars()
+            .filter(|c| WidthClass::of(*c) == Some(*self))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let _ = env_logger::try_init();
+
+        let options = CountOptions::default();
+        let counts = |s: &str| WIDTH_CLASSES.map(|class| class.count(s, &options));
+
+        assert_eq!([12, 0, 0], counts("Hello, world\t\n"));
+        assert_eq!([4, 4, 0], counts("東京 ２０ ｱｲ"));
+        assert_eq!([2, 0, 3], counts("°①·e\u{301}x"));
+    }
+}
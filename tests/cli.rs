@@ -7,14 +7,18 @@ extern crate log;
 use std::collections::{HashSet, VecDeque};
 use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 fn main_binary() -> Command {
     let mut cmd = Command::new("cargo");
     cmd.arg("run");
     cmd.arg("-q");
+
+    // don't use the config file or the options of whoever runs the tests
+    cmd.env("UWC_CONFIG", "");
+    cmd.env_remove("UWC_OPTS");
     cmd
 }
 
@@ -31,7 +35,7 @@ where
 
 /// Takes a String that should be the output of a run, discards the header, and
 /// parses the rest of the output into their fields.
-fn parse_lines<'a>(output: &'a str, has_header: bool) -> HashSet<(Vec<usize>, &'a str)> {
+fn parse_lines(output: &str, has_header: bool) -> HashSet<(Vec<usize>, &str)> {
     let mut lines: VecDeque<&str> = output.lines().collect();
 
     // If there's a header, there should be at least 2 lines. If there is no
@@ -86,7 +90,7 @@ fn test_no_args() {
 /// counters and all 0s.
 #[test]
 fn test_no_args_no_elastic_tabs() {
-    let out = main_binary_with_args(&["--no-elastic"]).output().unwrap();
+    let out = main_binary_with_args(["--no-elastic"]).output().unwrap();
 
     let stdout = String::from_utf8(out.stdout).unwrap();
     let correct_output = String::from("lines\twords\tbytes\tfilename\n0\t0\t0\t-\n");
@@ -98,6 +102,695 @@ fn test_no_args_no_elastic_tabs() {
     assert_eq!(0, stderr.len());
 }
 
+/// Tests that the options in `UWC_OPTS` are given before the ones on the
+/// command line, which override them.
+#[test]
+fn test_env_opts() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+
+    let out = main_binary_with_args([OsStr::new("-w"), OsStr::new("-n"), hello.as_os_str()])
+        .env("UWC_OPTS", "-l --no-elastic --count-regex 'ls=l+'")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let fields = parse_lines(&stdout, false);
+    assert_eq!(1, fields.len());
+    assert_eq!(vec![0, 1, 1], fields.iter().next().unwrap().0);
+    assert!(stdout.contains('\t'));
+
+    let out = main_binary_with_args(["--chunk-size", "2", "-"])
+        .env("UWC_OPTS", "--chunk-size 1")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let out = main_binary().env("UWC_OPTS", "'-l").output().unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("UWC_OPTS"), "got: {}", stderr);
+}
+
+/// Tests the exit codes for inputs that can't be counted, with and without
+/// `--strict` and `--lenient`.
+#[test]
+fn test_exit_codes() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let fixtures = test_dir.join("tests/fixtures");
+
+    let out = main_binary_with_args([fixtures.as_os_str(), hello.as_os_str()])
+        .output()
+        .unwrap();
+    assert_eq!(Some(2), out.status.code());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("hello"), "got: {}", stdout);
+
+    let out = main_binary_with_args([
+        OsStr::new("--lenient"),
+        fixtures.as_os_str(),
+        hello.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(0), out.status.code());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("fixtures"), "got: {}", stderr);
+
+    // with one thread, nothing after the directory is counted
+    let out = main_binary_with_args([
+        OsStr::new("--strict"),
+        OsStr::new("--sequential"),
+        fixtures.as_os_str(),
+        hello.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(2), out.status.code());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(!stdout.contains("hello"), "got: {}", stdout);
+
+    let out = main_binary_with_args([
+        OsStr::new("-l"),
+        OsStr::new("--fail-if=lines<1"),
+        hello.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    // the counters of the conditions are counted even if they aren't selected
+    let out = main_binary_with_args([
+        OsStr::new("-l"),
+        OsStr::new("--fail-if=words>0"),
+        hello.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    let out = main_binary_with_args(["--strict", "--lenient"])
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), out.status.code());
+}
+
+/// Tests that the counters with options of their own are built in, so that
+/// they can be selected and checked by name, their columns are in the same
+/// order however they're given, and they leave the custom counters to
+/// `--count-regex`.
+#[test]
+fn test_named_counters() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let links = test_dir.join("tests/fixtures/links/input");
+    let letter_case = test_dir.join("tests/fixtures/letter_case/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--no-elastic"),
+        OsStr::new("--mentions"),
+        OsStr::new("--counters=urls,lines"),
+        links.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.starts_with("lines\turls\tmentions\tfilename\n"),
+        "got: {}",
+        stdout
+    );
+
+    let out = main_binary_with_args([
+        OsStr::new("--urls"),
+        OsStr::new("--fail-if=urls>0"),
+        links.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    let out = main_binary_with_args([
+        OsStr::new("--no-elastic"),
+        OsStr::new("--counters=uppercase,lines"),
+        OsStr::new("--lowercase"),
+        letter_case.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.starts_with("lines\tuppercase\tlowercase\tfilename\n"),
+        "got: {}",
+        stdout
+    );
+
+    let mut args: Vec<OsString> = (0..16)
+        .map(|i| OsString::from(format!("--count-regex=regex{}=x", i)))
+        .collect();
+    args.extend(["--urls", "--emails", "--hashtags", "--mentions"].map(OsString::from));
+    args.extend(["--uppercase", "--lowercase", "--titlecase", "--widths"].map(OsString::from));
+    args.extend(["--replacements", "--private-use", "--unassigned"].map(OsString::from));
+    args.extend(["--comment-prefix=#", "--block-comment=/* */"].map(OsString::from));
+    args.push(links.clone().into_os_string());
+
+    let out = main_binary_with_args(&args).output().unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(out.status.success(), "got: {}", stderr);
+}
+
+/// Tests that `-q` keeps the inputs that can't be counted from being reported,
+/// and that `-v` logs the inputs as they're counted.
+#[test]
+fn test_verbose_quiet() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let fixtures = test_dir.join("tests/fixtures");
+
+    let out = main_binary_with_args([OsStr::new("-q"), fixtures.as_os_str(), hello.as_os_str()])
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(Some(2), out.status.code());
+    assert_eq!("", String::from_utf8(out.stderr).unwrap());
+
+    let out = main_binary_with_args([OsStr::new("-v"), hello.as_os_str()])
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("Counting file"), "got: {}", stderr);
+    assert!(!stderr.contains("DEBUG"), "got: {}", stderr);
+}
+
+/// Tests that the rows of inputs counted in parallel in line mode are written
+/// in the order the inputs were given, like when they're counted one by one,
+/// with the rows of every input together.
+#[test]
+fn test_ordered_line_mode() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut args: Vec<OsString> = ["-m", "line", "--no-elastic", "--chunk-size", "1"]
+        .iter()
+        .map(OsString::from)
+        .collect();
+
+    let fixtures = [
+        "ladle_rat_rotten_hut/input_english",
+        "line_mode/input",
+        "i_can_eat_glass/input",
+        "all_newlines/input",
+    ];
+
+    for fixture in fixtures.iter().cycle().take(12) {
+        args.push(
+            test_dir
+                .join("tests/fixtures")
+                .join(fixture)
+                .into_os_string(),
+        );
+    }
+
+    let sequential = main_binary_with_args(&args)
+        .arg("--sequential")
+        .output()
+        .unwrap();
+    let parallel = main_binary_with_args(&args)
+        .args(["--threads", "4"])
+        .output()
+        .unwrap();
+
+    assert!(parallel.status.success());
+    let stdout = String::from_utf8(parallel.stdout).unwrap();
+    assert_eq!(String::from_utf8(sequential.stdout).unwrap(), stdout);
+
+    // every input's lines are together, and end with its total
+    let names: Vec<&str> = stdout
+        .lines()
+        .skip(1)
+        .map(|line| line.rsplit('\t').next().unwrap())
+        .collect();
+
+    for (name, next) in names.iter().zip(names.iter().skip(1)) {
+        let (file, _) = name.rsplit_once(':').unwrap();
+
+        if !next.starts_with(file) {
+            assert!(name.ends_with(":total"), "{} is followed by {}", name, next);
+        }
+    }
+}
+
+/// Tests that `--byte-offset` gives the offset of the start of every line,
+/// including the ones that are split into pieces.
+#[test]
+fn test_byte_offset() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let input = test_dir.join("tests/fixtures/byte_offset_line_mode/input");
+
+    for extra_args in &[&[][..], &["--split-len", "4", "--chunk-size", "1"][..]] {
+        let mut args: Vec<OsString> =
+            ["-m", "line", "--byte-offset", "--no-elastic", "--strip-bom"]
+                .iter()
+                .chain(*extra_args)
+                .map(OsString::from)
+                .collect();
+        args.push(input.clone().into_os_string());
+
+        let out = main_binary_with_args(&args).output().unwrap();
+        assert!(out.status.success());
+
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let offsets: Vec<&str> = stdout
+            .lines()
+            .map(|line| line.split('\t').nth(3).unwrap())
+            .collect();
+        assert_eq!(vec!["offset", "3", "8", "12", "13", "-"], offsets);
+    }
+
+    let out = main_binary_with_args([OsStr::new("--byte-offset"), input.as_os_str()])
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), out.status.code());
+}
+
+/// Tests that lines are classified the same way when they're split into
+/// pieces, and that block comments carry over to the next lines.
+#[test]
+fn test_block_comment() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let input = test_dir.join("tests/fixtures/block_comment_line_mode/input");
+
+    for extra_args in &[&[][..], &["--split-len", "4", "--chunk-size", "1"][..]] {
+        let mut args: Vec<OsString> = ["-m", "line", "--no-elastic", "--counters", "code-lines"]
+            .iter()
+            .chain(&["--comment-prefix", "//", "--block-comment", "/* */"])
+            .chain(*extra_args)
+            .map(OsString::from)
+            .collect();
+        args.push(input.clone().into_os_string());
+
+        let out = main_binary_with_args(&args).output().unwrap();
+        assert!(out.status.success());
+
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let classes: Vec<String> = stdout
+            .lines()
+            .skip(1)
+            .map(|line| line.split('\t').take(3).collect::<Vec<_>>().join(" "))
+            .collect();
+        let expected = vec![
+            "1 0 0", "0 1 0", "0 0 1", "0 1 0", "0 0 1", "0 1 0", "1 0 0", "1 0 0", "3 3 2",
+        ];
+        assert_eq!(expected, classes);
+    }
+}
+
+/// Tests that --check-utf8 gives where the invalid sequences are, and fails
+/// with exit code 3 if there are any.
+#[test]
+fn test_check_utf8() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let invalid = test_dir.join("tests/fixtures/check_utf8/input");
+    let valid = test_dir.join("tests/fixtures/block_comment_line_mode/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--check-utf8"),
+        OsStr::new("--all-invalid"),
+        invalid.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let errors: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.rsplit(": ").next().unwrap())
+        .collect();
+    assert_eq!(vec!["\\xff", "\\xe2\\x82"], errors);
+    assert!(stdout.contains(":3: invalid UTF-8 at byte 11: "));
+
+    let out = main_binary_with_args([OsStr::new("--check-utf8"), valid.as_os_str()])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .ends_with(": valid UTF-8\n"));
+}
+
+/// Tests that --mean gives the average counts of the inputs after the total.
+#[test]
+fn test_mean() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let line_mode = test_dir.join("tests/fixtures/line_mode/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--mean"),
+        OsStr::new("--no-elastic"),
+        hello.as_os_str(),
+        line_mode.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(Some("4.0\t4.5\t17.0\tmean"), stdout.lines().last());
+}
+
+/// Tests that --min-max gives the smallest and largest counts of the inputs,
+/// along with the inputs they're from.
+#[test]
+fn test_min_max() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let line_mode = test_dir.join("tests/fixtures/line_mode/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--min-max"),
+        OsStr::new("--no-elastic"),
+        OsStr::new("-l"),
+        OsStr::new("-b"),
+        hello.as_os_str(),
+        line_mode.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let rows: Vec<&str> = stdout.lines().rev().take(2).collect();
+    let hello = hello.to_str().unwrap();
+    let line_mode = line_mode.to_str().unwrap();
+
+    assert_eq!(
+        format!("8 ({})\t29 ({})\tmax", line_mode, line_mode),
+        rows[0]
+    );
+    assert_eq!(format!("0 ({})\t5 ({})\tmin", hello, hello), rows[1]);
+}
+
+/// Tests that --annotate writes every line after its counts, without its line
+/// break, in place of its name.
+#[test]
+fn test_annotate() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let line_mode = test_dir.join("tests/fixtures/line_mode/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--annotate"),
+        OsStr::new("--no-elastic"),
+        OsStr::new("-m"),
+        OsStr::new("line"),
+        OsStr::new("-b"),
+        OsStr::new("-c"),
+        line_mode.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let rows: Vec<&str> = stdout.lines().take(5).collect();
+
+    assert_eq!(
+        vec![
+            "bytes\tgraphemes\tline",
+            "1\t1\ta",
+            "2\t2\taa",
+            "3\t3\taaa",
+            "5\t4\taaaá"
+        ],
+        rows
+    );
+}
+
+/// Tests that --filter writes only the lines whose counts satisfy the
+/// condition, after their file names and line numbers.
+#[test]
+fn test_filter() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let line_mode = test_dir.join("tests/fixtures/line_mode/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--filter"),
+        OsStr::new("graphemes>=4"),
+        line_mode.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let line_mode = line_mode.to_str().unwrap();
+
+    assert_eq!(
+        format!("{}:4:aaaá\n{}:6:bbbb\n", line_mode, line_mode),
+        stdout
+    );
+}
+
+/// Tests that --bytes-only-decode off counts the lines and bytes of an input
+/// with invalid UTF-8 in full, and gives the other counts as "-".
+#[test]
+fn test_bytes_only_decode_off() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let invalid = test_dir.join("tests/fixtures/lines_bytes_invalid_utf8/input_invalid");
+
+    let out = main_binary_with_args([
+        OsStr::new("--bytes-only-decode"),
+        OsStr::new("off"),
+        OsStr::new("--no-elastic"),
+        OsStr::new("-lwb"),
+        invalid.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let expected = format!(
+        "lines\twords\tbytes\tfilename\n3\t-\t24\t{}\n",
+        invalid.display()
+    );
+    assert_eq!(expected, stdout);
+}
+
+/// Tests that --list-files writes the files that would be counted, without the
+/// binary ones with --binary skip, and without counting them.
+#[test]
+fn test_list_files() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let binary_skip = test_dir.join("tests/fixtures/binary_skip");
+
+    let out = main_binary_with_args([
+        OsStr::new("--list-files"),
+        OsStr::new("-r"),
+        OsStr::new("--binary"),
+        OsStr::new("skip"),
+        binary_skip.as_os_str(),
+    ])
+    .output()
+    .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let expected: Vec<_> = ["input_text", "opts", "stdout"]
+        .iter()
+        .map(|name| binary_skip.join(name).to_string_lossy().into_owned())
+        .collect();
+
+    assert_eq!(expected, stdout.lines().collect::<Vec<_>>());
+}
+
+/// Tests that --status-file writes the exit code of the run, why it ended
+/// with it, and what happened to the inputs.
+#[test]
+fn test_status_file() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let status_file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("status.json");
+    let _ = fs::remove_file(&status_file);
+
+    let out = main_binary_with_args([
+        OsStr::new("--status-file"),
+        status_file.as_os_str(),
+        hello.as_os_str(),
+        OsStr::new("does-not-exist"),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(2), out.status.code());
+
+    let status: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&status_file).unwrap()).unwrap();
+
+    assert_eq!(2, status["exit_code"]);
+    assert_eq!("inputs-failed", status["reason"]);
+    assert_eq!(1, status["inputs"]["counted"]);
+    assert_eq!(1, status["inputs"]["failed"]);
+    assert_eq!(1, status["errors"]);
+}
+
+/// Tests that --output-dir writes the rows of every input to a file of its
+/// own, and that the inputs that fail don't leave one behind.
+#[test]
+fn test_output_dir() {
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("output-dir");
+    let _ = fs::remove_dir_all(&output_dir);
+
+    let out = main_binary_with_args([
+        OsStr::new("--no-elastic"),
+        OsStr::new("--output-dir"),
+        output_dir.as_os_str(),
+        OsStr::new("tests/fixtures/hello/input"),
+        OsStr::new("does-not-exist"),
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(Some(2), out.status.code());
+
+    let counts = fs::read_to_string(output_dir.join("tests/fixtures/hello/input.counts")).unwrap();
+    assert_eq!(
+        "lines\twords\tbytes\tfilename\n0\t1\t5\ttests/fixtures/hello/input\n",
+        counts
+    );
+    assert!(!output_dir.join("does-not-exist.counts").exists());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains("0\t1\t5\ttests/fixtures/hello/input\n"),
+        "got: {}",
+        stdout
+    );
+}
+
+/// Tests that --report-unicode-version gives the version of Unicode that
+/// --unassigned depends on, without counting anything.
+#[test]
+fn test_report_unicode_version() {
+    let out = main_binary_with_args(["--no-elastic", "--report-unicode-version"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(Some("unicode\tused for"), lines.next());
+
+    let (version, used_for) = lines.next().unwrap().split_once('\t').unwrap();
+    assert!(used_for.contains("--unassigned"), "got: {}", stdout);
+    assert!(
+        version.split('.').all(|part| part.parse::<u8>().is_ok()),
+        "got: {}",
+        stdout
+    );
+}
+
+/// Tests that --interval writes snapshots of stdin's counts while it's still
+/// open, with only what was counted since the last one given with
+/// --interval-counts delta.
+#[test]
+fn test_interval() {
+    let mut child = main_binary_with_args([
+        "--interval",
+        "100ms",
+        "--interval-counts",
+        "delta",
+        "--no-elastic",
+        "-l",
+        "-w",
+    ])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"one two\n").unwrap();
+
+    // the first snapshot is written while stdin is still open
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut lines = Vec::new();
+
+    while !lines.iter().any(|line: &String| line.ends_with("-:0.1s\n")) {
+        let mut line = String::new();
+        assert_ne!(0, stdout.read_line(&mut line).unwrap(), "got: {:?}", lines);
+        lines.push(line);
+    }
+
+    assert_eq!("1\t2\t-:0.1s\n", lines.last().unwrap());
+
+    stdin.write_all(b"three\n").unwrap();
+    drop(stdin);
+
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(Some("2\t3\t-"), rest.lines().last(), "got: {:?}", rest);
+}
+
+/// Tests that a profile from the config file gives its options, which the
+/// ones on the command line override.
+#[test]
+fn test_profile() {
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("profile");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir).unwrap();
+
+    let config = tmp_dir.join("config.toml");
+    fs::write(
+        &config,
+        "counters = [\"bytes\"]\n\n[profile.prose]\ncounters = [\"words\"]\nargs = [\"--no-elastic\"]\n",
+    )
+    .unwrap();
+
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+
+    let out = main_binary_with_args([
+        OsStr::new("--profile"),
+        OsStr::new("prose"),
+        hello.as_os_str(),
+    ])
+    .env("UWC_CONFIG", &config)
+    .output()
+    .unwrap();
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(format!("words\tfilename\n1\t{}\n", hello.display()), stdout);
+
+    let out = main_binary_with_args([
+        OsStr::new("--profile=prose"),
+        OsStr::new("-l"),
+        hello.as_os_str(),
+    ])
+    .env("UWC_CONFIG", &config)
+    .output()
+    .unwrap();
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(format!("lines\tfilename\n0\t{}\n", hello.display()), stdout);
+
+    let out = main_binary_with_args(["--profile", "code"])
+        .env("UWC_CONFIG", &config)
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(1), out.status.code());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("unknown profile: code (expected one of: prose)"),
+        "got: {}",
+        stderr
+    );
+}
+
 #[test]
 fn test_file_not_found() {
     let non_existent_file = "nofile";
@@ -105,7 +798,7 @@ fn test_file_not_found() {
     let self_test_file = Path::join(test_dir, "tests/cli.rs");
     let self_test_file_str = self_test_file.to_str().unwrap();
 
-    let mut cmd = main_binary_with_args(&[non_existent_file, self_test_file_str]);
+    let mut cmd = main_binary_with_args([non_existent_file, self_test_file_str]);
     assert!(!cmd.status().unwrap().success(), "should fail");
 
     let output = cmd.output().unwrap();
@@ -129,6 +822,75 @@ fn test_file_not_found() {
     );
 }
 
+/// Tests that the input files can be read from a NUL-separated list, and from
+/// a newline-separated one.
+#[test]
+fn test_files_from() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let glass = test_dir.join("tests/fixtures/i_can_eat_glass/input");
+    let hello = hello.to_str().unwrap();
+    let glass = glass.to_str().unwrap();
+
+    let correct_fields: HashSet<_> =
+        vec![(vec![1usize], hello), (vec![9], glass), (vec![10], "total")]
+            .into_iter()
+            .collect();
+
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+
+    for (flag, delimiter) in &[("--files0-from", "\0"), ("--files-from", "\n")] {
+        let list = tmp_dir.join(&flag[2..]);
+        fs::write(
+            &list,
+            format!("{}{}{}{}", hello, delimiter, glass, delimiter),
+        )
+        .unwrap();
+
+        let out = main_binary_with_args([*flag, list.to_str().unwrap(), "-w"])
+            .output()
+            .unwrap();
+
+        assert!(out.status.success(), "{} should succeed", flag);
+
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        assert_eq!(correct_fields, parse_lines(&stdout, true));
+    }
+}
+
+/// Tests that the input files can be read from stdin, in which case stdin
+/// itself can't be counted.
+#[test]
+fn test_files0_from_stdin() {
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let hello = test_dir.join("tests/fixtures/hello/input");
+    let hello = hello.to_str().unwrap();
+
+    let mut child = main_binary_with_args(["--files0-from", "-", "-w"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(format!("{}\0-\0", hello).as_bytes())
+        .unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    assert!(!out.status.success(), "should fail");
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let correct_fields: HashSet<_> = vec![(vec![1usize], hello)].into_iter().collect();
+    assert_eq!(correct_fields, parse_lines(&stdout, true));
+
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("-: can't count stdin"), "got: '{}'", stderr);
+}
+
 // ----------------------------
 //      FIXTURE TESTS
 // ----------------------------
@@ -162,7 +924,7 @@ fn soak_string(path: &Path) -> Option<String> {
         return None;
     }
 
-    let mut file = File::open(path).expect(&format!("error on test entry: {:?}", path));
+    let mut file = File::open(path).unwrap_or_else(|_| panic!("error on test entry: {:?}", path));
     let mut string = String::new();
     file.read_to_string(&mut string).unwrap();
     Some(string)
@@ -222,10 +984,9 @@ fn test_fixtures() {
         // parse the fields from stdout and compare for exact equality
         let expected_stdout = soak_string(&test_path.join(STDOUT_FILE_NAME));
 
-        if expected_stdout.is_some() {
+        if let Some(expected_stdout) = expected_stdout {
             let stdout = String::from_utf8(out.stdout).unwrap();
             let fields = parse_lines(&stdout, true);
-            let expected_stdout = expected_stdout.unwrap();
             let correct_fields = parse_lines(&expected_stdout, true);
             assert_eq!(
                 correct_fields, fields,
@@ -238,13 +999,12 @@ fn test_fixtures() {
         // the actual stderr
         let expected_stderr = soak_string(&test_path.join(STDERR_FILE_NAME));
 
-        if expected_stderr.is_some() {
-            let expected_stderr = expected_stderr.unwrap();
+        if let Some(expected_stderr) = expected_stderr {
             let expected_stderr_trimmed = expected_stderr.trim();
             let stderr = String::from_utf8(out.stderr).unwrap();
 
             assert!(
-                stderr.contains(&expected_stderr_trimmed),
+                stderr.contains(expected_stderr_trimmed),
                 "Wrong stderr. Expected `{}`, got `{}`",
                 expected_stderr_trimmed,
                 stderr.trim()
@@ -259,3 +1019,102 @@ fn test_fixtures() {
         }
     }
 }
+
+/// Tests that following symbolic links while counting recursively doesn't
+/// loop forever, and that `--no-follow` skips them.
+#[cfg(unix)]
+#[test]
+fn test_symlink_loop() {
+    use std::os::unix::fs::symlink;
+
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("symlink_loop");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(tmp_dir.join("sub")).unwrap();
+    fs::write(tmp_dir.join("words.txt"), "one two\n").unwrap();
+    symlink("..", tmp_dir.join("sub/up")).unwrap();
+    symlink("words.txt", tmp_dir.join("link.txt")).unwrap();
+
+    let words = tmp_dir.join("words.txt");
+    let link = tmp_dir.join("link.txt");
+
+    let out = main_binary_with_args(["-w", "-r", "--follow-symlinks"])
+        .arg(&tmp_dir)
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(2), out.status.code());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("loop"), "unexpected stderr: {}", stderr);
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let correct_fields: HashSet<_> = vec![
+        (vec![2usize], link.to_str().unwrap()),
+        (vec![2], words.to_str().unwrap()),
+        (vec![4], "total"),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(correct_fields, parse_lines(&stdout, true));
+
+    let out = main_binary_with_args(["-w", "--no-follow"])
+        .args([&link, &words])
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let correct_fields: HashSet<_> = vec![(vec![2usize], words.to_str().unwrap())]
+        .into_iter()
+        .collect();
+    assert_eq!(correct_fields, parse_lines(&stdout, true));
+}
+
+/// Tests saving a baseline of the counts, and comparing against it later.
+#[test]
+fn test_baseline_compare() {
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("baseline");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir).unwrap();
+
+    let input = tmp_dir.join("input");
+    let baseline = tmp_dir.join("baseline.json");
+    fs::write(&input, "one two\n").unwrap();
+
+    let out = main_binary_with_args(["-w", "--save-baseline"])
+        .args([&baseline, &input])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    fs::write(&input, "one two three four\n").unwrap();
+
+    let out = main_binary_with_args(["-w", "--no-elastic", "--compare"])
+        .args([&baseline, &input])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let expected = format!("words\tfilename\n+2\t{}\n+2\ttotal\n", input.display());
+    assert_eq!(expected, stdout);
+
+    let out = main_binary_with_args(["-w", "--max-delta", "words=1", "--compare"])
+        .args([&baseline, &input])
+        .output()
+        .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    // the limited counter is compared even if it isn't selected
+    let out = main_binary_with_args(["-l", "--max-delta", "words=1", "--compare"])
+        .args([&baseline, &input])
+        .output()
+        .unwrap();
+    assert_eq!(Some(3), out.status.code());
+
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("words changed by +2, more than 1"),
+        "unexpected stderr: {}",
+        stderr
+    );
+}
@@ -0,0 +1 @@
+counted